@@ -0,0 +1,45 @@
+//! Interrupt affinity hints derived from the guest enables.
+//!
+//! A guest usually enables each device interrupt on the contexts of one
+//! vCPU. Following which vCPUs enable a source tells the VMM scheduler
+//! where the guest expects it, so it can run that vCPU on the hart the
+//! physical interrupt targets (or retarget the interrupt). Changes of the
+//! hint are reported through
+//! [`VPlicHooks::affinity_changed`](crate::VPlicHooks::affinity_changed).
+
+use crate::banks::BankIter;
+use crate::{word_source, VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Returns the vCPU the guest routes `irq_id` to, i.e. the only vCPU
+    /// with a context enabling it, or `None` if no vCPU or several enable
+    /// it.
+    pub fn affinity_hint(&self, irq_id: usize) -> Option<usize> {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return None;
+        }
+        let contexts = self.contexts.lock();
+        let mut vcpus = contexts
+            .iter()
+            .filter(|context| context.enables.get(irq_id))
+            .map(|context| context.vcpu_id);
+        let vcpu_id = vcpus.next()?;
+        vcpus.all(|other| other == vcpu_id).then_some(vcpu_id)
+    }
+
+    /// Re-evaluates the hints of the sources of enable word `word_index`
+    /// whose enable bit flipped in `flipped`, reporting the changes.
+    pub(crate) fn refresh_affinity(&self, word_index: usize, flipped: u32) {
+        for bit in BankIter::new(flipped) {
+            let irq_id = word_source(word_index, bit);
+            let hint = self.affinity_hint(irq_id);
+            let changed = {
+                let mut hints = self.affinity_hints.lock();
+                core::mem::replace(&mut hints[irq_id], hint) != hint
+            };
+            if changed {
+                self.call_hooks(|hooks| hooks.affinity_changed(irq_id, hint));
+            }
+        }
+    }
+}
@@ -0,0 +1,602 @@
+//! Virtual APLIC of the RISC-V Advanced Interrupt Architecture.
+//!
+//! [`VAplic`] emulates the register map of a single APLIC interrupt domain
+//! (domaincfg, sourcecfg, the pending and enable arrays, genmsi, target and
+//! the per-hart IDC structures) entirely in software, for guests built for
+//! AIA. In direct delivery mode, each hart's external interrupt follows its
+//! IDC and is driven through [`VAplicTarget::set_external_irq`]; in MSI
+//! delivery mode, pending and enabled sources are forwarded as MSIs through
+//! [`VAplicTarget::send_msi`].
+//!
+//! Child domains are not supported: delegation bits read as zero.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axaddrspace::{device::AccessWidth, GuestPhysAddr, GuestPhysAddrRange};
+use axdevice_base::{BaseDeviceOps, EmuDeviceType};
+use axerrno::{AxError, AxResult};
+use bitmaps::Bitmap;
+use log::trace;
+
+use crate::consts::*;
+use crate::lock::Mutex;
+use crate::{source_bit, word_source, PLIC_NUM_BANKS, PLIC_NUM_SOURCES};
+
+/// domaincfg: interrupt enable.
+const DOMAINCFG_IE: u32 = 1 << 8;
+/// domaincfg: MSI delivery mode.
+const DOMAINCFG_DM: u32 = 1 << 2;
+/// domaincfg: bits reading as 0x80 in the top byte.
+const DOMAINCFG_FIXED: u32 = 0x80 << 24;
+
+/// sourcecfg: delegated to a child domain.
+const SOURCECFG_D: u32 = 1 << 10;
+/// sourcecfg: source mode.
+const SOURCECFG_SM: u32 = 0x7;
+
+/// target: hart index.
+const TARGET_HART_SHIFT: u32 = 18;
+/// target: guest index, in MSI delivery mode.
+const TARGET_GUEST_SHIFT: u32 = 12;
+const TARGET_GUEST_MASK: u32 = 0x3f;
+/// target: external interrupt identity, in MSI delivery mode.
+const TARGET_EIID_MASK: u32 = 0x7ff;
+/// target: priority, in direct delivery mode.
+const TARGET_IPRIO_MASK: u32 = 0xff;
+
+/// Source mode of an APLIC source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceMode {
+    Inactive,
+    Detached,
+    Edge1,
+    Edge0,
+    Level1,
+    Level0,
+}
+
+impl SourceMode {
+    fn from_cfg(cfg: u32) -> Self {
+        match cfg & SOURCECFG_SM {
+            1 => Self::Detached,
+            4 => Self::Edge1,
+            5 => Self::Edge0,
+            6 => Self::Level1,
+            7 => Self::Level0,
+            _ => Self::Inactive,
+        }
+    }
+
+    fn is_level(self) -> bool {
+        matches!(self, Self::Level1 | Self::Level0)
+    }
+
+    /// Returns the rectified value of an input at `level`.
+    fn rectify(self, level: bool) -> bool {
+        match self {
+            Self::Edge0 | Self::Level0 => !level,
+            Self::Edge1 | Self::Level1 => level,
+            _ => false,
+        }
+    }
+}
+
+/// The hypervisor side of a [`VAplic`].
+pub trait VAplicTarget: Send + Sync {
+    /// Asserts or withdraws the external interrupt of guest hart
+    /// `hart_index`, in direct delivery mode.
+    fn set_external_irq(&self, hart_index: usize, asserted: bool);
+    /// Sends an MSI with identity `eiid` to guest interrupt file
+    /// `guest_index` of hart `hart_index`, in MSI delivery mode.
+    fn send_msi(&self, _hart_index: usize, _guest_index: usize, _eiid: usize) -> AxResult {
+        Err(AxError::Unsupported)
+    }
+}
+
+/// Interrupt delivery control of one hart.
+#[derive(Debug, Clone, Copy, Default)]
+struct Idc {
+    idelivery: u32,
+    iforce: u32,
+    ithreshold: u32,
+}
+
+/// Register state of a [`VAplic`].
+struct AplicState {
+    domaincfg: u32,
+    sourcecfg: Vec<u32>,
+    target: Vec<u32>,
+    pending: Bitmap<{ PLIC_NUM_SOURCES }>,
+    enabled: Bitmap<{ PLIC_NUM_SOURCES }>,
+    /// Raw input levels, as driven by the devices.
+    inputs: Bitmap<{ PLIC_NUM_SOURCES }>,
+    idcs: Vec<Idc>,
+    /// External interrupt of each hart, as last reported.
+    asserted: Vec<bool>,
+}
+
+/// Notifications computed under the state lock, sent once it is released.
+#[derive(Default)]
+struct Outputs {
+    irqs: Vec<(usize, bool)>,
+    msis: Vec<(usize, usize, usize)>,
+}
+
+impl AplicState {
+    fn mode(&self, irq_id: usize) -> SourceMode {
+        match self.sourcecfg[irq_id] {
+            cfg if cfg & SOURCECFG_D != 0 => SourceMode::Inactive,
+            cfg => SourceMode::from_cfg(cfg),
+        }
+    }
+
+    fn msi_mode(&self) -> bool {
+        self.domaincfg & DOMAINCFG_DM != 0
+    }
+
+    /// Sets the pending bit of `irq_id` as a write to setip would.
+    fn set_pending(&mut self, irq_id: usize) {
+        let mode = self.mode(irq_id);
+        match mode {
+            SourceMode::Inactive => {}
+            // In direct mode, the pending bit of a level source follows the
+            // rectified input.
+            _ if mode.is_level() && !self.msi_mode() => {}
+            _ => {
+                self.pending.set(irq_id, true);
+            }
+        }
+    }
+
+    /// Clears the pending bit of `irq_id` as a write to in_clrip would.
+    fn clear_pending(&mut self, irq_id: usize) {
+        if !(self.mode(irq_id).is_level() && !self.msi_mode()) {
+            self.pending.set(irq_id, false);
+        }
+    }
+
+    /// Drives the input of `irq_id` to `level`.
+    fn drive(&mut self, irq_id: usize, level: bool) {
+        let mode = self.mode(irq_id);
+        let was = mode.rectify(self.inputs.get(irq_id));
+        self.inputs.set(irq_id, level);
+        let now = mode.rectify(level);
+        match mode {
+            SourceMode::Edge1 | SourceMode::Edge0 if now && !was => {
+                self.pending.set(irq_id, true);
+            }
+            SourceMode::Level1 | SourceMode::Level0 if !self.msi_mode() => {
+                self.pending.set(irq_id, now);
+            }
+            SourceMode::Level1 | SourceMode::Level0 if now => {
+                self.pending.set(irq_id, true);
+            }
+            _ => {}
+        }
+    }
+
+    /// Re-evaluates the sources whose pending bit follows the input after a
+    /// configuration change.
+    fn resample(&mut self, irq_id: usize) {
+        let mode = self.mode(irq_id);
+        if mode == SourceMode::Inactive {
+            self.pending.set(irq_id, false);
+            self.enabled.set(irq_id, false);
+        } else if mode.is_level() && !self.msi_mode() {
+            let level = mode.rectify(self.inputs.get(irq_id));
+            self.pending.set(irq_id, level);
+        }
+    }
+
+    /// Returns the highest-priority interrupt deliverable to hart
+    /// `hart_index`, as topi encodes it, 0 if none.
+    fn topi(&self, hart_index: usize) -> u32 {
+        if self.msi_mode() || self.domaincfg & DOMAINCFG_IE == 0 {
+            return 0;
+        }
+        let idc = &self.idcs[hart_index];
+        let mut best: Option<(u32, usize)> = None;
+        for irq_id in self.pending.into_iter() {
+            if !self.enabled.get(irq_id) || self.mode(irq_id) == SourceMode::Inactive {
+                continue;
+            }
+            let target = self.target[irq_id];
+            if (target >> TARGET_HART_SHIFT) as usize != hart_index {
+                continue;
+            }
+            let prio = (target & TARGET_IPRIO_MASK).max(1);
+            if idc.ithreshold != 0 && prio >= idc.ithreshold {
+                continue;
+            }
+            // Lower priority numbers win, then lower source numbers.
+            if best.is_none_or(|(p, _)| prio < p) {
+                best = Some((prio, irq_id));
+            }
+        }
+        best.map_or(0, |(prio, irq_id)| (irq_id as u32) << 16 | prio)
+    }
+
+    /// Returns whether the external interrupt of `hart_index` is asserted.
+    fn hart_asserted(&self, hart_index: usize) -> bool {
+        let idc = &self.idcs[hart_index];
+        idc.idelivery & 1 != 0
+            && self.domaincfg & DOMAINCFG_IE != 0
+            && !self.msi_mode()
+            && (self.topi(hart_index) != 0 || idc.iforce & 1 != 0)
+    }
+
+    /// Computes the notifications due after a state change: forwards the
+    /// deliverable sources as MSIs in MSI mode, and reports the harts whose
+    /// external interrupt changed.
+    fn outputs(&mut self) -> Outputs {
+        let mut outputs = Outputs::default();
+        if self.msi_mode() && self.domaincfg & DOMAINCFG_IE != 0 {
+            let ready: Vec<usize> = self
+                .pending
+                .into_iter()
+                .filter(|&irq_id| self.enabled.get(irq_id) && self.mode(irq_id) != SourceMode::Inactive)
+                .collect();
+            for irq_id in ready {
+                self.pending.set(irq_id, false);
+                let target = self.target[irq_id];
+                outputs.msis.push((
+                    (target >> TARGET_HART_SHIFT) as usize,
+                    ((target >> TARGET_GUEST_SHIFT) & TARGET_GUEST_MASK) as usize,
+                    (target & TARGET_EIID_MASK) as usize,
+                ));
+            }
+        }
+        for hart_index in 0..self.idcs.len() {
+            let asserted = self.hart_asserted(hart_index);
+            if self.asserted[hart_index] != asserted {
+                self.asserted[hart_index] = asserted;
+                outputs.irqs.push((hart_index, asserted));
+            }
+        }
+        outputs
+    }
+}
+
+/// Returns the source of per-source register `reg` of the array at `base`.
+fn source_reg(reg: usize, base: usize) -> Option<usize> {
+    let irq_id = reg.checked_sub(base)? / 4 + 1;
+    (irq_id < PLIC_NUM_SOURCES).then_some(irq_id)
+}
+
+/// A virtual APLIC interrupt domain.
+pub struct VAplic {
+    /// The address of the domain in the guest physical address space.
+    pub addr: GuestPhysAddr,
+    /// The size of the domain in bytes.
+    pub size: usize,
+    /// Number of sources, source IDs ranging from 1 to `sources_num - 1`.
+    pub sources_num: usize,
+    /// Number of harts with an IDC.
+    pub harts_num: usize,
+    state: Mutex<AplicState>,
+    target: &'static dyn VAplicTarget,
+}
+
+impl VAplic {
+    /// Creates a domain at `addr` with sources `1..sources_num` and an IDC
+    /// for each of `harts_num` harts, notifying the guest through `target`.
+    pub fn new(
+        addr: GuestPhysAddr,
+        sources_num: usize,
+        harts_num: usize,
+        target: &'static dyn VAplicTarget,
+    ) -> AxResult<Self> {
+        if sources_num < 2 || sources_num > PLIC_NUM_SOURCES || harts_num == 0 {
+            return Err(AxError::InvalidInput);
+        }
+        let size = (APLIC_IDC_OFFSET + harts_num * APLIC_IDC_STRIDE).next_multiple_of(0x4000);
+        Ok(Self {
+            addr,
+            size,
+            sources_num,
+            harts_num,
+            state: Mutex::new(AplicState {
+                domaincfg: 0,
+                sourcecfg: vec![0; PLIC_NUM_SOURCES],
+                target: vec![0; PLIC_NUM_SOURCES],
+                pending: Bitmap::new(),
+                enabled: Bitmap::new(),
+                inputs: Bitmap::new(),
+                idcs: vec![Idc::default(); harts_num],
+                asserted: vec![false; harts_num],
+            }),
+            target,
+        })
+    }
+
+    /// Drives the input wire of source `irq_id` to `level`.
+    pub fn set_source_level(&self, irq_id: usize, level: bool) -> AxResult {
+        if irq_id == 0 || irq_id >= self.sources_num {
+            return Err(AxError::InvalidInput);
+        }
+        self.update(|state| state.drive(irq_id, level));
+        Ok(())
+    }
+
+    /// Signals an edge on the input of source `irq_id`, i.e. a pulse of its
+    /// rectified input.
+    pub fn pulse_source(&self, irq_id: usize) -> AxResult {
+        if irq_id == 0 || irq_id >= self.sources_num {
+            return Err(AxError::InvalidInput);
+        }
+        self.update(|state| {
+            let mode = state.mode(irq_id);
+            let active = !mode.rectify(false);
+            state.drive(irq_id, !active);
+            state.drive(irq_id, active);
+            state.drive(irq_id, !active);
+        });
+        Ok(())
+    }
+
+    /// Returns `true` if source `irq_id` is pending.
+    pub fn is_pending(&self, irq_id: usize) -> bool {
+        irq_id < self.sources_num && self.state.lock().pending.get(irq_id)
+    }
+
+    /// Applies `f` to the state, then sends the resulting notifications.
+    fn update<R>(&self, f: impl FnOnce(&mut AplicState) -> R) -> R {
+        let (ret, outputs) = {
+            let mut state = self.state.lock();
+            let ret = f(&mut state);
+            (ret, state.outputs())
+        };
+        for (hart_index, guest_index, eiid) in outputs.msis {
+            if let Err(err) = self.target.send_msi(hart_index, guest_index, eiid) {
+                trace!("vAplic: MSI to hart {hart_index} failed: {err:?}");
+            }
+        }
+        for (hart_index, asserted) in outputs.irqs {
+            self.target.set_external_irq(hart_index, asserted);
+        }
+        ret
+    }
+
+    /// Returns `true` if `irq_id` is a valid source number.
+    fn valid_source(&self, irq_id: usize) -> bool {
+        irq_id != 0 && irq_id < self.sources_num
+    }
+
+    /// Reads word `index` of a source bit array.
+    fn read_bits(&self, bits: &Bitmap<{ PLIC_NUM_SOURCES }>, index: usize) -> u32 {
+        (0..32)
+            .map(|bit| word_source(index, bit))
+            .filter(|&irq_id| self.valid_source(irq_id) && bits.get(irq_id))
+            .fold(0, |word, irq_id| word | source_bit(irq_id))
+    }
+
+    /// Returns the sources of word `index` set in `val`.
+    fn sources_of(&self, index: usize, val: u32) -> impl Iterator<Item = usize> + '_ {
+        (0..32)
+            .filter(move |&bit| val & 1 << bit != 0)
+            .map(move |bit| word_source(index, bit))
+            .filter(|&irq_id| self.valid_source(irq_id))
+    }
+
+    /// Returns the hart and the offset within its IDC of `reg`, if it
+    /// belongs to an IDC.
+    fn idc_reg(&self, reg: usize) -> Option<(usize, usize)> {
+        let offset = reg.checked_sub(APLIC_IDC_OFFSET)?;
+        let hart_index = offset / APLIC_IDC_STRIDE;
+        (hart_index < self.harts_num).then_some((hart_index, offset % APLIC_IDC_STRIDE))
+    }
+
+    fn read_reg(&self, reg: usize) -> u32 {
+        let state = self.state.lock();
+        if let Some((hart_index, offset)) = self.idc_reg(reg) {
+            let idc = &state.idcs[hart_index];
+            return match offset {
+                APLIC_IDC_IDELIVERY_OFFSET => idc.idelivery,
+                APLIC_IDC_IFORCE_OFFSET => idc.iforce,
+                APLIC_IDC_ITHRESHOLD_OFFSET => idc.ithreshold,
+                APLIC_IDC_TOPI_OFFSET => state.topi(hart_index),
+                _ => 0,
+            };
+        }
+        let words = PLIC_NUM_BANKS * 4;
+        match reg {
+            APLIC_DOMAINCFG_OFFSET => DOMAINCFG_FIXED | state.domaincfg,
+            _ if reg < APLIC_SETIP_OFFSET => match source_reg(reg, APLIC_SOURCECFG_OFFSET) {
+                Some(irq_id) if self.valid_source(irq_id) => state.sourcecfg[irq_id],
+                _ => 0,
+            },
+            _ if (APLIC_SETIP_OFFSET..APLIC_SETIP_OFFSET + words).contains(&reg) => {
+                self.read_bits(&state.pending, (reg - APLIC_SETIP_OFFSET) / 4)
+            }
+            _ if (APLIC_IN_CLRIP_OFFSET..APLIC_IN_CLRIP_OFFSET + words).contains(&reg) => {
+                // Reads return the rectified inputs.
+                let index = (reg - APLIC_IN_CLRIP_OFFSET) / 4;
+                (0..32)
+                    .map(|bit| word_source(index, bit))
+                    .filter(|&irq_id| {
+                        self.valid_source(irq_id) && state.mode(irq_id).rectify(state.inputs.get(irq_id))
+                    })
+                    .fold(0, |word, irq_id| word | source_bit(irq_id))
+            }
+            _ if (APLIC_SETIE_OFFSET..APLIC_SETIE_OFFSET + words).contains(&reg) => {
+                self.read_bits(&state.enabled, (reg - APLIC_SETIE_OFFSET) / 4)
+            }
+            _ if (APLIC_TARGET_OFFSET..APLIC_IDC_OFFSET).contains(&reg) => {
+                match source_reg(reg, APLIC_TARGET_OFFSET) {
+                    Some(irq_id) if self.valid_source(irq_id) && state.mode(irq_id) != SourceMode::Inactive => {
+                        state.target[irq_id]
+                    }
+                    _ => 0,
+                }
+            }
+            // setipnum, clripnum, setienum, clrienum, setipnum_le/be, genmsi
+            // and reserved registers read as zero.
+            _ => 0,
+        }
+    }
+
+    /// Claims the top interrupt of `hart_index`.
+    fn claim(&self, hart_index: usize) -> u32 {
+        self.update(|state| {
+            let topi = state.topi(hart_index);
+            match topi >> 16 {
+                0 => {
+                    state.idcs[hart_index].iforce = 0;
+                }
+                irq_id => {
+                    let irq_id = irq_id as usize;
+                    if !state.mode(irq_id).is_level() {
+                        state.pending.set(irq_id, false);
+                    }
+                }
+            }
+            topi
+        })
+    }
+
+    fn write_reg(&self, reg: usize, val: u32) {
+        if let Some((hart_index, offset)) = self.idc_reg(reg) {
+            self.update(|state| {
+                let idc = &mut state.idcs[hart_index];
+                match offset {
+                    APLIC_IDC_IDELIVERY_OFFSET => idc.idelivery = val & 1,
+                    APLIC_IDC_IFORCE_OFFSET => idc.iforce = val & 1,
+                    APLIC_IDC_ITHRESHOLD_OFFSET => idc.ithreshold = val & TARGET_IPRIO_MASK,
+                    _ => {}
+                }
+            });
+            return;
+        }
+        let words = PLIC_NUM_BANKS * 4;
+        let in_array = |start: usize| (start..start + words).contains(&reg);
+        match reg {
+            APLIC_DOMAINCFG_OFFSET => self.update(|state| {
+                // Only little-endian is supported: BE reads as zero.
+                state.domaincfg = val & (DOMAINCFG_IE | DOMAINCFG_DM);
+                for irq_id in 1..self.sources_num {
+                    state.resample(irq_id);
+                }
+            }),
+            _ if reg < APLIC_SETIP_OFFSET => {
+                let irq_id = source_reg(reg, APLIC_SOURCECFG_OFFSET).unwrap_or(0);
+                if self.valid_source(irq_id) {
+                    self.update(|state| {
+                        // No child domain: delegation cannot be set.
+                        state.sourcecfg[irq_id] = match SourceMode::from_cfg(val) {
+                            SourceMode::Inactive => 0,
+                            _ => val & SOURCECFG_SM,
+                        };
+                        state.resample(irq_id);
+                    });
+                }
+            }
+            _ if in_array(APLIC_SETIP_OFFSET) => self.update(|state| {
+                for irq_id in self.sources_of((reg - APLIC_SETIP_OFFSET) / 4, val) {
+                    state.set_pending(irq_id);
+                }
+            }),
+            APLIC_SETIPNUM_OFFSET | APLIC_SETIPNUM_LE_OFFSET => self.set_pending_num(val as usize),
+            APLIC_SETIPNUM_BE_OFFSET => self.set_pending_num(val.swap_bytes() as usize),
+            _ if in_array(APLIC_IN_CLRIP_OFFSET) => self.update(|state| {
+                for irq_id in self.sources_of((reg - APLIC_IN_CLRIP_OFFSET) / 4, val) {
+                    state.clear_pending(irq_id);
+                }
+            }),
+            APLIC_CLRIPNUM_OFFSET if self.valid_source(val as usize) => {
+                self.update(|state| state.clear_pending(val as usize))
+            }
+            _ if in_array(APLIC_SETIE_OFFSET) => self.update(|state| {
+                for irq_id in self.sources_of((reg - APLIC_SETIE_OFFSET) / 4, val) {
+                    if state.mode(irq_id) != SourceMode::Inactive {
+                        state.enabled.set(irq_id, true);
+                    }
+                }
+            }),
+            APLIC_SETIENUM_OFFSET if self.valid_source(val as usize) => self.update(|state| {
+                if state.mode(val as usize) != SourceMode::Inactive {
+                    state.enabled.set(val as usize, true);
+                }
+            }),
+            _ if in_array(APLIC_CLRIE_OFFSET) => self.update(|state| {
+                for irq_id in self.sources_of((reg - APLIC_CLRIE_OFFSET) / 4, val) {
+                    state.enabled.set(irq_id, false);
+                }
+            }),
+            APLIC_CLRIENUM_OFFSET if self.valid_source(val as usize) => {
+                self.update(|state| {
+                    state.enabled.set(val as usize, false);
+                })
+            }
+            APLIC_GENMSI_OFFSET => {
+                let msi_mode = self.state.lock().msi_mode();
+                if msi_mode {
+                    let hart_index = (val >> TARGET_HART_SHIFT) as usize;
+                    // The MSI is sent right away, so busy always reads as 0.
+                    let eiid = (val & TARGET_EIID_MASK) as usize;
+                    if let Err(err) = self.target.send_msi(hart_index, 0, eiid) {
+                        trace!("vAplic: genmsi to hart {hart_index} failed: {err:?}");
+                    }
+                }
+            }
+            _ if (APLIC_TARGET_OFFSET..APLIC_IDC_OFFSET).contains(&reg) => {
+                let irq_id = source_reg(reg, APLIC_TARGET_OFFSET).unwrap_or(0);
+                if self.valid_source(irq_id) {
+                    self.update(|state| {
+                        if state.mode(irq_id) == SourceMode::Inactive {
+                            return;
+                        }
+                        let hart = (val >> TARGET_HART_SHIFT) << TARGET_HART_SHIFT;
+                        state.target[irq_id] = if state.msi_mode() {
+                            hart | val & (TARGET_GUEST_MASK << TARGET_GUEST_SHIFT | TARGET_EIID_MASK)
+                        } else {
+                            // A priority of 0 reads back as 1.
+                            hart | (val & TARGET_IPRIO_MASK).max(1)
+                        };
+                    });
+                }
+            }
+            // Read-only and reserved registers ignore writes.
+            _ => {}
+        }
+    }
+
+    fn set_pending_num(&self, irq_id: usize) {
+        if self.valid_source(irq_id) {
+            self.update(|state| state.set_pending(irq_id));
+        }
+    }
+}
+
+impl BaseDeviceOps<GuestPhysAddrRange> for VAplic {
+    fn emu_type(&self) -> EmuDeviceType {
+        EmuDeviceType::InterruptController
+    }
+
+    fn address_range(&self) -> GuestPhysAddrRange {
+        GuestPhysAddrRange::from_start_size(self.addr, self.size)
+    }
+
+    fn handle_read(&self, addr: GuestPhysAddr, width: AccessWidth) -> AxResult<usize> {
+        if width != AccessWidth::Dword {
+            return Err(AxError::InvalidInput);
+        }
+        let reg = addr - self.addr;
+        let val = match self.idc_reg(reg) {
+            Some((hart_index, APLIC_IDC_CLAIMI_OFFSET)) => self.claim(hart_index),
+            _ => self.read_reg(reg),
+        };
+        trace!("vAplic read reg {reg:#x} val {val:#x}");
+        Ok(val as usize)
+    }
+
+    fn handle_write(&self, addr: GuestPhysAddr, width: AccessWidth, val: usize) -> AxResult {
+        if width != AccessWidth::Dword {
+            return Err(AxError::InvalidInput);
+        }
+        let reg = addr - self.addr;
+        trace!("vAplic write reg {reg:#x} val {val:#x}");
+        self.write_reg(reg, val as u32);
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Host interrupt latency attribution.
+//!
+//! Each vPLIC serves one VM, so timing the host interrupts it forwards
+//! attributes interrupt handling latency to that VM: from the forwarding to
+//! the guest claim, from the forwarding to the guest completion, and how
+//! long the guest held a source claimed. The source held the longest is
+//! remembered, to find the guest keeping a level interrupt asserted.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::utils::now_ns;
+use crate::{InjectionSource, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Aggregate of a series of durations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencySummary {
+    /// Number of samples.
+    pub count: u64,
+    /// Sum of the samples, in nanoseconds.
+    pub total_ns: u64,
+    /// Largest sample, in nanoseconds.
+    pub max_ns: u64,
+}
+
+impl LatencySummary {
+    fn record(&mut self, ns: u64) {
+        self.count += 1;
+        self.total_ns = self.total_ns.saturating_add(ns);
+        self.max_ns = self.max_ns.max(ns);
+    }
+
+    /// Returns the mean sample in nanoseconds, or 0 without samples.
+    pub fn mean_ns(&self) -> u64 {
+        self.total_ns.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Latency of the host interrupts forwarded to a VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HostIrqLatency {
+    /// From the forwarding of a host interrupt to its guest claim.
+    pub to_claim: LatencySummary,
+    /// From the forwarding of a host interrupt to its guest completion.
+    pub to_complete: LatencySummary,
+    /// From the guest claim to the guest completion.
+    pub hold: LatencySummary,
+    /// Source of the longest hold, 0 if none.
+    pub longest_hold_irq: usize,
+}
+
+/// Timestamps and aggregates behind [`HostIrqLatency`].
+pub(crate) struct HostLatency {
+    forwarded_at: Vec<Option<u64>>,
+    claimed_at: Vec<Option<u64>>,
+    summary: HostIrqLatency,
+}
+
+impl HostLatency {
+    pub fn new() -> Self {
+        Self {
+            forwarded_at: vec![None; PLIC_NUM_SOURCES],
+            claimed_at: vec![None; PLIC_NUM_SOURCES],
+            summary: HostIrqLatency::default(),
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Returns the latency of the host interrupts forwarded to this VM.
+    pub fn host_irq_latency(&self) -> HostIrqLatency {
+        self.host_latency.lock().summary
+    }
+
+    /// Clears the latency aggregates, keeping the interrupts in flight.
+    pub fn reset_host_irq_latency(&self) {
+        self.host_latency.lock().summary = HostIrqLatency::default();
+    }
+
+    /// Timestamps the injection of `irq_id` by `source`, unless it was
+    /// collapsed into an already pending interrupt.
+    pub(crate) fn attribute_injection(&self, irq_id: usize, source: InjectionSource, was_pending: bool) {
+        if source == InjectionSource::HostHardware && !was_pending {
+            self.host_latency.lock().forwarded_at[irq_id] = Some(now_ns());
+        }
+    }
+
+    /// Accounts the guest claim of `irq_id`.
+    pub(crate) fn attribute_claim(&self, irq_id: usize) {
+        let now = now_ns();
+        let mut latency = self.host_latency.lock();
+        if let Some(forwarded_at) = latency.forwarded_at[irq_id] {
+            latency.summary.to_claim.record(now.saturating_sub(forwarded_at));
+            latency.claimed_at[irq_id] = Some(now);
+        }
+    }
+
+    /// Accounts the guest completion of `irq_id`.
+    pub(crate) fn attribute_complete(&self, irq_id: usize) {
+        if irq_id >= PLIC_NUM_SOURCES {
+            return;
+        }
+        let now = now_ns();
+        let mut latency = self.host_latency.lock();
+        if let Some(forwarded_at) = latency.forwarded_at[irq_id].take() {
+            latency.summary.to_complete.record(now.saturating_sub(forwarded_at));
+        }
+        if let Some(claimed_at) = latency.claimed_at[irq_id].take() {
+            let hold = now.saturating_sub(claimed_at);
+            if hold > latency.summary.hold.max_ns {
+                latency.summary.longest_hold_irq = irq_id;
+            }
+            latency.summary.hold.record(hold);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Cross-check of the shadows against the host PLIC.
+//!
+//! Firmware or other software may touch the PLIC behind the vPLIC's back.
+//! An audit reads back the host registers of the assigned sources and of the
+//! host contexts backing the guest, and reports every value that drifted
+//! from what the shadows say should be programmed, along with guest bugs
+//! detected on the way.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+
+use crate::host::HostBackend;
+use crate::{source_bit, source_word, VPlicGlobal, PLIC_NUM_BANKS, PLIC_PRIORITY_OFFSET};
+
+/// A host register that drifted from its expected value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditFinding {
+    /// The host priority of an assigned source differs from the shadow.
+    PriorityDrift { irq_id: usize, name: Option<&'static str>, expected: u32, found: u32 },
+    /// The assigned bits of a host enable word differ from the shadow.
+    EnableDrift { context_id: usize, word_index: usize, expected: u32, found: u32 },
+    /// The host threshold of a context differs from the shadow.
+    ThresholdDrift { context_id: usize, expected: u32, found: u32 },
+    /// The guest tried to clear pending interrupts by writing ones to the
+    /// pending registers.
+    GuestPendingW1c { attempts: u64 },
+    /// A host register access failed.
+    Access(AxError),
+}
+
+/// Outcome of [`VPlicGlobal::audit`].
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    /// Drifted registers; empty if the host matches the shadows.
+    pub findings: Vec<AuditFinding>,
+}
+
+impl AuditReport {
+    /// Returns `true` if no drift was found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl VPlicGlobal {
+    /// Compares the host PLIC with the shadows.
+    pub fn audit(&self) -> AuditReport {
+        let mut report = AuditReport::default();
+        if let Err(err) = self.audit_priorities(&mut report) {
+            report.findings.push(AuditFinding::Access(err));
+        }
+        if let Err(err) = self.audit_contexts(&mut report) {
+            report.findings.push(AuditFinding::Access(err));
+        }
+        let attempts = self.w1c_attempts();
+        if attempts != 0 {
+            report.findings.push(AuditFinding::GuestPendingW1c { attempts });
+        }
+        report
+    }
+
+    /// Sets the period of [`VPlicGlobal::audit_if_due`] in nanoseconds, or
+    /// disables periodic audits with `None`.
+    pub fn set_audit_interval(&self, interval_ns: Option<u64>) {
+        self.audit_interval_ns.store(interval_ns.unwrap_or(0), Ordering::Relaxed);
+        self.next_audit_ns.store(0, Ordering::Relaxed);
+    }
+
+    /// Runs an audit if periodic audits are enabled and one is due at
+    /// `now_ns`.
+    pub fn audit_if_due(&self, now_ns: u64) -> Option<AuditReport> {
+        let interval_ns = self.audit_interval_ns.load(Ordering::Relaxed);
+        if interval_ns == 0 || now_ns < self.next_audit_ns.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.next_audit_ns
+            .store(now_ns.saturating_add(interval_ns), Ordering::Relaxed);
+        Some(self.audit())
+    }
+
+    fn audit_priorities(&self, report: &mut AuditReport) -> AxResult {
+        let assigned = *self.assigned_irqs.lock();
+        for host_irq in assigned.into_iter().filter(|&host_irq| host_irq != 0) {
+            let irq_id = match self.guest_irq(host_irq) {
+                Some(irq_id) if self.guest_owns(irq_id) && !self.power_masked(irq_id) => irq_id,
+                _ => continue,
+            };
+            let expected = self.guard_priority(irq_id, self.priority(irq_id));
+            let found = self.host_read(PLIC_PRIORITY_OFFSET + host_irq * 4)?;
+            if found != expected {
+                report.findings.push(AuditFinding::PriorityDrift {
+                    irq_id,
+                    name: self.source_name(irq_id),
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn audit_contexts(&self, report: &mut AuditReport) -> AxResult {
+        let assigned = *self.assigned_irqs.lock();
+        let mut assigned_words = [0u32; PLIC_NUM_BANKS];
+        for host_irq in assigned.into_iter() {
+            assigned_words[source_word(host_irq)] |= source_bit(host_irq);
+        }
+        for context_id in 0..self.contexts_num {
+            let shadow = self.enables(context_id);
+            for (word_index, &mask) in assigned_words.iter().enumerate() {
+                if mask == 0 {
+                    continue;
+                }
+                let expected = self.host_enable_word(&shadow, word_index) & mask;
+                let found = self.host_read(self.host_enable_offset(context_id, word_index))? & mask;
+                if found != expected {
+                    report.findings.push(AuditFinding::EnableDrift {
+                        context_id,
+                        word_index,
+                        expected,
+                        found,
+                    });
+                }
+            }
+            let expected = self.guard_threshold(context_id, self.threshold(context_id)?);
+            let found = self.host_read(self.host_threshold_offset(context_id))?;
+            if found != expected {
+                report.findings.push(AuditFinding::ThresholdDrift {
+                    context_id,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Injection back-pressure towards device models.
+//!
+//! Injections into a source that is still pending, or held by its gateway
+//! while claimed, pile up as a backlog the guest has not consumed. Once the
+//! backlog of a source reaches the configured threshold, the source is
+//! back-pressured: the injecting device model learns it from
+//! [`VPlicGlobal::inject_checked`] or from the
+//! [`VPlicHooks::backpressure`](crate::VPlicHooks::backpressure) callback,
+//! and can throttle (e.g. delay virtio used-ring notifications) until the
+//! guest claims the source again.
+
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, TracePoint, VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Sets the backlog at which a source becomes back-pressured, or
+    /// disables back-pressure with `None`.
+    pub fn set_backpressure_threshold(&self, threshold: Option<u32>) {
+        self.backpressure_threshold
+            .store(threshold.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Returns the back-pressure threshold, if enabled.
+    pub fn backpressure_threshold(&self) -> Option<u32> {
+        match self.backpressure_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            threshold => Some(threshold),
+        }
+    }
+
+    /// Returns `true` if `irq_id` is back-pressured.
+    pub fn backpressured(&self, irq_id: usize) -> bool {
+        match self.backpressure_threshold() {
+            Some(threshold) => self
+                .backlog
+                .lock()
+                .get(irq_id)
+                .is_some_and(|&backlog| backlog >= threshold),
+            None => false,
+        }
+    }
+
+    /// Injects `irq_id` on behalf of `source`, returning `true` if the
+    /// source is back-pressured and the device model should throttle.
+    pub fn inject_checked(&self, irq_id: usize, source: InjectionSource) -> AxResult<bool> {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.inject(irq_id, source);
+        Ok(self.backpressured(irq_id))
+    }
+
+    /// Accounts an injection of `irq_id` the guest has not consumed yet.
+    pub(crate) fn note_backlog(&self, irq_id: usize) {
+        let backlog = {
+            let mut backlog = self.backlog.lock();
+            backlog[irq_id] = backlog[irq_id].saturating_add(1);
+            backlog[irq_id]
+        };
+        if Some(backlog) == self.backpressure_threshold() {
+            self.trace_point(TracePoint::StormDetected, irq_id, 1);
+            self.call_hooks(|hooks| hooks.backpressure(irq_id, true));
+        }
+    }
+
+    /// Clears the backlog of `irq_id` once the guest claimed it.
+    pub(crate) fn clear_backlog(&self, irq_id: usize) {
+        let backlog = core::mem::take(&mut self.backlog.lock()[irq_id]);
+        if self.backpressure_threshold().is_some_and(|threshold| backlog >= threshold) {
+            self.trace_point(TracePoint::StormDetected, irq_id, 0);
+            self.call_hooks(|hooks| hooks.backpressure(irq_id, false));
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Source bitmaps grouped into 32-source banks.
+//!
+//! Each bitmap keeps a one-word summary with a bit per non-empty bank, so
+//! configurations with many mostly-idle sources only scan the banks that
+//! actually hold something.
+//!
+//! [`AtomicBitmap`] holds the same bits in atomic words, for the hot
+//! pending and active state updated without a lock. Each bank is an atomic
+//! word of its own cache line, so a burst of injections into one bank does
+//! not slow down claims and injections of sources in the others.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::PLIC_NUM_SOURCES;
+
+/// Number of sources per bank (one 32-bit register word).
+pub const PLIC_BANK_SIZE: usize = 32;
+
+/// Number of banks covering all sources.
+pub const PLIC_NUM_BANKS: usize = PLIC_NUM_SOURCES / PLIC_BANK_SIZE;
+
+/// Returns the register word holding the bit of `irq_id`.
+pub const fn source_word(irq_id: usize) -> usize {
+    irq_id / PLIC_BANK_SIZE
+}
+
+/// Returns the mask of the bit of `irq_id` within its register word.
+pub const fn source_bit(irq_id: usize) -> u32 {
+    1 << (irq_id % PLIC_BANK_SIZE)
+}
+
+/// Returns the source mapped to bit `bit` of register word `word_index`.
+pub const fn word_source(word_index: usize, bit: usize) -> usize {
+    word_index * PLIC_BANK_SIZE + bit
+}
+
+/// A source bitmap with per-bank summary state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankedBitmap {
+    words: [u32; PLIC_NUM_BANKS],
+    summary: u32,
+}
+
+impl Default for BankedBitmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BankedBitmap {
+    /// Creates an empty bitmap.
+    pub const fn new() -> Self {
+        Self {
+            words: [0; PLIC_NUM_BANKS],
+            summary: 0,
+        }
+    }
+
+    /// Returns the value of the bit for `index`.
+    pub fn get(&self, index: usize) -> bool {
+        self.words[source_word(index)] & source_bit(index) != 0
+    }
+
+    /// Sets the bit for `index` to `value`, returning its previous value.
+    pub fn set(&mut self, index: usize, value: bool) -> bool {
+        let bank = source_word(index);
+        let mask = source_bit(index);
+        let prev = self.words[bank] & mask != 0;
+        if value {
+            self.words[bank] |= mask;
+        } else {
+            self.words[bank] &= !mask;
+        }
+        self.refresh_bank(bank);
+        prev
+    }
+
+    /// Returns the 32 bits of `bank`.
+    pub fn word(&self, bank: usize) -> u32 {
+        self.words[bank]
+    }
+
+    /// Replaces the 32 bits of `bank`.
+    pub fn set_word(&mut self, bank: usize, word: u32) {
+        self.words[bank] = word;
+        self.refresh_bank(bank);
+    }
+
+    /// Returns the summary word, with bit `b` set if bank `b` is not empty.
+    pub fn summary(&self) -> u32 {
+        self.summary
+    }
+
+    /// Returns `true` if no bit is set.
+    pub fn is_empty(&self) -> bool {
+        self.summary == 0
+    }
+
+    /// Returns the lowest set index, if any.
+    pub fn first_index(&self) -> Option<usize> {
+        self.iter().next()
+    }
+
+    /// Iterates over the set indices in ascending order, visiting only
+    /// non-empty banks.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        BankIter::new(self.summary).flat_map(move |bank| {
+            BankIter::new(self.words[bank]).map(move |bit| word_source(bank, bit))
+        })
+    }
+
+    fn refresh_bank(&mut self, bank: usize) {
+        if self.words[bank] != 0 {
+            self.summary |= 1 << bank;
+        } else {
+            self.summary &= !(1 << bank);
+        }
+    }
+}
+
+/// One bank of an [`AtomicBitmap`], alone on its cache line.
+#[derive(Debug)]
+#[repr(align(64))]
+struct AtomicBank(AtomicU32);
+
+/// A source bitmap of atomic words, one per bank, updated with
+/// `fetch_or`/`fetch_and`.
+///
+/// Each bit changes atomically; reading several bits or words is not a
+/// consistent snapshot while other harts update them.
+#[derive(Debug)]
+pub struct AtomicBitmap {
+    banks: [AtomicBank; PLIC_NUM_BANKS],
+}
+
+impl Default for AtomicBitmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomicBitmap {
+    /// Creates an empty bitmap.
+    pub const fn new() -> Self {
+        Self {
+            banks: [const { AtomicBank(AtomicU32::new(0)) }; PLIC_NUM_BANKS],
+        }
+    }
+
+    /// Returns the value of the bit for `index`.
+    pub fn get(&self, index: usize) -> bool {
+        self.word(source_word(index)) & source_bit(index) != 0
+    }
+
+    /// Atomically sets the bit for `index` to `value`, returning its previous
+    /// value.
+    pub fn set(&self, index: usize, value: bool) -> bool {
+        let word = &self.banks[source_word(index)].0;
+        let mask = source_bit(index);
+        let prev = if value {
+            word.fetch_or(mask, Ordering::AcqRel)
+        } else {
+            word.fetch_and(!mask, Ordering::AcqRel)
+        };
+        prev & mask != 0
+    }
+
+    /// Returns the 32 bits of `bank`.
+    pub fn word(&self, bank: usize) -> u32 {
+        self.banks[bank].0.load(Ordering::Acquire)
+    }
+
+    /// Atomically replaces the 32 bits of `bank`.
+    pub fn set_word(&self, bank: usize, val: u32) {
+        self.banks[bank].0.store(val, Ordering::Release);
+    }
+
+    /// Returns the summary word, with bit `b` set if bank `b` is not empty.
+    pub fn summary(&self) -> u32 {
+        (0..PLIC_NUM_BANKS)
+            .filter(|&bank| self.word(bank) != 0)
+            .fold(0, |summary, bank| summary | 1 << bank)
+    }
+
+    /// Returns `true` if no bit is set.
+    pub fn is_empty(&self) -> bool {
+        self.banks.iter().all(|bank| bank.0.load(Ordering::Acquire) == 0)
+    }
+
+    /// Returns the 32-bit words of all banks.
+    pub fn words(&self) -> [u32; PLIC_NUM_BANKS] {
+        let mut words = [0; PLIC_NUM_BANKS];
+        for (bank, word) in words.iter_mut().enumerate() {
+            *word = self.word(bank);
+        }
+        words
+    }
+
+    /// Returns a copy of the bits.
+    pub fn snapshot(&self) -> BankedBitmap {
+        let mut bitmap = BankedBitmap::new();
+        for bank in 0..PLIC_NUM_BANKS {
+            bitmap.set_word(bank, self.word(bank));
+        }
+        bitmap
+    }
+}
+
+/// Iterates over the set bits of a word, lowest first.
+#[derive(Clone)]
+pub(crate) struct BankIter(u32);
+
+impl BankIter {
+    pub(crate) fn new(word: u32) -> Self {
+        Self(word)
+    }
+}
+
+impl Iterator for BankIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let bit = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::{align_of, size_of};
+
+    use super::{word_source, AtomicBank, AtomicBitmap, PLIC_NUM_BANKS};
+
+    #[test]
+    fn banks_do_not_share_cache_lines() {
+        assert_eq!(align_of::<AtomicBank>(), 64);
+        assert_eq!(size_of::<AtomicBitmap>(), PLIC_NUM_BANKS * 64);
+    }
+
+    #[test]
+    fn concurrent_banks_keep_every_bit() {
+        let bitmap = AtomicBitmap::new();
+        std::thread::scope(|scope| {
+            for bank in 0..PLIC_NUM_BANKS {
+                let bitmap = &bitmap;
+                scope.spawn(move || {
+                    for bit in 0..32 {
+                        assert!(!bitmap.set(word_source(bank, bit), true));
+                    }
+                });
+            }
+        });
+        assert!((0..PLIC_NUM_BANKS).all(|bank| bitmap.word(bank) == u32::MAX));
+        assert_eq!(bitmap.summary(), u32::MAX);
+        bitmap.set_word(3, 0);
+        assert!(!bitmap.get(word_source(3, 5)));
+        assert!(bitmap.get(word_source(4, 5)));
+    }
+}
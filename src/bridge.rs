@@ -0,0 +1,17 @@
+//! Adapter between the vPLIC and device models that live outside the
+//! hypervisor core (another VM, host userspace, ...).
+
+/// A host-provided channel (callback/queue handle) used to exchange interrupt
+/// events with an out-of-core device model.
+pub trait EventChannel: Send + Sync {
+    /// Forwards a claim of `irq` by the guest.
+    fn claimed(&self, irq: usize);
+    /// Forwards a completion of `irq` by the guest.
+    fn completed(&self, irq: usize);
+    /// Pops the next IRQ the device model wants injected, if any.
+    fn next_injection(&self) -> Option<usize>;
+    /// Identifies the device model in injection provenance records.
+    fn device_id(&self) -> u32 {
+        0
+    }
+}
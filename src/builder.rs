@@ -0,0 +1,137 @@
+//! Builder for [`VPlicGlobal`] with an initial register configuration.
+//!
+//! Guests booted without firmware (unikernels, bare kernels) often assume
+//! the PLIC was already set up for them. The builder pre-programs the
+//! guest-visible priorities, enables and thresholds before the guest runs.
+
+use alloc::vec::Vec;
+
+use axaddrspace::{GuestPhysAddr, HostPhysAddr};
+use axerrno::{AxError, AxResult};
+
+use crate::{h_extension_present, DeliveryBackend, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Builder of a [`VPlicGlobal`].
+pub struct VPlicBuilder {
+    addr: GuestPhysAddr,
+    size: Option<usize>,
+    host_addr: Option<HostPhysAddr>,
+    contexts_num: usize,
+    priorities: Vec<u32>,
+    enables: Vec<(usize, usize)>,
+    thresholds: Vec<(usize, u32)>,
+    emulated: bool,
+    h_extension: bool,
+    backend: Option<&'static dyn DeliveryBackend>,
+}
+
+impl VPlicBuilder {
+    /// Starts building a vPLIC at `addr` with `contexts_num` contexts.
+    pub fn new(addr: GuestPhysAddr, contexts_num: usize) -> Self {
+        Self {
+            addr,
+            size: None,
+            host_addr: None,
+            contexts_num,
+            priorities: Vec::new(),
+            enables: Vec::new(),
+            thresholds: Vec::new(),
+            emulated: false,
+            h_extension: cfg!(any(target_arch = "riscv32", target_arch = "riscv64")),
+            backend: None,
+        }
+    }
+
+    /// Sets the size of the MMIO region.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Backs the vPLIC with the host PLIC at `host_addr` instead of the one
+    /// at the guest address.
+    pub fn host_addr(mut self, host_addr: HostPhysAddr) -> Self {
+        self.host_addr = Some(host_addr);
+        self
+    }
+
+    /// Keeps all register state in software, without host PLIC backing.
+    pub fn emulated(mut self, emulated: bool) -> Self {
+        self.emulated = emulated;
+        self
+    }
+
+    /// Describes the host by its ISA string; without the H extension the
+    /// vPLIC is built in pure device-model mode.
+    pub fn host_isa(mut self, isa: &str) -> Self {
+        self.h_extension = h_extension_present(isa);
+        self
+    }
+
+    /// Notifies the guest through `backend` instead of hvip.
+    pub fn delivery(mut self, backend: &'static dyn DeliveryBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Pre-seeds the priorities of sources `0..priorities.len()`.
+    pub fn priorities(mut self, priorities: &[u32]) -> Self {
+        self.priorities = priorities.to_vec();
+        self
+    }
+
+    /// Pre-seeds the priority of `irq_id`.
+    pub fn priority(mut self, irq_id: usize, priority: u32) -> Self {
+        if self.priorities.len() <= irq_id {
+            self.priorities.resize(irq_id + 1, 0);
+        }
+        self.priorities[irq_id] = priority;
+        self
+    }
+
+    /// Pre-enables `irq_id` for `context_id`.
+    pub fn enable(mut self, context_id: usize, irq_id: usize) -> Self {
+        self.enables.push((context_id, irq_id));
+        self
+    }
+
+    /// Pre-seeds the threshold of `context_id`.
+    pub fn threshold(mut self, context_id: usize, threshold: u32) -> Self {
+        self.thresholds.push((context_id, threshold));
+        self
+    }
+
+    /// Creates the vPLIC and programs the initial configuration.
+    ///
+    /// Without the H extension, fails with [`AxError::Unsupported`] unless a
+    /// delivery backend was given.
+    pub fn build(self) -> AxResult<VPlicGlobal> {
+        if self.enables.iter().any(|&(_, irq_id)| irq_id == 0 || irq_id >= PLIC_NUM_SOURCES) {
+            return Err(AxError::InvalidInput);
+        }
+        let vplic = match (self.h_extension, self.backend) {
+            (false, None) => return Err(AxError::Unsupported),
+            (false, Some(backend)) => {
+                VPlicGlobal::new_device_model(self.addr, self.size, self.contexts_num, backend)
+            }
+            (true, _) if self.emulated => VPlicGlobal::new_emulated(self.addr, self.size, self.contexts_num),
+            (true, _) => match self.host_addr {
+                Some(host_addr) => {
+                    VPlicGlobal::new_with_host(self.addr, self.size, self.contexts_num, host_addr)
+                }
+                None => VPlicGlobal::new(self.addr, self.size, self.contexts_num),
+            },
+        };
+        if let Some(backend) = self.backend {
+            vplic.set_delivery_backend(backend);
+        }
+        vplic.load_priorities(&self.priorities)?;
+        for (context_id, irq_id) in self.enables {
+            vplic.set_enable(context_id, irq_id, true)?;
+        }
+        for (context_id, threshold) in self.thresholds {
+            vplic.set_threshold(context_id, threshold)?;
+        }
+        Ok(vplic)
+    }
+}
@@ -0,0 +1,47 @@
+//! Capabilities of a vPLIC, for orchestration layers.
+//!
+//! [`VPlicGlobal::capabilities`] combines host probing (guest interrupt
+//! files, host PLIC backing) with the current configuration of the VM, so
+//! the VMM can decide placement and which features to enable per VM.
+
+use crate::hal::hal;
+use crate::VPlicGlobal;
+
+/// The host has AIA guest interrupt files to back guest interrupt files
+/// (see [`VImsic`](crate::VImsic)) on the current hart.
+pub const CAP_AIA_BACKEND: u32 = 1 << 0;
+/// Passthrough sources can be delivered through guest external interrupt
+/// lines (see [`VPlicGlobal::set_direct_irq`]).
+pub const CAP_DIRECT_MAP: u32 = 1 << 1;
+/// Assigned host sources can be presented under other numbers (see
+/// [`VPlicGlobal::compact_irqs`]).
+pub const CAP_REMAP: u32 = 1 << 2;
+/// The interrupt state can be saved and restored on another host (see
+/// [`VPlicGlobal::save_state`]).
+pub const CAP_MIGRATION: u32 = 1 << 3;
+
+impl VPlicGlobal {
+    /// Returns the `CAP_*` flags of this vPLIC on the current hart.
+    ///
+    /// Direct delivery and remapping need a host PLIC behind the vPLIC.
+    /// Migration is lost while sources are delivered directly, as their
+    /// pending state then lives in the host PLIC.
+    pub fn capabilities(&self) -> u32 {
+        let guest_files = hal().guest_external_lines() != 0;
+        let host_backed = !self.is_emulated();
+        let mut caps = 0;
+        if guest_files {
+            caps |= CAP_AIA_BACKEND;
+        }
+        if guest_files && host_backed {
+            caps |= CAP_DIRECT_MAP;
+        }
+        if host_backed {
+            caps |= CAP_REMAP;
+        }
+        if self.direct_irqs.lock().is_empty() {
+            caps |= CAP_MIGRATION;
+        }
+        caps
+    }
+}
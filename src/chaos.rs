@@ -0,0 +1,196 @@
+//! Error-injection mode for guest driver testing.
+//!
+//! When enabled, the vPLIC deliberately misbehaves in ways a guest driver
+//! must tolerate: spurious claims returning 0, duplicated or dropped
+//! injections, and completions that only take effect later. Decisions come
+//! from a seeded xorshift generator so runs are reproducible.
+//!
+//! Misbehavior stays within what a PLIC can do: a duplicate goes through
+//! the gateway like any injection, so it is held while the source is being
+//! handled, and a delayed completion gets the full completion bookkeeping
+//! once it is released.
+
+use alloc::vec::Vec;
+
+use axaddrspace::HostPhysAddr;
+
+use crate::utils::XorShift64;
+use crate::{InjectionSource, VPlicGlobal};
+
+/// Error-injection settings. Every rate is a percentage in `0..=100`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChaosConfig {
+    /// Claims answered with 0 even though an IRQ is pending.
+    pub spurious_claim_pct: u8,
+    /// Injections delivered twice.
+    pub duplicate_inject_pct: u8,
+    /// Injections silently dropped.
+    pub drop_inject_pct: u8,
+    /// Completions held back until the next completion write.
+    pub delay_complete_pct: u8,
+    /// Seed of the pseudo-random generator.
+    pub seed: u64,
+}
+
+/// Runtime state of the error-injection mode.
+pub(crate) struct Chaos {
+    config: ChaosConfig,
+    rng: XorShift64,
+    /// Held back completions, as (context, IRQ id, host claim/complete
+    /// register).
+    delayed_completes: Vec<(usize, usize, HostPhysAddr)>,
+    /// IRQs owed a duplicate delivery, with the origin of the injection.
+    duplicates: Vec<(usize, InjectionSource)>,
+}
+
+impl Chaos {
+    fn new(config: ChaosConfig) -> Self {
+        Self {
+            config,
+            rng: XorShift64::new(config.seed),
+            delayed_completes: Vec::new(),
+            duplicates: Vec::new(),
+        }
+    }
+
+    /// Returns `true` with probability `pct` percent.
+    fn roll(&mut self, pct: u8) -> bool {
+        if pct == 0 {
+            return false;
+        }
+        self.rng.next_u64() % 100 < pct as u64
+    }
+}
+
+impl VPlicGlobal {
+    /// Enables the error-injection mode with `config`.
+    pub fn enable_chaos(&self, config: ChaosConfig) {
+        *self.chaos.lock() = Some(Chaos::new(config));
+    }
+
+    /// Disables the error-injection mode, applying held back completions.
+    pub fn disable_chaos(&self) {
+        let chaos = self.chaos.lock().take();
+        if let Some(chaos) = chaos {
+            for (context_id, irq_id, host_addr) in chaos.delayed_completes {
+                let _ = self.retire_completion(context_id, irq_id, host_addr);
+            }
+        }
+    }
+
+    /// Decides whether the next claim should spuriously return 0.
+    pub(crate) fn chaos_spurious_claim(&self) -> bool {
+        match self.chaos.lock().as_mut() {
+            Some(chaos) => chaos.roll(chaos.config.spurious_claim_pct),
+            None => false,
+        }
+    }
+
+    /// Decides the fate of an injection of `irq_id` by `source`.
+    ///
+    /// Returns `false` if it must be dropped. Duplicated injections are
+    /// remembered and injected again right after the guest claims them.
+    pub(crate) fn chaos_filter_injection(&self, irq_id: usize, source: InjectionSource) -> bool {
+        let mut chaos = self.chaos.lock();
+        let chaos = match chaos.as_mut() {
+            Some(chaos) => chaos,
+            None => return true,
+        };
+        if chaos.roll(chaos.config.drop_inject_pct) {
+            return false;
+        }
+        if chaos.roll(chaos.config.duplicate_inject_pct) {
+            chaos.duplicates.push((irq_id, source));
+        }
+        true
+    }
+
+    /// Returns the origin of the duplicate of the just claimed `irq_id`, if
+    /// one is owed.
+    pub(crate) fn chaos_take_duplicate(&self, irq_id: usize) -> Option<InjectionSource> {
+        let mut chaos = self.chaos.lock();
+        let duplicates = match chaos.as_mut() {
+            Some(chaos) => &mut chaos.duplicates,
+            None => return None,
+        };
+        let pos = duplicates.iter().position(|&(id, _)| id == irq_id)?;
+        Some(duplicates.swap_remove(pos).1)
+    }
+
+    /// Holds back the completion of `irq_id` by `context_id` if the dice say
+    /// so.
+    ///
+    /// Returns `true` if the completion was held back.
+    pub(crate) fn chaos_delay_complete(
+        &self,
+        context_id: usize,
+        irq_id: usize,
+        host_addr: HostPhysAddr,
+    ) -> bool {
+        let mut chaos = self.chaos.lock();
+        let chaos = match chaos.as_mut() {
+            Some(chaos) => chaos,
+            None => return false,
+        };
+        if chaos.roll(chaos.config.delay_complete_pct) {
+            chaos.delayed_completes.push((context_id, irq_id, host_addr));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the completions held back so far.
+    pub(crate) fn chaos_take_delayed(&self) -> Vec<(usize, usize, HostPhysAddr)> {
+        match self.chaos.lock().as_mut() {
+            Some(chaos) => core::mem::take(&mut chaos.delayed_completes),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChaosConfig;
+    use crate::mock::testing::*;
+    use crate::{BackToBackClaim, SourceState};
+
+    #[test]
+    fn delayed_completion_releases_the_claim() {
+        let vplic = mock_vplic(1);
+        vplic.set_back_to_back_claim(BackToBackClaim::Zero);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(1).unwrap();
+        vplic.inject_irq(2).unwrap();
+        vplic.enable_chaos(ChaosConfig {
+            delay_complete_pct: 100,
+            ..Default::default()
+        });
+        assert_eq!(claim(&vplic, 0), 1);
+        complete(&vplic, 0, 1);
+        assert_eq!(vplic.last_claim(0), Some(1));
+        assert_eq!(claim(&vplic, 0), 0);
+        // The next completion write releases the delayed one.
+        complete(&vplic, 0, 1);
+        assert_eq!(vplic.last_claim(0), None);
+        assert_eq!(vplic.source_state(1), SourceState::Inactive);
+        assert_eq!(claim(&vplic, 0), 2);
+    }
+
+    #[test]
+    fn duplicate_is_held_by_the_gateway() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 3, 1);
+        vplic.enable_chaos(ChaosConfig {
+            duplicate_inject_pct: 100,
+            ..Default::default()
+        });
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(claim(&vplic, 0), 3);
+        assert_eq!(vplic.source_state(3), SourceState::Active);
+        vplic.disable_chaos();
+        complete(&vplic, 0, 3);
+        assert_eq!(vplic.source_state(3), SourceState::Pending);
+    }
+}
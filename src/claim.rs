@@ -0,0 +1,304 @@
+//! Claim bookkeeping per context.
+//!
+//! Some guests read the claim register twice in quick succession (buggy
+//! drivers, or after an early spurious wake). The last claim of every context
+//! is tracked until it is completed, and [`BackToBackClaim`] defines what a
+//! second claim returns meanwhile.
+//!
+//! As on a real PLIC, a completion of an IRQ the context does not hold (not
+//! active, or claimed by another context) is silently ignored and never
+//! reaches the host PLIC. A snapshot only records the last claim of each
+//! context, so other claims restored from one accept a completion from any
+//! context.
+//!
+//! With claim batching, a claim that leaves more IRQs its context can claim
+//! (enabled, above its threshold and not blocked by the in-flight claim)
+//! keeps VSEIP asserted, so the guest handler loops over the burst instead
+//! of taking one exit per interrupt.
+
+use core::sync::atomic::Ordering;
+
+use axaddrspace::HostPhysAddr;
+use axerrno::AxResult;
+use log::{trace, warn};
+
+use crate::delivery::GuestNotifier;
+use crate::{source_word, SourceEvent, TracePoint, VPlicGlobal};
+
+/// Result of a claim issued while the previous claim of the same context is
+/// still in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackToBackClaim {
+    /// Claim the next eligible IRQ, as real hardware does.
+    #[default]
+    NextEligible,
+    /// Return 0 until the in-flight IRQ is completed.
+    Zero,
+}
+
+impl VPlicGlobal {
+    /// Sets the back-to-back claim behavior.
+    pub fn set_back_to_back_claim(&self, policy: BackToBackClaim) {
+        self.claim_zero_in_flight
+            .store(policy == BackToBackClaim::Zero, Ordering::Relaxed);
+    }
+
+    /// Returns the back-to-back claim behavior.
+    pub fn back_to_back_claim(&self) -> BackToBackClaim {
+        if self.claim_zero_in_flight.load(Ordering::Relaxed) {
+            BackToBackClaim::Zero
+        } else {
+            BackToBackClaim::NextEligible
+        }
+    }
+
+    /// Enables or disables claim batching.
+    pub fn set_claim_batching(&self, enable: bool) {
+        self.claim_batching.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if claim batching is enabled.
+    pub fn claim_batching(&self) -> bool {
+        self.claim_batching.load(Ordering::Relaxed)
+    }
+
+    /// Returns the IRQ last claimed by `context_id` and not completed yet.
+    pub fn last_claim(&self, context_id: usize) -> Option<usize> {
+        match self.contexts.lock().get(context_id) {
+            Some(context) if context.last_claim != 0 => Some(context.last_claim),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if a claim by `context_id` must return 0 because its
+    /// previous claim is still in flight.
+    pub(crate) fn claim_blocked(&self, context_id: usize) -> bool {
+        self.back_to_back_claim() == BackToBackClaim::Zero && self.last_claim(context_id).is_some()
+    }
+
+    /// Records that `context_id` claimed `irq_id`.
+    pub(crate) fn record_claim(&self, context_id: usize, irq_id: usize) {
+        self.contexts.lock()[context_id].last_claim = irq_id;
+        self.claimants.lock()[irq_id] = Some(context_id);
+    }
+
+    /// Forgets the context holding the claim of `irq_id` once it is retired.
+    pub(crate) fn forget_claimant(&self, irq_id: usize) {
+        if let Some(claimant) = self.claimants.lock().get_mut(irq_id) {
+            *claimant = None;
+        }
+    }
+
+    /// Forgets the claim of `irq_id` when it goes back to pending without
+    /// being completed, so its claimant no longer holds it.
+    pub(crate) fn release_claim(&self, irq_id: usize) {
+        self.forget_claimant(irq_id);
+        for context in self.contexts.lock().iter_mut() {
+            if context.last_claim == irq_id {
+                context.last_claim = 0;
+            }
+        }
+    }
+
+    /// Returns `true` if `context_id` may complete `irq_id`: the IRQ is
+    /// active and, if known, claimed by `context_id`.
+    pub(crate) fn holds_claim(&self, context_id: usize, irq_id: usize) -> bool {
+        match self.claimants.lock().get(irq_id) {
+            Some(&claimant) => {
+                self.active_irqs.get(irq_id) && claimant.is_none_or(|claimant| claimant == context_id)
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets the claim of `irq_id` by `context_id` once it is completed.
+    pub(crate) fn record_complete(&self, context_id: usize, irq_id: usize) {
+        let mut contexts = self.contexts.lock();
+        if contexts[context_id].last_claim == irq_id {
+            contexts[context_id].last_claim = 0;
+        }
+    }
+
+    /// Claims the IRQ to be handled by `context_id`, returning 0 if none.
+    pub(crate) fn claim(&self, context_id: usize) -> usize {
+        self.claim_in(context_id, u32::MAX)
+    }
+
+    /// Claims the IRQ to be handled by `context_id` among the sources of the
+    /// banks set in `banks`, returning 0 if none.
+    pub(crate) fn claim_in(&self, context_id: usize, banks: u32) -> usize {
+        if self.forbid_in_hook("claim").is_err()
+            || self.chaos_spurious_claim()
+            || self.claim_blocked(context_id)
+        {
+            self.stats.count_claim(0);
+            return 0;
+        }
+        // Clear the pending bit and set the active bit, means the IRQ is being handling.
+        // Another hart may claim the picked IRQ first, then pick again.
+        let irq_id = loop {
+            let irq_id = match self
+                .pick_pending_in(context_id, banks)
+                .and_then(|id| self.intercept_claim(context_id, id))
+                .filter(|&id| banks & 1 << source_word(id) != 0)
+            {
+                Some(id) => id,
+                None => {
+                    self.stats.count_claim(0);
+                    return 0;
+                }
+            };
+            if self.try_transition(irq_id, SourceEvent::Claim) {
+                break irq_id;
+            }
+        };
+        self.account_claim(context_id, irq_id);
+        if self.claim_batching()
+            && !self.claim_blocked(context_id)
+            && self.pick_pending(context_id).is_some()
+        {
+            self.stats.count_batched_claim();
+            self.assert_vseip();
+        }
+        self.refresh_deliverability();
+        irq_id
+    }
+
+    /// Returns the IRQ a claim by `context_id` would return right now, or 0,
+    /// without claiming it.
+    ///
+    /// Meant for debuggers, monitors and trace capture: no state changes and
+    /// no error injection applies.
+    pub fn peek_claim(&self, context_id: usize) -> usize {
+        if context_id >= self.contexts_num || self.claim_blocked(context_id) {
+            return 0;
+        }
+        self.pick_pending(context_id).unwrap_or(0)
+    }
+
+    /// Bookkeeping of a claim of `irq_id` by `context_id`, once it moved from
+    /// pending to active.
+    pub(crate) fn account_claim(&self, context_id: usize, irq_id: usize) {
+        self.disarm_claim_timeout(context_id);
+        if let Some(source) = self.chaos_take_duplicate(irq_id) {
+            self.deliver(irq_id, source);
+        }
+        self.record_claim(context_id, irq_id);
+        self.trace_point(TracePoint::Claim, irq_id, context_id);
+        self.history_claim(context_id, irq_id);
+        self.attribute_claim(irq_id);
+        self.clear_backlog(irq_id);
+        self.call_hooks(|hooks| hooks.claimed(context_id, irq_id, self.source_cookie(irq_id)));
+        let channel = *self.event_channel.lock();
+        if let Some(channel) = channel {
+            channel.claimed(irq_id);
+        }
+        self.stats.count_claim(irq_id);
+    }
+
+    /// Completes `irq_id` on behalf of `context_id`; `host_addr` is the host
+    /// claim/complete register of the context.
+    pub(crate) fn complete(&self, context_id: usize, irq_id: usize, host_addr: HostPhysAddr) -> AxResult {
+        self.forbid_in_hook("complete")?;
+        for (delayed_context, delayed_irq, delayed_addr) in self.chaos_take_delayed() {
+            self.retire_completion(delayed_context, delayed_irq, delayed_addr)?;
+        }
+        if !self.guest_owns(irq_id) {
+            warn!(
+                "vPlicGlobal: ignore guest completion of hypervisor-owned {}",
+                self.source_label(irq_id)
+            );
+            return Ok(());
+        }
+        if !self.holds_claim(context_id, irq_id) {
+            trace!(
+                "vPlicGlobal: ignore completion of {} not claimed by context {}",
+                self.source_label(irq_id),
+                context_id
+            );
+            return Ok(());
+        }
+        if self.chaos_delay_complete(context_id, irq_id, host_addr) {
+            return Ok(());
+        }
+        self.retire_completion(context_id, irq_id, host_addr)
+    }
+
+    /// Retires the completion of `irq_id` held by `context_id`, forwarding it
+    /// to the host claim/complete register `host_addr`.
+    pub(crate) fn retire_completion(
+        &self,
+        context_id: usize,
+        irq_id: usize,
+        host_addr: HostPhysAddr,
+    ) -> AxResult {
+        self.record_complete(context_id, irq_id);
+        self.history_complete(context_id, irq_id);
+
+        // There is no irq to handle.
+        if self.pending_irqs.is_empty() {
+            self.deassert_vseip();
+        }
+
+        let ret = self.finish_complete(irq_id, host_addr);
+        self.refresh_deliverability();
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackToBackClaim;
+    use crate::mock::testing::*;
+    use crate::SourceState;
+
+    #[test]
+    fn back_to_back_claim_takes_next_eligible() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(1).unwrap();
+        vplic.inject_irq(2).unwrap();
+        assert_eq!(claim(&vplic, 0), 1);
+        assert_eq!(claim(&vplic, 0), 2);
+        assert_eq!(vplic.last_claim(0), Some(2));
+    }
+
+    #[test]
+    fn back_to_back_claim_returns_zero_in_flight() {
+        let vplic = mock_vplic(1);
+        vplic.set_back_to_back_claim(BackToBackClaim::Zero);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(1).unwrap();
+        vplic.inject_irq(2).unwrap();
+        assert_eq!(claim(&vplic, 0), 1);
+        assert_eq!(claim(&vplic, 0), 0);
+        assert!(vplic.is_irq_pending(2));
+        complete(&vplic, 0, 1);
+        assert_eq!(vplic.last_claim(0), None);
+        assert_eq!(claim(&vplic, 0), 2);
+    }
+
+    #[test]
+    fn bogus_completion_is_ignored() {
+        let vplic = mock_vplic(2);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(1).unwrap();
+        assert_eq!(claim(&vplic, 0), 1);
+        // Held by the closed gateway until 1 is completed.
+        vplic.inject_irq(1).unwrap();
+        vplic.inject_irq(2).unwrap();
+        complete(&vplic, 0, 2);
+        complete(&vplic, 1, 1);
+        complete(&vplic, 0, 5);
+        assert_eq!(vplic.source_state(1), SourceState::Active);
+        assert_eq!(vplic.source_state(2), SourceState::Pending);
+        assert_eq!(vplic.last_claim(0), Some(1));
+        assert_eq!(vplic.stats().completions, 0);
+        complete(&vplic, 0, 1);
+        assert_eq!(vplic.stats().completions, 1);
+        assert_eq!(vplic.source_state(1), SourceState::Pending);
+    }
+}
@@ -0,0 +1,135 @@
+//! Write-combining of host PLIC register accesses.
+//!
+//! Host PLIC registers are uncached MMIO, and bulk reprogramming (VM start,
+//! restore, masking all sources) touches the same enable word once per
+//! source when done source by source. A [`WriteCombiner`] reads each register at most once, merges
+//! the updates in memory and writes every changed register once, by
+//! increasing offset, when flushed.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use core::sync::atomic::Ordering;
+
+use axerrno::AxResult;
+
+use crate::host::HostBackend;
+use crate::{BankedBitmap, VPlicGlobal, PLIC_NUM_BANKS};
+
+/// Buffer of host register updates, flushed in offset order.
+pub(crate) struct WriteCombiner<'a, B: HostBackend> {
+    host: &'a B,
+    /// Per offset, the value read from the host if any, and the value to
+    /// write.
+    regs: BTreeMap<usize, (Option<u32>, u32)>,
+}
+
+impl<'a, B: HostBackend> WriteCombiner<'a, B> {
+    pub(crate) fn new(host: &'a B) -> Self {
+        Self {
+            host,
+            regs: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the value of the register at `offset`, including buffered
+    /// updates.
+    pub(crate) fn read(&mut self, offset: usize) -> AxResult<u32> {
+        if let Some(&(_, val)) = self.regs.get(&offset) {
+            return Ok(val);
+        }
+        let val = self.host.host_read(offset)?;
+        self.regs.insert(offset, (Some(val), val));
+        Ok(val)
+    }
+
+    /// Buffers a write of `val` to the register at `offset`.
+    pub(crate) fn write(&mut self, offset: usize, val: u32) {
+        self.regs
+            .entry(offset)
+            .and_modify(|(_, pending)| *pending = val)
+            .or_insert((None, val));
+    }
+
+    /// Buffers a read-modify-write of the register at `offset`.
+    pub(crate) fn modify(&mut self, offset: usize, f: impl FnOnce(u32) -> u32) -> AxResult {
+        let val = self.read(offset)?;
+        self.write(offset, f(val));
+        Ok(())
+    }
+
+    /// Writes the buffered registers whose value changed, by increasing
+    /// offset.
+    pub(crate) fn flush(self) -> AxResult {
+        for (offset, (read, val)) in self.regs {
+            if read != Some(val) {
+                self.host.host_write(offset, val)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VPlicGlobal {
+    /// Reprograms the host enables of every context from the guest shadows
+    /// in one combined pass, clearing the assigned sources the guest did not
+    /// enable and leaving the other sources untouched.
+    pub(crate) fn sync_host_enables(&self) -> AxResult {
+        let host_offsets: Vec<usize> = (0..self.contexts_num)
+            .map(|context_id| self.host_enable_offset(context_id, 0))
+            .collect();
+        let shadows: Vec<BankedBitmap> =
+            self.contexts.lock().iter().map(|context| context.enables).collect();
+        let assigned: Vec<u32> =
+            (0..PLIC_NUM_BANKS).map(|host_word| self.assigned_word(host_word)).collect();
+        let mut combiner = WriteCombiner::new(self);
+        for (shadow, &host_offset) in shadows.iter().zip(&host_offsets) {
+            for (host_word, &mask) in assigned.iter().enumerate() {
+                if mask == 0 {
+                    continue;
+                }
+                let enabled = self.host_enable_word(shadow, host_word);
+                combiner.modify(host_offset + host_word * 4, |word| word & !mask | enabled)?;
+            }
+        }
+        combiner.flush()?;
+        self.publish_hint();
+        Ok(())
+    }
+
+    /// Masks or unmasks every source of the VM in the host enables in one
+    /// combined pass, e.g. while the VM is stopped. The guest enables are
+    /// kept, and per-source enable updates honour the mask meanwhile.
+    pub fn mask_all_host_enables(&self, masked: bool) -> AxResult {
+        self.host_masked.store(masked, Ordering::SeqCst);
+        self.sync_host_enables()
+    }
+
+    /// Returns `true` if every source of the VM is masked in the host
+    /// enables.
+    pub fn host_enables_masked(&self) -> bool {
+        self.host_masked.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::HostBackend;
+    use crate::mock::testing::*;
+    use crate::SourceInfo;
+
+    #[test]
+    fn mask_all_holds_per_source_enables() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(3, SourceInfo::default()).unwrap();
+        vplic.assign_source(4, SourceInfo::default()).unwrap();
+        let host_enables = || vplic.host_read(vplic.host_enable_offset(0, 0)).unwrap();
+        vplic.set_enable(0, 3, true).unwrap();
+        vplic.mask_all_host_enables(true).unwrap();
+        assert_eq!(host_enables(), 0);
+        vplic.set_enable(0, 4, true).unwrap();
+        assert_eq!(host_enables(), 0);
+        vplic.mask_all_host_enables(false).unwrap();
+        assert_eq!(host_enables(), 1 << 3 | 1 << 4);
+    }
+}
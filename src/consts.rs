@@ -6,6 +6,11 @@ pub const PLIC_NUM_SOURCES: usize = 1024; // includes source 0 for indexing conv
 
 // --- Register Offsets (relative to PLIC_BASE) ---
 
+/// Highest priority implemented by the vPLIC. Priority and threshold
+/// writes above it are clamped, so a threshold of this value masks every
+/// source.
+pub const PLIC_MAX_PRIORITY: u32 = 7;
+
 /// Offset to priority register for interrupt source 0 (reserved).
 /// Priority for source N is at: PLIC_PRIORITY_OFFSET + N * 4
 pub const PLIC_PRIORITY_OFFSET: usize = 0x000000;
@@ -35,3 +40,71 @@ pub const PLIC_CONTEXT_THRESHOLD_OFFSET: usize = 0x00;
 
 /// Offset within a context's control region to the claim/complete register.
 pub const PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET: usize = 0x04;
+
+// Follows the APLIC memory map of the RISC-V Advanced Interrupt Architecture 1.0.
+
+/// Offset to the domain configuration register.
+pub const APLIC_DOMAINCFG_OFFSET: usize = 0x0000;
+
+/// Offset to the configuration register of source 1.
+/// Source N is configured at: APLIC_SOURCECFG_OFFSET + (N - 1) * 4
+pub const APLIC_SOURCECFG_OFFSET: usize = 0x0004;
+
+/// Offset to the first set-pending word (bits 0–31).
+pub const APLIC_SETIP_OFFSET: usize = 0x1C00;
+
+/// Offset to the set-pending-by-number register.
+pub const APLIC_SETIPNUM_OFFSET: usize = 0x1CDC;
+
+/// Offset to the first rectified-input / clear-pending word.
+pub const APLIC_IN_CLRIP_OFFSET: usize = 0x1D00;
+
+/// Offset to the clear-pending-by-number register.
+pub const APLIC_CLRIPNUM_OFFSET: usize = 0x1DDC;
+
+/// Offset to the first set-enable word.
+pub const APLIC_SETIE_OFFSET: usize = 0x1E00;
+
+/// Offset to the set-enable-by-number register.
+pub const APLIC_SETIENUM_OFFSET: usize = 0x1EDC;
+
+/// Offset to the first clear-enable word.
+pub const APLIC_CLRIE_OFFSET: usize = 0x1F00;
+
+/// Offset to the clear-enable-by-number register.
+pub const APLIC_CLRIENUM_OFFSET: usize = 0x1FDC;
+
+/// Offset to the little-endian set-pending-by-number register.
+pub const APLIC_SETIPNUM_LE_OFFSET: usize = 0x2000;
+
+/// Offset to the big-endian set-pending-by-number register.
+pub const APLIC_SETIPNUM_BE_OFFSET: usize = 0x2004;
+
+/// Offset to the register generating an MSI by software.
+pub const APLIC_GENMSI_OFFSET: usize = 0x3000;
+
+/// Offset to the target register of source 1.
+/// Source N targets: APLIC_TARGET_OFFSET + (N - 1) * 4
+pub const APLIC_TARGET_OFFSET: usize = 0x3004;
+
+/// Offset to the interrupt delivery control (IDC) structure of hart 0.
+/// For hart H, the IDC starts at: APLIC_IDC_OFFSET + H * APLIC_IDC_STRIDE
+pub const APLIC_IDC_OFFSET: usize = 0x4000;
+
+/// Stride between IDC structures (in bytes).
+pub const APLIC_IDC_STRIDE: usize = 0x20;
+
+/// Offset within an IDC to the delivery enable register.
+pub const APLIC_IDC_IDELIVERY_OFFSET: usize = 0x00;
+
+/// Offset within an IDC to the force register.
+pub const APLIC_IDC_IFORCE_OFFSET: usize = 0x04;
+
+/// Offset within an IDC to the priority threshold register.
+pub const APLIC_IDC_ITHRESHOLD_OFFSET: usize = 0x08;
+
+/// Offset within an IDC to the top-interrupt register.
+pub const APLIC_IDC_TOPI_OFFSET: usize = 0x18;
+
+/// Offset within an IDC to the claim register.
+pub const APLIC_IDC_CLAIMI_OFFSET: usize = 0x1C;
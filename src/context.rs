@@ -0,0 +1,160 @@
+//! Per-context state and control registers.
+//!
+//! Everything a context owns (enables, threshold, in-flight claim, backing
+//! host context, vCPU binding) lives in one [`ContextState`] indexed by
+//! context ID, so claims and eligibility are computed for the right context
+//! on SMP guests.
+//!
+//! Thresholds are shadowed as written by the guest; the value forwarded to
+//! the host may be clamped by the priority inversion guard.
+
+use axerrno::{AxError, AxResult};
+
+use crate::hartmode::HartMode;
+use crate::host::HostBackend;
+use crate::ordering::ClaimOrder;
+use crate::{BankedBitmap, VPlicGlobal, PLIC_MAX_PRIORITY, PLIC_NUM_BANKS};
+
+/// Consistent view of one context, for debugging a single vCPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextSnapshot {
+    /// The context.
+    pub context_id: usize,
+    /// vCPU owning the context.
+    pub vcpu_id: usize,
+    /// Host context backing the context.
+    pub host_context: usize,
+    /// Priority threshold, as written by the guest.
+    pub threshold: u32,
+    /// Enable words, as written by the guest.
+    pub enables: BankedBitmap,
+    /// Pending, enabled, guest-owned sources with a priority above the
+    /// threshold, i.e. what a claim could return.
+    pub eligible: BankedBitmap,
+    /// IRQ claimed and not completed yet, if any.
+    pub claimed: Option<usize>,
+    /// Whether the vPLIC wants the guest interrupt asserted for the context.
+    pub vseip: bool,
+}
+
+/// State of one guest context.
+#[derive(Debug, Clone)]
+pub(crate) struct ContextState {
+    /// Shadow of the enable words.
+    pub enables: BankedBitmap,
+    /// Shadow of the priority threshold.
+    pub threshold: u32,
+    /// IRQ claimed and not completed yet, 0 if none.
+    pub last_claim: usize,
+    /// Deadline for a claim after VSEIP was asserted.
+    pub claim_deadline: Option<u64>,
+    /// Host context backing this context.
+    pub host_context: usize,
+    /// Whether the hypervisor also takes interrupts on the host context.
+    pub shared: bool,
+    /// vCPU owning this context.
+    pub vcpu_id: usize,
+    /// Order of claims among equal-priority sources.
+    pub claim_order: ClaimOrder,
+    /// Whether the context shares its physical hart.
+    pub hart_mode: HartMode,
+}
+
+impl ContextState {
+    /// Creates the reset state of context `context_id`, backed by the host
+    /// context of the same number and owned by vCPU `context_id`.
+    pub fn new(context_id: usize) -> Self {
+        Self {
+            enables: BankedBitmap::new(),
+            threshold: 0,
+            last_claim: 0,
+            claim_deadline: None,
+            host_context: context_id,
+            shared: false,
+            vcpu_id: context_id,
+            claim_order: ClaimOrder::PriorityThenId,
+            hart_mode: HartMode::Shared,
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Sets the priority threshold of `context_id`, clamped to
+    /// [`PLIC_MAX_PRIORITY`], and re-evaluates the guest interrupt: a
+    /// threshold of [`PLIC_MAX_PRIORITY`] masks every source, so claims
+    /// return 0 and the guest interrupt is withdrawn until it is lowered.
+    pub fn set_threshold(&self, context_id: usize, threshold: u32) -> AxResult {
+        if context_id >= self.contexts_num {
+            return Err(AxError::InvalidInput);
+        }
+        let threshold = threshold.min(PLIC_MAX_PRIORITY);
+        let offset = self.host_threshold_offset(context_id);
+        let guarded = self.guard_threshold(context_id, threshold);
+        {
+            let mut contexts = self.contexts.lock();
+            self.host_write(offset, guarded)?;
+            contexts[context_id].threshold = threshold;
+        }
+        self.resync_vseip();
+        Ok(())
+    }
+
+    /// Captures the state of `context_id` in one shot, without stopping the
+    /// other contexts.
+    pub fn snapshot_context(&self, context_id: usize) -> AxResult<ContextSnapshot> {
+        let contexts = self.contexts.lock();
+        let context = contexts.get(context_id).ok_or(AxError::InvalidInput)?;
+        // Under the same lock as the context state, so both are one instant.
+        let pending_irqs = self.pending_irqs.snapshot();
+        let mut eligible = BankedBitmap::new();
+        for bank in 0..PLIC_NUM_BANKS {
+            eligible.set_word(bank, pending_irqs.word(bank) & context.enables.word(bank));
+        }
+        let candidates = eligible;
+        for irq_id in candidates.iter() {
+            if !self.guest_owns(irq_id) || self.priority(irq_id) <= context.threshold {
+                eligible.set(irq_id, false);
+            }
+        }
+        Ok(ContextSnapshot {
+            context_id,
+            vcpu_id: context.vcpu_id,
+            host_context: context.host_context,
+            threshold: context.threshold,
+            enables: context.enables,
+            vseip: !eligible.is_empty(),
+            eligible,
+            claimed: (context.last_claim != 0).then_some(context.last_claim),
+        })
+    }
+
+    /// Returns the priority threshold of `context_id`.
+    pub fn threshold(&self, context_id: usize) -> AxResult<u32> {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map(|context| context.threshold)
+            .ok_or(AxError::InvalidInput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+    use crate::PLIC_MAX_PRIORITY;
+
+    #[test]
+    fn max_threshold_masks_every_source() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 1, PLIC_MAX_PRIORITY as usize);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(1).unwrap();
+        vplic.inject_irq(2).unwrap();
+        write(&vplic, threshold_reg(0), PLIC_MAX_PRIORITY as usize);
+        assert!(!vplic.should_wake(0));
+        assert_eq!(claim(&vplic, 0), 0);
+        write(&vplic, threshold_reg(0), 0);
+        assert_eq!(claim(&vplic, 0), 1);
+        assert_eq!(claim(&vplic, 0), 2);
+    }
+}
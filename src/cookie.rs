@@ -0,0 +1,25 @@
+//! Opaque per-source cookies.
+//!
+//! Integrators attach a pointer-sized value (device model handle, routing
+//! information, ...) to a source and get it back in the claim and completion
+//! hooks, without side tables keyed by IRQ number.
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Attaches `cookie` to `irq_id`, or detaches it with `None`.
+    pub fn set_source_cookie(&self, irq_id: usize, cookie: Option<usize>) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.cookies.lock()[irq_id] = cookie;
+        Ok(())
+    }
+
+    /// Returns the cookie attached to `irq_id`.
+    pub fn source_cookie(&self, irq_id: usize) -> Option<usize> {
+        self.cookies.lock().get(irq_id).copied().flatten()
+    }
+}
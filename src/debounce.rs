@@ -0,0 +1,121 @@
+//! Debouncing of guest enable writes.
+//!
+//! While probing devices, guests toggle enable bits many times in a row, and
+//! each toggle costs uncached host MMIO accesses. With a debounce window,
+//! guest enable writes only update the shadow; the host enables are
+//! reprogrammed once with the final values when the window expires. The
+//! hypervisor arms a timer for [`VPlicGlobal::enable_flush_deadline`] and
+//! calls [`VPlicGlobal::flush_enables`] when it goes off.
+//!
+//! Meanwhile the host keeps the previous enables, so a host interrupt the
+//! guest just enabled reaches it up to one window late. Meant for guest
+//! bring-up; disable it once the guest is up.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+
+use crate::banks::BankIter;
+use crate::utils::now_ns;
+use crate::{VPlicGlobal, PLIC_NUM_BANKS};
+
+/// Enable words awaiting host programming.
+pub(crate) struct DirtyEnables {
+    /// Per-context mask of dirty enable words.
+    words: Vec<u32>,
+    /// Time at which to program them.
+    deadline: Option<u64>,
+}
+
+impl DirtyEnables {
+    pub(crate) fn new(contexts_num: usize) -> Self {
+        Self {
+            words: vec![0; contexts_num],
+            deadline: None,
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Sets the window during which guest enable writes are coalesced, in
+    /// nanoseconds, or disables debouncing with `None`, programming the
+    /// pending enables right away.
+    pub fn set_enable_debounce(&self, window_ns: Option<u64>) -> AxResult {
+        self.enable_debounce_ns
+            .store(window_ns.unwrap_or(0), Ordering::Relaxed);
+        if window_ns.is_none() {
+            self.flush_enables(u64::MAX)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the enable debounce window in nanoseconds, if enabled.
+    pub fn enable_debounce(&self) -> Option<u64> {
+        match self.enable_debounce_ns.load(Ordering::Relaxed) {
+            0 => None,
+            window_ns => Some(window_ns),
+        }
+    }
+
+    /// Returns the time at which coalesced enable writes are due, if any.
+    pub fn enable_flush_deadline(&self) -> Option<u64> {
+        self.dirty_enables.lock().deadline
+    }
+
+    /// Programs the host enables coalesced so far if they are due at
+    /// `now_ns`.
+    ///
+    /// Returns `true` if they were programmed.
+    pub fn flush_enables(&self, now_ns: u64) -> AxResult<bool> {
+        let words = {
+            let mut dirty = self.dirty_enables.lock();
+            if dirty.deadline.is_none_or(|deadline| deadline > now_ns) {
+                return Ok(false);
+            }
+            dirty.deadline = None;
+            core::mem::replace(&mut dirty.words, vec![0; self.contexts_num])
+        };
+        for (context_id, &mask) in words.iter().enumerate() {
+            for word_index in BankIter::new(mask) {
+                self.modify_enable(context_id, word_index, |word| word)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Writes enable word `word_index` of `context_id` to the shadow only,
+    /// leaving the host programming to [`VPlicGlobal::flush_enables`], if
+    /// debouncing is enabled.
+    ///
+    /// Returns the previous value of the word, or `None` if debouncing is
+    /// disabled and nothing was written.
+    pub(crate) fn defer_enable(
+        &self,
+        context_id: usize,
+        word_index: usize,
+        val: u32,
+    ) -> AxResult<Option<u32>> {
+        let window_ns = match self.enable_debounce() {
+            Some(window_ns) => window_ns,
+            None => return Ok(None),
+        };
+        if context_id >= self.contexts_num || word_index >= PLIC_NUM_BANKS {
+            return Err(AxError::InvalidInput);
+        }
+        let old = {
+            let mut contexts = self.contexts.lock();
+            let enables = &mut contexts[context_id].enables;
+            let old = enables.word(word_index);
+            enables.set_word(word_index, val);
+            old
+        };
+        let mut dirty = self.dirty_enables.lock();
+        dirty.words[context_id] |= 1 << word_index;
+        dirty.deadline.get_or_insert_with(|| now_ns().saturating_add(window_ns));
+        drop(dirty);
+        self.publish_hint();
+        Ok(Some(old))
+    }
+}
@@ -0,0 +1,361 @@
+//! Notification of the guest about pending interrupts.
+//!
+//! Everything that makes the guest see (or stop seeing) an external
+//! interrupt goes through a [`DeliveryBackend`], so new hardware mechanisms
+//! and tests plug in without touching the device model.
+//!
+//! Delivery can also be gated while the guest boots: injections keep
+//! accumulating as pending interrupts, but the guest is only notified once
+//! the gate opens, so it does not take traps before installing its vector.
+//!
+//! A notification that fails (e.g. the target vCPU is being destroyed or its
+//! hart is offline) is not dropped: the interrupts stay pending and the
+//! hypervisor retries with [`VPlicGlobal::retry_delivery`] on its next
+//! scheduling event. After too many failed attempts the pending interrupts
+//! that only the unreachable vCPUs can take are dropped and counted as
+//! undeliverable.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axaddrspace::HostPhysAddr;
+use axerrno::AxResult;
+use log::warn;
+
+use crate::hal::hal;
+use crate::lock::Mutex;
+use crate::{source_bit, source_word, SourceEvent, VPlicGlobal};
+
+/// Default number of failed notification attempts before pending interrupts
+/// are dropped.
+pub const DEFAULT_DELIVERY_RETRIES: u32 = 8;
+
+/// How the guest learns about pending interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// Notify the guest through the delivery backend while interrupts are
+    /// pending.
+    #[default]
+    Interrupt,
+    /// Only update the pending registers and never notify the guest, for
+    /// guests running the PLIC in polled mode or to debug delivery problems.
+    Polling,
+}
+
+/// A mechanism making the guest see a supervisor external interrupt.
+pub trait DeliveryBackend: Send + Sync {
+    /// Makes the external interrupt visible to the guest.
+    ///
+    /// Fails if the guest cannot be notified right now; the notification is
+    /// retried later.
+    fn assert(&self) -> AxResult;
+    /// Withdraws the external interrupt.
+    fn deassert(&self);
+}
+
+/// Legacy delivery through hvip.VSEIP on the current hart, through the HAL.
+pub struct HvipBackend;
+
+impl DeliveryBackend for HvipBackend {
+    fn assert(&self) -> AxResult {
+        hal().set_vseip();
+        Ok(())
+    }
+
+    fn deassert(&self) {
+        hal().clear_vseip();
+    }
+}
+
+/// AIA delivery: hvip.VSEIP plus the priority of the VS-level external
+/// interrupt programmed in hvictl (IID = 9), both through the HAL.
+pub struct HvictlBackend {
+    /// Priority reported in hvictl.IPRIO, 1 (highest) to 255.
+    pub iprio: u8,
+}
+
+impl HvictlBackend {
+    /// Interrupt identity of supervisor external interrupts.
+    const IID_SEI: usize = 9;
+}
+
+impl DeliveryBackend for HvictlBackend {
+    fn assert(&self) -> AxResult {
+        hal().set_hvictl(Self::IID_SEI << 16 | self.iprio as usize)?;
+        hal().set_vseip();
+        Ok(())
+    }
+
+    fn deassert(&self) {
+        hal().clear_vseip();
+        let _ = hal().set_hvictl(0);
+    }
+}
+
+/// Delivery through a guest interrupt file of an IMSIC: asserting writes the
+/// interrupt identity to the file's `seteipnum_le` register, and the guest
+/// clears it itself when claiming through its `topei` CSR.
+pub struct GuestFileBackend {
+    /// Host physical address of the guest interrupt file.
+    file_addr: HostPhysAddr,
+    /// Interrupt identity standing for the vPLIC in the file.
+    eiid: u32,
+}
+
+impl GuestFileBackend {
+    /// Creates a backend signalling `eiid` in the guest interrupt file at
+    /// `file_addr`.
+    ///
+    /// # Safety
+    ///
+    /// `file_addr` must be the host physical address of a guest interrupt
+    /// file, mapped by the HAL, for as long as the backend is in use.
+    pub const unsafe fn new(file_addr: HostPhysAddr, eiid: u32) -> Self {
+        Self { file_addr, eiid }
+    }
+}
+
+impl DeliveryBackend for GuestFileBackend {
+    fn assert(&self) -> AxResult {
+        let seteipnum = hal().phys_to_virt(self.file_addr).as_mut_ptr() as *mut u32;
+        // SAFETY: `file_addr` is a guest interrupt file, per `new`.
+        unsafe { seteipnum.write_volatile(self.eiid) };
+        Ok(())
+    }
+
+    fn deassert(&self) {}
+}
+
+/// Test backend recording every transition instead of touching hardware.
+#[derive(Default)]
+pub struct RecordingBackend {
+    events: Mutex<Vec<bool>>,
+}
+
+impl RecordingBackend {
+    /// Creates an empty recorder.
+    pub const fn new() -> Self {
+        Self {
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the recorded transitions, `true` for assertions.
+    pub fn events(&self) -> Vec<bool> {
+        self.events.lock().clone()
+    }
+
+    /// Returns `true` if the last transition asserted the interrupt.
+    pub fn is_asserted(&self) -> bool {
+        self.events.lock().last().copied().unwrap_or(false)
+    }
+
+    /// Forgets the recorded transitions.
+    pub fn clear(&self) {
+        self.events.lock().clear();
+    }
+}
+
+impl DeliveryBackend for RecordingBackend {
+    fn assert(&self) -> AxResult {
+        self.events.lock().push(true);
+        Ok(())
+    }
+
+    fn deassert(&self) {
+        self.events.lock().push(false);
+    }
+}
+
+/// The default backend.
+pub(crate) static HVIP_BACKEND: HvipBackend = HvipBackend;
+
+impl VPlicGlobal {
+    /// Sets the delivery mode.
+    pub fn set_delivery_mode(&self, mode: DeliveryMode) {
+        self.polling.store(mode == DeliveryMode::Polling, Ordering::Relaxed);
+    }
+
+    /// Returns the delivery mode.
+    pub fn delivery_mode(&self) -> DeliveryMode {
+        if self.polling.load(Ordering::Relaxed) {
+            DeliveryMode::Polling
+        } else {
+            DeliveryMode::Interrupt
+        }
+    }
+
+    /// Closes (`true`) or opens (`false`) the delivery gate.
+    ///
+    /// While closed, injections are buffered as pending interrupts without
+    /// notifying the guest or waking its vCPUs. Opening the gate delivers
+    /// whatever accumulated meanwhile.
+    pub fn gate_delivery(&self, gated: bool) {
+        self.delivery_gated.store(gated, Ordering::Release);
+        if !gated && !self.pending_irqs.is_empty() {
+            self.assert_vseip();
+            self.refresh_deliverability();
+        }
+    }
+
+    /// Returns `true` if the delivery gate is closed.
+    pub fn delivery_gated(&self) -> bool {
+        self.delivery_gated.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the guest must not be notified, because the
+    /// delivery gate is closed or the vPLIC is all-masked.
+    pub(crate) fn delivery_held(&self) -> bool {
+        self.delivery_gated() || self.guest_masked()
+    }
+
+    /// Sets the delivery backend.
+    pub fn set_delivery_backend(&self, backend: &'static dyn DeliveryBackend) {
+        *self.backend.lock() = backend;
+    }
+
+    /// Sets how many failed notification attempts are tolerated before the
+    /// pending interrupts are dropped.
+    pub fn set_max_delivery_retries(&self, retries: u32) {
+        self.max_delivery_retries.store(retries, Ordering::Relaxed);
+    }
+
+    /// Retries a failed notification, to be called on scheduling events.
+    ///
+    /// Returns `true` if the guest was notified.
+    pub fn retry_delivery(&self) -> bool {
+        if self.failed_deliveries.load(Ordering::Acquire) == 0 {
+            return false;
+        }
+        if self.pending_irqs.is_empty() {
+            self.failed_deliveries.store(0, Ordering::Release);
+            return false;
+        }
+        self.assert_vseip();
+        self.failed_deliveries.load(Ordering::Acquire) == 0
+    }
+
+    /// Drops the pending interrupts after delivery to `vcpus` failed for
+    /// good: those enabled in a context of `vcpus` and in no context of
+    /// another vCPU.
+    fn drop_undeliverable(&self, vcpus: &[usize]) {
+        let contexts: Vec<(usize, bool)> = (0..self.contexts_num)
+            .map(|context_id| (context_id, vcpus.contains(&self.vcpu_of_context(context_id))))
+            .collect();
+        let dropped = self
+            .pending_irqs
+            .snapshot()
+            .iter()
+            .filter(|&irq_id| {
+                let (word, bit) = (source_word(irq_id), source_bit(irq_id));
+                let mut takers = contexts
+                    .iter()
+                    .filter(|&&(context_id, _)| self.enable_word(context_id, word) & bit != 0);
+                takers.clone().next().is_some() && takers.all(|&(_, unreachable)| unreachable)
+            })
+            .filter(|&irq_id| self.try_transition(irq_id, SourceEvent::Drop))
+            .count();
+        warn!("vPlicGlobal: dropped {} undeliverable IRQs of vCPUs {:?}", dropped, vcpus);
+        self.stats.count_dropped(dropped as u64);
+        self.failed_deliveries.store(0, Ordering::Release);
+    }
+}
+
+/// Notification of the guest by a virtual interrupt controller.
+pub(crate) trait GuestNotifier {
+    /// Notifies the guest that interrupts are pending.
+    fn assert_vseip(&self);
+    /// Withdraws the pending-interrupt notification.
+    fn deassert_vseip(&self);
+}
+
+impl GuestNotifier for VPlicGlobal {
+    fn assert_vseip(&self) {
+        if self.delivery_mode() != DeliveryMode::Interrupt || self.delivery_held() {
+            return;
+        }
+        match self.assert_routed() {
+            Ok(()) => {
+                self.failed_deliveries.store(0, Ordering::Release);
+                self.arm_claim_timeouts();
+            }
+            Err((err, vcpus)) => {
+                let failures = self.failed_deliveries.fetch_add(1, Ordering::AcqRel) + 1;
+                warn!(
+                    "vPlicGlobal: delivery to vCPUs {:?} failed ({:?}), attempt {}",
+                    vcpus, err, failures
+                );
+                if failures > self.max_delivery_retries.load(Ordering::Relaxed) {
+                    self.drop_undeliverable(&vcpus);
+                }
+            }
+        }
+    }
+
+    fn deassert_vseip(&self) {
+        if self.delivery_mode() == DeliveryMode::Interrupt {
+            let backend = *self.backend.lock();
+            backend.deassert();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axerrno::{AxError, AxResult};
+
+    use super::HvictlBackend;
+    use crate::mock::testing::*;
+    use crate::HartRouter;
+
+    /// Runs vCPU N on hart N from hart 0, and fails to reach other harts.
+    struct Unreachable;
+
+    impl HartRouter for Unreachable {
+        fn current_hart(&self) -> usize {
+            0
+        }
+
+        fn running_hart(&self, vcpu_id: usize) -> Option<usize> {
+            Some(vcpu_id)
+        }
+
+        fn kick(&self, _hart_id: usize, _vcpu_id: usize) -> AxResult {
+            Err(AxError::BadState)
+        }
+    }
+
+    #[test]
+    fn undeliverable_drop_is_scoped_to_unreachable_vcpus() {
+        static ROUTER: Unreachable = Unreachable;
+        let vplic = mock_vplic(2);
+        vplic.set_hart_router(Some(&ROUTER));
+        vplic.set_max_delivery_retries(0);
+        route(&vplic, 0, 3, 1);
+        route(&vplic, 1, 4, 1);
+        route(&vplic, 0, 5, 1);
+        route(&vplic, 1, 5, 1);
+        vplic.inject_irq(3).unwrap();
+        vplic.inject_irq(5).unwrap();
+        vplic.inject_irq(4).unwrap();
+        assert!(vplic.is_irq_pending(3));
+        assert!(vplic.is_irq_pending(5));
+        assert!(!vplic.is_irq_pending(4));
+    }
+
+    #[test]
+    fn hvictl_goes_through_the_hal() {
+        static BACKEND: HvictlBackend = HvictlBackend { iprio: 4 };
+        let vplic = mock_vplic(1);
+        let hal = mock_hal();
+        vplic.set_delivery_backend(&BACKEND);
+        route(&vplic, 0, 3, 1);
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(hal.hvictl(), 9 << 16 | 4);
+        assert!(hal.vseip());
+        assert_eq!(claim(&vplic, 0), 3);
+        complete(&vplic, 0, 3);
+        assert_eq!(hal.hvictl(), 0);
+        assert!(!hal.vseip());
+    }
+}
@@ -0,0 +1,42 @@
+//! Pure device-model mode for hosts without the H extension.
+//!
+//! Emulators and simulators can use the vPLIC without running under the
+//! hypervisor extension: the register state is emulated in software (see
+//! [`VPlicGlobal::new_emulated`]) and the guest is notified through a
+//! delivery backend provided by the caller, so no hypervisor CSR is ever
+//! accessed.
+
+use axaddrspace::GuestPhysAddr;
+
+use crate::{DeliveryBackend, VPlicGlobal};
+
+/// Returns `true` if the RISC-V ISA string `isa` (e.g. the `riscv,isa`
+/// property of a CPU node, `"rv64imafdch_zicsr"`) includes the H extension.
+///
+/// Always `false` when not built for RISC-V, where no hypervisor CSR exists.
+pub fn h_extension_present(isa: &str) -> bool {
+    if !cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        return false;
+    }
+    let isa = isa.trim().to_ascii_lowercase();
+    let base = match isa.strip_prefix("rv64").or_else(|| isa.strip_prefix("rv32")) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    base.split('_').next().unwrap_or("").contains('h')
+}
+
+impl VPlicGlobal {
+    /// Creates a vPLIC in pure device-model mode: all register state lives in
+    /// software and the guest is notified through `backend`.
+    pub fn new_device_model(
+        addr: GuestPhysAddr,
+        size: Option<usize>,
+        contexts_num: usize,
+        backend: &'static dyn DeliveryBackend,
+    ) -> Self {
+        let vplic = Self::new_emulated(addr, size, contexts_num);
+        vplic.set_delivery_backend(backend);
+        vplic
+    }
+}
@@ -0,0 +1,136 @@
+//! Direct delivery of passthrough interrupts through guest external
+//! interrupt lines.
+//!
+//! Normally a passthrough interrupt traps into the hypervisor, which claims
+//! it on the host and injects it, and hvip.VSEIP is raised in software. On
+//! hosts that wire host PLIC contexts to guest external interrupt lines
+//! (hgeip), an exclusive passthrough source can instead be routed to the
+//! line of the vCPU's guest file: with hstatus.VGEIN selecting that line and
+//! its hgeie bit set, the hardware raises the guest interrupt by itself, and
+//! the host never traps. When the guest then claims, the vPLIC claims the
+//! direct sources pending on the host context backing the claiming context,
+//! which withdraws them from hgeip, and makes them pending in its own state,
+//! so the claim path stays the same. Completions of direct sources are only
+//! forwarded to the host when they were claimed there this way.
+
+use axerrno::{AxError, AxResult};
+use log::warn;
+
+use crate::hal::hal;
+use crate::host::HostBackend;
+use crate::{InjectionSource, VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Sets the guest external interrupt line of `vcpu_id`'s guest file, or
+    /// `None` to stop delivering its interrupts directly.
+    ///
+    /// Fails with [`AxError::Unsupported`] unless the vCPU runs on dedicated
+    /// harts (see [`HartMode`](crate::HartMode)).
+    pub fn set_direct_line(&self, vcpu_id: usize, line: Option<usize>) -> AxResult {
+        // Line 0 does not exist: VGEIN 0 selects no line.
+        if line.is_some_and(|line| line == 0 || line >= usize::BITS as usize) {
+            return Err(AxError::InvalidInput);
+        }
+        if line.is_some() {
+            self.require_dedicated(vcpu_id, "direct delivery")?;
+        }
+        let mut lines = self.direct_lines.lock();
+        let slot = lines.get_mut(vcpu_id).ok_or(AxError::InvalidInput)?;
+        *slot = line;
+        Ok(())
+    }
+
+    /// Delivers `irq_id` directly (`true`) or through the hypervisor.
+    ///
+    /// Only exclusive passthrough sources qualify: mapped to a host source
+    /// assigned to the VM, owned by the guest and not forwarded through
+    /// [`VPlicGlobal::bind_host_irq`].
+    pub fn set_direct_irq(&self, irq_id: usize, direct: bool) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if direct {
+            let host_irq = self.host_irq(irq_id);
+            let assigned = self.assigned_irqs.lock().get(host_irq);
+            if host_irq == 0 || !assigned || !self.guest_owns(irq_id) {
+                return Err(AxError::InvalidInput);
+            }
+            if self.bound_irqs.lock().get(host_irq) {
+                return Err(AxError::AlreadyExists);
+            }
+        }
+        self.direct_irqs.lock().set(irq_id, direct);
+        Ok(())
+    }
+
+    /// Returns `true` if `irq_id` is delivered directly.
+    pub fn is_direct_irq(&self, irq_id: usize) -> bool {
+        irq_id < PLIC_NUM_SOURCES && self.direct_irqs.lock().get(irq_id)
+    }
+
+    /// Programs the current hart for `vcpu_id`, which is being loaded:
+    /// selects its guest external interrupt line in hstatus.VGEIN and
+    /// enables it in hgeie, or disables direct delivery if it has none.
+    pub fn load_direct(&self, vcpu_id: usize) -> AxResult {
+        match self.direct_lines.lock().get(vcpu_id).copied().flatten() {
+            Some(line) => {
+                self.require_dedicated(vcpu_id, "direct delivery")?;
+                hal().set_vgein(line)?;
+                hal().set_hgeie(1 << line)
+            }
+            None => {
+                hal().set_vgein(0)?;
+                hal().set_hgeie(0)
+            }
+        }
+    }
+
+    /// Claims the direct sources pending on the host context backing
+    /// `context_id` and makes them pending in the vPLIC, if a guest external
+    /// interrupt line is raised. Called before claims.
+    ///
+    /// Each host claim is recorded, so that the guest completion of the
+    /// source completes it on the host. A guest source claimed on the host
+    /// that is not delivered directly is made pending all the same, and its
+    /// guest completion completes it on the host like any emulated source;
+    /// only a host source the guest does not own is completed back right
+    /// away.
+    pub(crate) fn sync_direct(&self, context_id: usize) {
+        if self.is_emulated() || self.direct_irqs.lock().is_empty() || hal().hgeip() == 0 {
+            return;
+        }
+        let offset = self.host_claim_offset(context_id);
+        loop {
+            // Claimed sources are not returned again until completed, so
+            // this ends once the host context has no direct source pending.
+            let host_irq = match self.host_read(offset) {
+                Ok(0) | Err(_) => return,
+                Ok(host_irq) => host_irq as usize,
+            };
+            match self.guest_irq(host_irq) {
+                Some(irq_id) if self.is_direct_irq(irq_id) => {
+                    self.direct_claims.lock().set(irq_id, true);
+                    self.deliver(irq_id, InjectionSource::HostHardware);
+                }
+                Some(irq_id) => {
+                    // An edge would be lost if completed back unhandled.
+                    warn!("vPlicGlobal: host source {host_irq} on a direct line is not direct");
+                    self.deliver(irq_id, InjectionSource::HostHardware);
+                }
+                None => {
+                    warn!("vPlicGlobal: host source {host_irq} on a direct line is not the guest's");
+                    let _ = self.host_write(offset, host_irq as u32);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Consumes the host claim recorded for `irq_id` by direct delivery.
+    /// Returns `false` if `irq_id` is delivered directly but was not claimed
+    /// on the host, so its completion must not be forwarded.
+    pub(crate) fn take_direct_claim(&self, irq_id: usize) -> bool {
+        let claimed = self.direct_claims.lock().set(irq_id, false);
+        claimed || !self.is_direct_irq(irq_id)
+    }
+}
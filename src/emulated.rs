@@ -0,0 +1,31 @@
+//! Fully emulated mode, without host PLIC backing.
+//!
+//! For guests without passthrough devices on hosts where the physical PLIC
+//! is owned elsewhere, the host registers the vPLIC would forward to are
+//! replaced by a software register file. Every layer above the host backend
+//! works unchanged; nothing ever touches the physical PLIC.
+
+use alloc::collections::BTreeMap;
+
+use axaddrspace::GuestPhysAddr;
+
+use crate::lock::Mutex;
+use crate::VPlicGlobal;
+
+/// Software register file standing in for the host PLIC, by offset. Offsets
+/// never written read as zero.
+pub(crate) type EmulatedRegs = BTreeMap<usize, u32>;
+
+impl VPlicGlobal {
+    /// Creates a vPLIC whose register state lives entirely in software.
+    pub fn new_emulated(addr: GuestPhysAddr, size: Option<usize>, contexts_num: usize) -> Self {
+        let mut vplic = Self::new(addr, size, contexts_num);
+        vplic.emulated_regs = Some(Mutex::new(EmulatedRegs::new()));
+        vplic
+    }
+
+    /// Returns `true` if the vPLIC has no host PLIC backing.
+    pub fn is_emulated(&self) -> bool {
+        self.emulated_regs.is_some()
+    }
+}
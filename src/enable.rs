@@ -0,0 +1,163 @@
+//! Shadowed enable words.
+//!
+//! Every context keeps a shadow copy of its enable words, as written by the
+//! guest. The value forwarded to the host is filtered (only sources assigned
+//! to the VM are ever enabled, and never those owned by the hypervisor),
+//! translated to host source numbers and lands in the host context backing
+//! the guest one, leaving the bits of sources not assigned to the VM as they
+//! are.
+//! Updates of the shadow and of the host registers happen under one lock, so
+//! hypervisor-side read-modify-writes (affinity changes, masking) compose
+//! safely with concurrent guest enable writes.
+
+use axerrno::{AxError, AxResult};
+
+use crate::banks::BankIter;
+use crate::host::HostBackend;
+use crate::{source_bit, source_word, word_source, BankedBitmap, VPlicGlobal, PLIC_NUM_BANKS};
+
+impl VPlicGlobal {
+    /// Atomically applies `f` to enable word `word_index` of `context_id`,
+    /// updating both the shadow and the host register.
+    ///
+    /// Returns the new value of the word.
+    pub fn modify_enable<F>(&self, context_id: usize, word_index: usize, f: F) -> AxResult<u32>
+    where
+        F: FnOnce(u32) -> u32,
+    {
+        if context_id >= self.contexts_num || word_index >= PLIC_NUM_BANKS {
+            return Err(AxError::InvalidInput);
+        }
+        let host_words = self.host_words_of(word_index);
+        let host_offset = self.host_enable_offset(context_id, 0);
+        let mut contexts = self.contexts.lock();
+        let enables = &mut contexts[context_id].enables;
+        let val = f(enables.word(word_index));
+        enables.set_word(word_index, val);
+        for host_word in BankIter::new(host_words) {
+            let offset = host_offset + host_word * 4;
+            let others = self.host_read(offset)? & !self.assigned_word(host_word);
+            self.host_write(offset, others | self.host_enable_word(enables, host_word))?;
+        }
+        drop(contexts);
+        self.publish_hint();
+        Ok(val)
+    }
+
+    /// Returns the host enable words backing the sources of guest enable
+    /// word `word_index`, one bit per host word.
+    fn host_words_of(&self, word_index: usize) -> u32 {
+        BankIter::new(u32::MAX)
+            .map(|bit| self.host_irq(word_source(word_index, bit)))
+            .filter(|&host_irq| host_irq != 0)
+            .fold(0, |words, host_irq| words | 1 << source_word(host_irq))
+    }
+
+    /// Returns the sources of host enable word `host_word` assigned to the VM.
+    pub(crate) fn assigned_word(&self, host_word: usize) -> u32 {
+        let assigned_irqs = self.assigned_irqs.lock();
+        BankIter::new(u32::MAX)
+            .filter(|&bit| assigned_irqs.get(word_source(host_word, bit)))
+            .fold(0u32, |word, bit| word | 1 << bit)
+    }
+
+    /// Computes the host enable word `host_word` from the guest `shadow`,
+    /// keeping only the sources assigned to the VM and leaving out the ones
+    /// owned by the hypervisor or masked while their vCPU is descheduled,
+    /// and all of them while the host enables are masked.
+    pub(crate) fn host_enable_word(&self, shadow: &BankedBitmap, host_word: usize) -> u32 {
+        if self.host_enables_masked() {
+            return 0;
+        }
+        let hypervisor_irqs = self.hypervisor_irqs.lock();
+        BankIter::new(self.assigned_word(host_word))
+            .filter(|&bit| match self.guest_irq(word_source(host_word, bit)) {
+                Some(irq_id) => {
+                    shadow.get(irq_id) && !hypervisor_irqs.get(irq_id) && !self.preempt_masked(irq_id)
+                }
+                None => false,
+            })
+            .fold(0u32, |word, bit| word | 1 << bit)
+    }
+
+    /// Reprograms the host enable words holding `irq_id` for every context,
+    /// e.g. after a change of the source owner.
+    pub(crate) fn resync_host_enables(&self, irq_id: usize) -> AxResult {
+        for context_id in 0..self.contexts_num {
+            self.modify_enable(context_id, source_word(irq_id), |word| word)?;
+        }
+        Ok(())
+    }
+
+    /// Reports the passthrough sources of enable word `word_index` the guest
+    /// flipped from `old` to `new` on `context_id` to the hooks.
+    pub(crate) fn notify_enable_changes(&self, context_id: usize, word_index: usize, old: u32, new: u32) {
+        if self.hooks().is_none() {
+            return;
+        }
+        let flipped = {
+            let assigned_irqs = self.assigned_irqs.lock();
+            BankIter::new(old ^ new)
+                .filter(|&bit| assigned_irqs.get(self.host_irq(word_source(word_index, bit))))
+                .fold(0u32, |mask, bit| mask | 1 << bit)
+        };
+        self.call_hooks(|hooks| {
+            for bit in BankIter::new(flipped) {
+                hooks.enable_changed(context_id, word_source(word_index, bit), new & 1 << bit != 0);
+            }
+        });
+    }
+
+    /// Sets the bit of `irq_id` in the enable words of `context_id`.
+    pub fn set_enable(&self, context_id: usize, irq_id: usize, enable: bool) -> AxResult {
+        let bit = source_bit(irq_id);
+        self.modify_enable(context_id, source_word(irq_id), |word| {
+            if enable {
+                word | bit
+            } else {
+                word & !bit
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Returns the shadow enable word `word_index` of `context_id`, 0 if
+    /// either does not exist.
+    pub fn enable_word(&self, context_id: usize, word_index: usize) -> u32 {
+        if word_index >= PLIC_NUM_BANKS {
+            return 0;
+        }
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or(0, |context| context.enables.word(word_index))
+    }
+
+    /// Returns the shadow enable words of `context_id`, all clear if it does
+    /// not exist.
+    pub fn enables(&self, context_id: usize) -> BankedBitmap {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or_else(BankedBitmap::new, |context| context.enables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::HostBackend;
+    use crate::mock::testing::*;
+    use crate::SourceInfo;
+
+    #[test]
+    fn unassigned_host_sources_are_never_enabled() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(3, SourceInfo::default()).unwrap();
+        write(&vplic, enable_reg(0, 0), 1 << 3 | 1 << 4);
+        assert_eq!(vplic.host_read(vplic.host_enable_offset(0, 0)).unwrap(), 1 << 3);
+        vplic.set_enable(0, 40, true).unwrap();
+        assert_eq!(vplic.host_read(vplic.host_enable_offset(0, 1)).unwrap(), 0);
+        write(&vplic, enable_reg(0, 0), u32::MAX as usize);
+        assert_eq!(vplic.host_read(vplic.host_enable_offset(0, 0)).unwrap(), 1 << 3);
+    }
+}
@@ -0,0 +1,65 @@
+//! Classification of vPLIC failures.
+//!
+//! Failures fall in three classes the dispatcher handles differently: the
+//! guest did something illegal (inject a fault into the guest), the VMM
+//! configured the vPLIC inconsistently (a hypervisor bug to report), or the
+//! host hardware failed. The guest access handlers map every failure onto
+//! one [`AxError`] per class, so [`VPlicError::classify`] recovers the class
+//! from the error the dispatcher receives.
+
+use axerrno::AxError;
+
+/// Class of a vPLIC failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VPlicError {
+    /// The guest accessed the device illegally (bad width, bad value,
+    /// completion of an IRQ it did not claim). Reported as
+    /// [`AxError::InvalidInput`].
+    GuestFault,
+    /// The vPLIC is not configured for the access (missing backend, HAL or
+    /// mapping). Reported as [`AxError::Unsupported`].
+    Config,
+    /// The host interrupt controller failed. Reported as
+    /// [`AxError::Io`].
+    Host,
+}
+
+impl VPlicError {
+    /// Returns the class of an error returned by the guest access
+    /// handlers, or `None` for errors outside of the classes (e.g.
+    /// [`AxError::WouldBlock`] while the device is paused).
+    pub fn classify(err: AxError) -> Option<Self> {
+        match err {
+            AxError::InvalidInput => Some(Self::GuestFault),
+            AxError::Unsupported => Some(Self::Config),
+            AxError::Io => Some(Self::Host),
+            _ => None,
+        }
+    }
+
+    /// Classifies an error raised while handling a guest access.
+    pub(crate) fn of_guest_access(err: AxError) -> Option<Self> {
+        match err {
+            AxError::InvalidInput | AxError::BadState => Some(Self::GuestFault),
+            AxError::Unsupported | AxError::NotFound | AxError::AlreadyExists => Some(Self::Config),
+            AxError::WouldBlock => None,
+            _ => Some(Self::Host),
+        }
+    }
+}
+
+impl From<VPlicError> for AxError {
+    fn from(err: VPlicError) -> Self {
+        match err {
+            VPlicError::GuestFault => AxError::InvalidInput,
+            VPlicError::Config => AxError::Unsupported,
+            VPlicError::Host => AxError::Io,
+        }
+    }
+}
+
+/// Maps an error raised while handling a guest access onto the error of
+/// its class.
+pub(crate) fn guest_access_error(err: AxError) -> AxError {
+    VPlicError::of_guest_access(err).map_or(err, AxError::from)
+}
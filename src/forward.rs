@@ -0,0 +1,80 @@
+//! Automatic forwarding of host interrupts.
+//!
+//! Instead of the hypervisor injecting every passthrough interrupt by hand,
+//! a host source can be bound to the vPLIC: the binding registers a handler
+//! with the host interrupt subsystem through a [`HostIrqRegistry`], and when
+//! the physical interrupt fires the handler calls
+//! [`VPlicGlobal::handle_host_irq`], which makes the guest source pending
+//! and notifies the guest.
+
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// The host interrupt subsystem, as seen by the vPLIC.
+pub trait HostIrqRegistry: Send + Sync {
+    /// Installs a handler for `host_irq` calling
+    /// [`VPlicGlobal::handle_host_irq`] on `vplic`.
+    fn register(&self, host_irq: usize, vplic: &'static VPlicGlobal) -> AxResult;
+    /// Removes the handler of `host_irq`.
+    fn unregister(&self, host_irq: usize);
+}
+
+impl VPlicGlobal {
+    /// Sets the host interrupt subsystem used by [`VPlicGlobal::bind_host_irq`].
+    pub fn set_host_irq_registry(&self, registry: &'static dyn HostIrqRegistry) {
+        *self.irq_registry.lock() = Some(registry);
+    }
+
+    /// Forwards host source `host_irq` to the guest automatically.
+    ///
+    /// The source must be assigned to the VM and mapped to a guest source.
+    /// Fails with [`AxError::Unsupported`] if no registry was set.
+    pub fn bind_host_irq(&'static self, host_irq: usize) -> AxResult {
+        if host_irq == 0 || host_irq >= PLIC_NUM_SOURCES || !self.assigned_irqs.lock().get(host_irq) {
+            return Err(AxError::InvalidInput);
+        }
+        match self.guest_irq(host_irq) {
+            None => return Err(AxError::NotFound),
+            // Delivered by the hardware, never through the hypervisor.
+            Some(irq_id) if self.is_direct_irq(irq_id) => return Err(AxError::AlreadyExists),
+            Some(_) => {}
+        }
+        let registry = (*self.irq_registry.lock()).ok_or(AxError::Unsupported)?;
+        if self.bound_irqs.lock().get(host_irq) {
+            return Err(AxError::AlreadyExists);
+        }
+        registry.register(host_irq, self)?;
+        self.bound_irqs.lock().set(host_irq, true);
+        Ok(())
+    }
+
+    /// Stops forwarding host source `host_irq`.
+    pub fn unbind_host_irq(&self, host_irq: usize) -> AxResult {
+        if host_irq >= PLIC_NUM_SOURCES || !self.bound_irqs.lock().set(host_irq, false) {
+            return Err(AxError::NotFound);
+        }
+        if let Some(registry) = *self.irq_registry.lock() {
+            registry.unregister(host_irq);
+        }
+        Ok(())
+    }
+
+    /// Handles a firing of the bound host source `host_irq`, claimed by the
+    /// host interrupt subsystem: makes its guest source pending and notifies
+    /// the guest, whose completion completes it on the host.
+    ///
+    /// Returns `false` if the source is not bound to this vPLIC.
+    pub fn handle_host_irq(&self, host_irq: usize) -> bool {
+        if host_irq >= PLIC_NUM_SOURCES || !self.bound_irqs.lock().get(host_irq) {
+            return false;
+        }
+        match self.guest_irq(host_irq) {
+            Some(irq_id) => {
+                self.inject(irq_id, InjectionSource::HostHardware);
+                true
+            }
+            None => false,
+        }
+    }
+}
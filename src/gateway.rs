@@ -0,0 +1,215 @@
+//! Interrupt gateways.
+//!
+//! As on the PLIC, a gateway sits between each source and its pending bit.
+//! For a level-triggered source the gateway closes once the source is
+//! forwarded: a request arriving while it is claimed does not make it
+//! pending again mid-handling, and the gateway re-evaluates the line when
+//! the guest completes it, making the source pending again if the line is
+//! still asserted.
+//!
+//! For an edge-triggered source the gateway latches the edges arriving
+//! while the source is pending or claimed instead of losing them, and
+//! forwards one of them after each completion.
+//!
+//! Sources are level-triggered unless configured otherwise. Emulated level
+//! devices drive their line with [`VPlicGlobal::set_irq_level`], and the
+//! line level alone decides whether the source is pending again after a
+//! completion. Injections into a claimed level source whose line is not
+//! driven count as a line held asserted until the completion.
+
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, Trigger, VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Sets the trigger type of `irq_id`.
+    pub fn set_source_trigger(&self, irq_id: usize, trigger: Trigger) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.edge_irqs.lock().set(irq_id, trigger == Trigger::Edge);
+        match trigger {
+            Trigger::Edge => {
+                self.asserted_lines.lock().set(irq_id, false);
+                self.held_irqs.lock().set(irq_id, false);
+            }
+            Trigger::Level => self.latched_edges.lock()[irq_id] = 0,
+        }
+        Ok(())
+    }
+
+    /// Returns the trigger type of `irq_id`.
+    pub fn source_trigger(&self, irq_id: usize) -> Trigger {
+        if irq_id < PLIC_NUM_SOURCES && self.edge_irqs.lock().get(irq_id) {
+            Trigger::Edge
+        } else {
+            Trigger::Level
+        }
+    }
+
+    /// Drives the line of the level-triggered source `irq_id` from the
+    /// emulated device `device_id`.
+    ///
+    /// Asserting the line makes the source pending if its gateway is open;
+    /// otherwise the source becomes pending again once the guest completes
+    /// it, unless the line was deasserted meanwhile.
+    pub fn set_irq_level(&self, irq_id: usize, asserted: bool, device_id: u32) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES || self.source_trigger(irq_id) != Trigger::Level {
+            return Err(AxError::InvalidInput);
+        }
+        self.asserted_lines.lock().set(irq_id, asserted);
+        if asserted {
+            self.inject(irq_id, InjectionSource::Device(device_id));
+        } else {
+            // The request held by a closed gateway goes away with the line.
+            self.held_irqs.lock().set(irq_id, false);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of edges of `irq_id` latched by its gateway and
+    /// not forwarded yet.
+    pub fn latched_edges(&self, irq_id: usize) -> u32 {
+        self.latched_edges.lock().get(irq_id).copied().unwrap_or(0)
+    }
+
+    /// Passes a request of `irq_id` through its gateway.
+    ///
+    /// Returns `false` if the gateway is closed and the request is held.
+    ///
+    /// The state is tested under the lock [`VPlicGlobal::gateway_reopen`]
+    /// takes after clearing the active bit, so a concurrent completion
+    /// either finds the held request or reopened before the test.
+    pub(crate) fn gateway_accepts(&self, irq_id: usize) -> bool {
+        match self.source_trigger(irq_id) {
+            Trigger::Level => {
+                let mut held = self.held_irqs.lock();
+                if !self.active_irqs.get(irq_id) {
+                    return true;
+                }
+                // A driven line is re-evaluated on completion by its level.
+                if !self.asserted_lines.lock().get(irq_id) {
+                    held.set(irq_id, true);
+                }
+                false
+            }
+            Trigger::Edge => {
+                let mut latched = self.latched_edges.lock();
+                if !self.active_irqs.get(irq_id) && !self.pending_irqs.get(irq_id) {
+                    return true;
+                }
+                latched[irq_id] = latched[irq_id].saturating_add(1);
+                false
+            }
+        }
+    }
+
+    /// Runs `f` under the lock [`VPlicGlobal::gateway_accepts`] tests the
+    /// state of `irq_id` under.
+    pub(crate) fn with_gateway_locked<R>(&self, irq_id: usize, f: impl FnOnce() -> R) -> R {
+        match self.source_trigger(irq_id) {
+            Trigger::Level => {
+                let _held = self.held_irqs.lock();
+                f()
+            }
+            Trigger::Edge => {
+                let _latched = self.latched_edges.lock();
+                f()
+            }
+        }
+    }
+
+    /// Reopens the gateway of `irq_id` after its completion, making it
+    /// pending again if its line is still asserted or an edge was latched.
+    pub(crate) fn gateway_reopen(&self, irq_id: usize) {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return;
+        }
+        let repend = match self.source_trigger(irq_id) {
+            Trigger::Level => {
+                let held = self.held_irqs.lock().set(irq_id, false);
+                held || self.asserted_lines.lock().get(irq_id)
+            }
+            Trigger::Edge => {
+                let mut latched = self.latched_edges.lock();
+                let repend = latched[irq_id] != 0;
+                latched[irq_id] = latched[irq_id].saturating_sub(1);
+                repend
+            }
+        };
+        if repend {
+            let source = self.injection_source(irq_id).unwrap_or(InjectionSource::Monitor);
+            self.deliver(irq_id, source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+    use crate::{InjectionSource, SourceState};
+
+    #[test]
+    fn deasserted_line_is_not_pending_again() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 9, 1);
+        vplic.set_irq_level(9, true, 7).unwrap();
+        assert_eq!(claim(&vplic, 0), 9);
+        vplic.set_irq_level(9, true, 7).unwrap();
+        vplic.set_irq_level(9, false, 7).unwrap();
+        complete(&vplic, 0, 9);
+        assert_eq!(vplic.source_state(9), SourceState::Inactive);
+    }
+
+    #[test]
+    fn asserted_line_is_pending_again() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 9, 1);
+        vplic.set_irq_level(9, true, 7).unwrap();
+        assert_eq!(claim(&vplic, 0), 9);
+        complete(&vplic, 0, 9);
+        assert_eq!(vplic.source_state(9), SourceState::Pending);
+        assert_eq!(claim(&vplic, 0), 9);
+    }
+
+    #[test]
+    fn driven_line_is_injected_by_its_device() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 9, 1);
+        vplic.set_irq_level(9, true, 7).unwrap();
+        assert_eq!(vplic.injection_source(9), Some(InjectionSource::Device(7)));
+    }
+
+    #[test]
+    fn injection_into_claimed_source_is_held() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 9, 1);
+        vplic.inject_irq(9).unwrap();
+        assert_eq!(claim(&vplic, 0), 9);
+        vplic.inject_irq(9).unwrap();
+        assert_eq!(vplic.source_state(9), SourceState::Active);
+        complete(&vplic, 0, 9);
+        assert_eq!(vplic.source_state(9), SourceState::Pending);
+    }
+
+    #[test]
+    fn racing_injection_never_pends_a_claimed_source() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 9, 1);
+        vplic.inject_irq(9).unwrap();
+        std::thread::scope(|scope| {
+            let vplic = &vplic;
+            let injector = scope.spawn(move || {
+                for _ in 0..10_000 {
+                    vplic.inject_irq(9).unwrap();
+                }
+            });
+            while !injector.is_finished() {
+                if claim(vplic, 0) == 9 {
+                    assert_eq!(vplic.source_state(9), SourceState::Active);
+                    complete(vplic, 0, 9);
+                }
+            }
+        });
+    }
+}
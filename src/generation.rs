@@ -0,0 +1,118 @@
+//! Generation-counted reconfiguration.
+//!
+//! The configuration (remap table, source assignments, context map) is
+//! spread over several locks, so a claim, completion or injection running
+//! while the VMM changes it could observe half of an update. The VMM
+//! therefore batches changes in [`VPlicGlobal::reconfigure`]: the batch only
+//! starts once no operation is in flight, the configuration generation is
+//! odd while it runs, and new operations wait for it to finish. Every
+//! operation thus runs entirely against either the old or the new
+//! configuration. Operations nested in another one (e.g. an injection from a
+//! claim hook) never wait, since no batch can start under them.
+//!
+//! Operations covered: guest claims and completions (including the
+//! paravirtual batched ones) and injections.
+//!
+//! For longer windows, [`VPlicGlobal::reconfigure_masked`] additionally
+//! makes the whole vPLIC look masked to the guest while the batch runs:
+//! claims return 0 and the guest interrupt is held low, and both are
+//! restored together once the new configuration is in place.
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::delivery::GuestNotifier;
+use crate::VPlicGlobal;
+
+/// Marks an operation running against one configuration generation.
+pub(crate) struct ConfigGuard<'a>(&'a AtomicUsize);
+
+impl Drop for ConfigGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl VPlicGlobal {
+    /// Runs `f` as one configuration change.
+    ///
+    /// Waits until no claim, completion or injection is in flight, holds new
+    /// ones back while `f` runs and bumps the configuration generation. `f`
+    /// must not claim, complete or inject, nor call `reconfigure` again.
+    pub fn reconfigure<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        let _ = self.forbid_in_hook("reconfigure");
+        let generation = loop {
+            let generation = self.config_generation.load(Ordering::SeqCst);
+            if generation % 2 != 0 || self.config_users.load(Ordering::SeqCst) != 0 {
+                spin_loop();
+                continue;
+            }
+            if self
+                .config_generation
+                .compare_exchange(generation, generation + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                continue;
+            }
+            // An operation may have entered between the check and the
+            // update: back off and let it finish.
+            if self.config_users.load(Ordering::SeqCst) == 0 {
+                break generation;
+            }
+            self.config_generation.store(generation, Ordering::SeqCst);
+        };
+        let ret = f(self);
+        self.config_generation.store(generation + 2, Ordering::SeqCst);
+        ret
+    }
+
+    /// Runs `f` as one configuration change, with the vPLIC all-masked to
+    /// the guest meanwhile.
+    ///
+    /// Waits for the claims in progress, withdraws the guest interrupt and
+    /// makes claims return 0 until `f` finished, then notifies the guest of
+    /// whatever is pending under the new configuration. The constraints of
+    /// [`VPlicGlobal::reconfigure`] apply to `f`.
+    pub fn reconfigure_masked<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        let _ = self.forbid_in_hook("reconfigure_masked");
+        self.guest_masked.store(true, Ordering::SeqCst);
+        while self.claims_in_progress.load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+        self.deassert_vseip();
+        let ret = self.reconfigure(f);
+        self.guest_masked.store(false, Ordering::SeqCst);
+        if !self.pending_irqs.is_empty() {
+            self.assert_vseip();
+        }
+        self.refresh_deliverability();
+        ret
+    }
+
+    /// Returns `true` while the vPLIC looks all-masked to the guest.
+    pub fn guest_masked(&self) -> bool {
+        self.guest_masked.load(Ordering::SeqCst)
+    }
+
+    /// Returns the configuration generation, odd while a reconfiguration is
+    /// in progress.
+    pub fn config_generation(&self) -> u64 {
+        self.config_generation.load(Ordering::SeqCst)
+    }
+
+    /// Enters an operation that must see a stable configuration, waiting for
+    /// a reconfiguration in progress.
+    pub(crate) fn enter_config(&self) -> ConfigGuard<'_> {
+        loop {
+            self.config_users.fetch_add(1, Ordering::SeqCst);
+            let guard = ConfigGuard(&self.config_users);
+            if self.config_generation() % 2 == 0 {
+                return guard;
+            }
+            drop(guard);
+            while self.config_generation() % 2 != 0 {
+                spin_loop();
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Several vPLIC instances in one VM.
+//!
+//! SoCs with more than one PLIC get one [`VPlicGlobal`] per PLIC, each with
+//! its own guest region, host PLIC and source namespace. They still share a
+//! single VS-level external interrupt per hart, so a [`VPlicGroup`] hands
+//! each member a delivery backend of its own and only withdraws the
+//! interrupt once no member has anything pending.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use axaddrspace::GuestPhysAddr;
+use axerrno::{AxError, AxResult};
+
+use crate::lock::Mutex;
+use crate::{DeliveryBackend, VPlicGlobal};
+
+/// Maximum number of vPLIC instances in a group.
+pub const MAX_GROUP_INSTANCES: usize = 64;
+
+/// The vPLIC instances of a VM, sharing one guest external interrupt.
+pub struct VPlicGroup {
+    /// Backend actually notifying the guest.
+    inner: &'static dyn DeliveryBackend,
+    /// Members of the group, indexed by their line.
+    instances: Mutex<Vec<&'static VPlicGlobal>>,
+    /// Members currently asserting the interrupt, one bit per line.
+    asserted: Mutex<u64>,
+}
+
+/// Delivery backend of one member of a [`VPlicGroup`].
+struct GroupLine {
+    group: &'static VPlicGroup,
+    index: usize,
+}
+
+impl DeliveryBackend for GroupLine {
+    fn assert(&self) -> AxResult {
+        let mut asserted = self.group.asserted.lock();
+        self.group.inner.assert()?;
+        *asserted |= 1 << self.index;
+        Ok(())
+    }
+
+    fn deassert(&self) {
+        let mut asserted = self.group.asserted.lock();
+        *asserted &= !(1 << self.index);
+        if *asserted == 0 {
+            self.group.inner.deassert();
+        }
+    }
+}
+
+impl VPlicGroup {
+    /// Creates an empty group notifying the guest through `inner`.
+    pub const fn new(inner: &'static dyn DeliveryBackend) -> Self {
+        Self {
+            inner,
+            instances: Mutex::new(Vec::new()),
+            asserted: Mutex::new(0),
+        }
+    }
+
+    /// Adds `vplic` to the group and routes its delivery through the group.
+    ///
+    /// Returns the index of the new member. Fails with
+    /// [`AxError::AlreadyExists`] if its guest region overlaps another
+    /// member's or it is backed by the same host PLIC, and with
+    /// [`AxError::NoMemory`] once the group is full.
+    pub fn add(&'static self, vplic: &'static VPlicGlobal) -> AxResult<usize> {
+        let mut instances = self.instances.lock();
+        if instances.len() >= MAX_GROUP_INSTANCES {
+            return Err(AxError::NoMemory);
+        }
+        let start = vplic.addr.as_usize();
+        let end = start + vplic.size;
+        let clash = instances.iter().any(|other| {
+            let other_start = other.addr.as_usize();
+            let other_end = other_start + other.size;
+            (start < other_end && other_start < end) || other.host_plic_addr == vplic.host_plic_addr
+        });
+        if clash {
+            return Err(AxError::AlreadyExists);
+        }
+        let index = instances.len();
+        // Members live as long as the VM; the line is never freed.
+        let line = Box::leak(Box::new(GroupLine { group: self, index }));
+        vplic.set_delivery_backend(line);
+        instances.push(vplic);
+        Ok(index)
+    }
+
+    /// Returns the members of the group.
+    pub fn instances(&self) -> Vec<&'static VPlicGlobal> {
+        self.instances.lock().clone()
+    }
+
+    /// Returns the member whose guest region holds `addr`.
+    pub fn instance_at(&self, addr: GuestPhysAddr) -> Option<&'static VPlicGlobal> {
+        let addr = addr.as_usize();
+        self.instances
+            .lock()
+            .iter()
+            .find(|vplic| (vplic.addr.as_usize()..vplic.addr.as_usize() + vplic.size).contains(&addr))
+            .copied()
+    }
+
+    /// Returns `true` if any member has an interrupt `context_id` can take.
+    pub fn should_wake(&self, context_id: usize) -> bool {
+        self.instances.lock().iter().any(|vplic| vplic.should_wake(context_id))
+    }
+}
@@ -0,0 +1,277 @@
+//! Hardware abstraction layer.
+//!
+//! Everything the vPLIC needs from the host platform goes through
+//! [`VPlicHal`], so the emulation logic can be reused by other hypervisors
+//! and host architectures, and run off-target. With the `axvisor` feature
+//! (on by default), [`AxvisorHal`] provides the axvisor_api/riscv_h
+//! behavior and is used unless another HAL is installed with [`set_hal`]
+//! before the first register access. Enabling the `mock` feature does not
+//! change this: the mock HAL is only used once installed with [`set_hal`].
+//! Without the `axvisor` feature, and in unit tests, no HAL is installed
+//! implicitly, so the first [`set_hal`] call always takes effect.
+
+use axaddrspace::{HostPhysAddr, HostVirtAddr};
+use axerrno::{AxError, AxResult};
+use spin::Once;
+
+/// Host platform services used by the vPLIC.
+pub trait VPlicHal: Send + Sync {
+    /// Translates a host physical address to a host virtual address the
+    /// vPLIC can access.
+    fn phys_to_virt(&self, paddr: HostPhysAddr) -> HostVirtAddr;
+    /// Asserts the VS-level external interrupt on the current hart.
+    fn set_vseip(&self);
+    /// Withdraws the VS-level external interrupt on the current hart.
+    fn clear_vseip(&self);
+    /// Returns the current host time in nanoseconds.
+    fn now_ns(&self) -> u64;
+    /// Sends an inter-processor interrupt to `hart_id`.
+    fn send_ipi(&self, _hart_id: usize) -> AxResult {
+        Err(AxError::Unsupported)
+    }
+    /// Masks host interrupts on the current hart, returning the state to
+    /// restore. Only used with the `lock-irq-save` feature.
+    fn irq_save(&self) -> usize {
+        0
+    }
+    /// Restores the interrupt state returned by [`VPlicHal::irq_save`].
+    fn irq_restore(&self, _flags: usize) {}
+    /// Selects guest external interrupt line `line` (hstatus.VGEIN) for the
+    /// guest running on the current hart, 0 for none.
+    fn set_vgein(&self, _line: usize) -> AxResult {
+        Err(AxError::Unsupported)
+    }
+    /// Sets the guest external interrupt lines enabled on the current hart
+    /// (hgeie).
+    fn set_hgeie(&self, _mask: usize) -> AxResult {
+        Err(AxError::Unsupported)
+    }
+    /// Returns the guest external interrupt lines pending on the current
+    /// hart (hgeip).
+    fn hgeip(&self) -> usize {
+        0
+    }
+    /// Returns the number of guest external interrupt lines of the current
+    /// hart (GEILEN), 0 without AIA guest interrupt files.
+    fn guest_external_lines(&self) -> usize {
+        0
+    }
+    /// Writes hvictl on the current hart, for AIA delivery with an explicit
+    /// interrupt priority.
+    fn set_hvictl(&self, _val: usize) -> AxResult {
+        Err(AxError::Unsupported)
+    }
+    /// Returns the ID of the current hart, if known. Used to catch hooks
+    /// calling back into APIs that would deadlock (see [`VPlicHooks`]); the
+    /// check is skipped where the hart is unknown.
+    ///
+    /// [`VPlicHooks`]: crate::VPlicHooks
+    fn hart_id(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The HAL of axvisor, backed by axvisor_api and the hvip CSR.
+///
+/// The ID of the current hart is not part of axvisor_api: it is read with
+/// the function registered by [`AxvisorHal::set_hart_id_fn`].
+#[cfg(feature = "axvisor")]
+pub struct AxvisorHal;
+
+/// Returns the ID of the current hart for [`AxvisorHal`].
+#[cfg(feature = "axvisor")]
+static AXVISOR_HART_ID: Once<fn() -> usize> = Once::new();
+
+#[cfg(feature = "axvisor")]
+impl AxvisorHal {
+    /// Registers `hart_id`, returning the ID of the current hart (e.g. the
+    /// CPU ID of the hypervisor's per-CPU data), as the source of
+    /// [`VPlicHal::hart_id`].
+    ///
+    /// Only the first call has an effect; another function fails with
+    /// [`AxError::AlreadyExists`].
+    pub fn set_hart_id_fn(hart_id: fn() -> usize) -> AxResult {
+        let installed = *AXVISOR_HART_ID.call_once(|| hart_id);
+        if core::ptr::fn_addr_eq(installed, hart_id) {
+            Ok(())
+        } else {
+            Err(AxError::AlreadyExists)
+        }
+    }
+}
+
+#[cfg(feature = "axvisor")]
+impl VPlicHal for AxvisorHal {
+    fn phys_to_virt(&self, paddr: HostPhysAddr) -> HostVirtAddr {
+        axvisor_api::memory::phys_to_virt(paddr)
+    }
+
+    fn set_vseip(&self) {
+        unsafe { riscv_h::register::hvip::set_vseip() };
+    }
+
+    fn clear_vseip(&self) {
+        unsafe { riscv_h::register::hvip::clear_vseip() };
+    }
+
+    fn now_ns(&self) -> u64 {
+        axvisor_api::time::current_time_nanos()
+    }
+
+    fn hart_id(&self) -> Option<usize> {
+        AXVISOR_HART_ID.get().map(|hart_id| hart_id())
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn irq_save(&self) -> usize {
+        let flags: usize;
+        // Clear sstatus.SIE, returning the previous value.
+        unsafe { core::arch::asm!("csrrci {}, sstatus, 2", out(reg) flags) };
+        flags & 2
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn irq_restore(&self, flags: usize) {
+        unsafe { core::arch::asm!("csrs sstatus, {}", in(reg) flags) };
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn set_vgein(&self, line: usize) -> AxResult {
+        const VGEIN_SHIFT: usize = 12;
+        const VGEIN_MASK: usize = 0x3f << VGEIN_SHIFT;
+        // hstatus is CSR 0x600.
+        unsafe {
+            core::arch::asm!("csrc 0x600, {}", in(reg) VGEIN_MASK);
+            core::arch::asm!("csrs 0x600, {}", in(reg) (line << VGEIN_SHIFT) & VGEIN_MASK);
+        }
+        Ok(())
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn set_hgeie(&self, mask: usize) -> AxResult {
+        // hgeie is CSR 0x607.
+        unsafe { core::arch::asm!("csrw 0x607, {}", in(reg) mask) };
+        Ok(())
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn hgeip(&self) -> usize {
+        let hgeip: usize;
+        // hgeip is CSR 0xe12.
+        unsafe { core::arch::asm!("csrr {}, 0xe12", out(reg) hgeip) };
+        hgeip
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn set_hvictl(&self, val: usize) -> AxResult {
+        // hvictl is CSR 0x609.
+        unsafe { core::arch::asm!("csrw 0x609, {}", in(reg) val) };
+        Ok(())
+    }
+
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    fn guest_external_lines(&self) -> usize {
+        let (saved, probed): (usize, usize);
+        // The implemented bits of hgeie (CSR 0x607) stick when all are set.
+        unsafe {
+            core::arch::asm!("csrrw {}, 0x607, {}", out(reg) saved, in(reg) usize::MAX);
+            core::arch::asm!("csrrw {}, 0x607, {}", out(reg) probed, in(reg) saved);
+        }
+        probed.count_ones() as usize
+    }
+}
+
+static HAL: Once<&'static dyn VPlicHal> = Once::new();
+
+/// Installs the HAL used by every vPLIC.
+///
+/// Only the first call has an effect: installing another HAL afterwards, or
+/// once the default one is in use, fails with [`AxError::AlreadyExists`].
+pub fn set_hal(hal: &'static dyn VPlicHal) -> AxResult {
+    let installed = *HAL.call_once(|| hal);
+    if core::ptr::addr_eq(installed, hal) {
+        Ok(())
+    } else {
+        Err(AxError::AlreadyExists)
+    }
+}
+
+/// Returns the installed HAL, or the default one.
+#[cfg(all(feature = "axvisor", not(test)))]
+pub(crate) fn hal() -> &'static dyn VPlicHal {
+    *HAL.call_once(|| &AxvisorHal)
+}
+
+/// Returns the installed HAL.
+#[cfg(not(all(feature = "axvisor", not(test))))]
+pub(crate) fn hal() -> &'static dyn VPlicHal {
+    *HAL.get().expect("no vPLIC HAL installed, call set_hal() first")
+}
+
+/// Returns the installed HAL, or the default one, without installing it
+/// or panicking: for the panic path.
+#[cfg(all(feature = "axvisor", not(test)))]
+pub(crate) fn try_hal() -> Option<&'static dyn VPlicHal> {
+    Some(HAL.get().copied().unwrap_or(&AxvisorHal))
+}
+
+/// Returns the installed HAL, if any, without panicking: for the panic path.
+#[cfg(not(all(feature = "axvisor", not(test))))]
+pub(crate) fn try_hal() -> Option<&'static dyn VPlicHal> {
+    HAL.get().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use axaddrspace::{HostPhysAddr, HostVirtAddr};
+    use axerrno::AxError;
+
+    use super::VPlicHal;
+    use crate::MockHal;
+
+    /// A HAL implementing only the required methods.
+    struct MinimalHal;
+
+    impl VPlicHal for MinimalHal {
+        fn phys_to_virt(&self, paddr: HostPhysAddr) -> HostVirtAddr {
+            HostVirtAddr::from_usize(paddr.as_usize())
+        }
+
+        fn set_vseip(&self) {}
+
+        fn clear_vseip(&self) {}
+
+        fn now_ns(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn optional_services_default_to_unsupported() {
+        let hal = MinimalHal;
+        assert_eq!(hal.send_ipi(1), Err(AxError::Unsupported));
+        assert_eq!(hal.set_vgein(1), Err(AxError::Unsupported));
+        assert_eq!(hal.set_hgeie(1), Err(AxError::Unsupported));
+        assert_eq!(hal.set_hvictl(1), Err(AxError::Unsupported));
+        assert_eq!(hal.hgeip(), 0);
+        assert_eq!(hal.guest_external_lines(), 0);
+        assert_eq!(hal.hart_id(), None);
+        let flags = hal.irq_save();
+        hal.irq_restore(flags);
+    }
+
+    #[test]
+    fn mock_hal_tracks_vseip_and_time() {
+        let hal = MockHal::new();
+        assert!(!hal.vseip());
+        hal.set_vseip();
+        assert!(hal.vseip());
+        hal.clear_vseip();
+        assert!(!hal.vseip());
+        assert_eq!(hal.now_ns(), 0);
+        hal.advance(1500);
+        assert_eq!(hal.now_ns(), 1500);
+        let paddr = HostPhysAddr::from_usize(0xc00_0000);
+        assert_eq!(hal.phys_to_virt(paddr).as_usize(), 0xc00_0000);
+    }
+}
@@ -0,0 +1,91 @@
+//! Per-hart companion device.
+//!
+//! The global device decodes the context of a threshold or claim/complete
+//! access from its offset in the context control area. Hypervisors mapping
+//! each vCPU's context window at its own guest address register one
+//! [`VPlicHart`] per vCPU instead: it owns a single context's control page
+//! and forwards accesses to the [`VPlicGlobal`] holding the shared state.
+
+use axaddrspace::{device::AccessWidth, GuestPhysAddr, GuestPhysAddrRange};
+use axdevice_base::{BaseDeviceOps, EmuDeviceType};
+use axerrno::{AxError, AxResult};
+use log::trace;
+
+use crate::consts::*;
+use crate::host::HostBackend;
+use crate::regmap::RegAccess;
+use crate::VPlicGlobal;
+
+/// Threshold and claim/complete window of one context.
+pub struct VPlicHart {
+    /// The address of the window in the guest physical address space.
+    pub addr: GuestPhysAddr,
+    /// The context owning the window.
+    pub context_id: usize,
+    global: &'static VPlicGlobal,
+}
+
+impl VPlicHart {
+    /// Creates the window of `context_id` of `global` at guest address
+    /// `addr`, spanning one context stride.
+    pub fn new(global: &'static VPlicGlobal, context_id: usize, addr: GuestPhysAddr) -> AxResult<Self> {
+        if context_id >= global.contexts_num {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(Self {
+            addr,
+            context_id,
+            global,
+        })
+    }
+
+    /// Returns the global device holding the state.
+    pub fn global(&self) -> &'static VPlicGlobal {
+        self.global
+    }
+
+    /// Builds the access to offset `within` of the window, as the global
+    /// device would decode it.
+    fn access(&self, within: usize, width: AccessWidth) -> RegAccess {
+        let reg = PLIC_CONTEXT_CTRL_OFFSET + self.context_id * PLIC_CONTEXT_STRIDE + within;
+        RegAccess {
+            reg,
+            context_id: self.context_id,
+            index: within / 4,
+            width,
+            host_addr: self.global.host_reg(reg),
+        }
+    }
+}
+
+impl BaseDeviceOps<GuestPhysAddrRange> for VPlicHart {
+    fn emu_type(&self) -> EmuDeviceType {
+        EmuDeviceType::InterruptController
+    }
+
+    fn address_range(&self) -> GuestPhysAddrRange {
+        GuestPhysAddrRange::from_start_size(self.addr, PLIC_CONTEXT_STRIDE)
+    }
+
+    fn handle_read(&self, addr: GuestPhysAddr, width: AccessWidth) -> AxResult<usize> {
+        let within = addr - self.addr;
+        let access = self.access(within, width);
+        trace!("vPlicHart {} read reg {within:#x}", self.context_id);
+        match within {
+            PLIC_CONTEXT_THRESHOLD_OFFSET => self.global.read_threshold(&access),
+            PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET => self.global.read_claim(&access),
+            _ => self.global.read_zero(&access),
+        }
+    }
+
+    fn handle_write(&self, addr: GuestPhysAddr, width: AccessWidth, val: usize) -> AxResult {
+        let within = addr - self.addr;
+        let access = self.access(within, width);
+        trace!("vPlicHart {} write reg {within:#x} val {val:#x}", self.context_id);
+        match within {
+            PLIC_CONTEXT_THRESHOLD_OFFSET => self.global.write_threshold(&access, val),
+            PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET => self.global.write_complete(&access, val),
+            _ => self.global.write_ignore(&access, val),
+        }
+    }
+}
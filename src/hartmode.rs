@@ -0,0 +1,81 @@
+//! Shared-hart and dedicated-hart modes.
+//!
+//! Each guest context either shares its physical hart with the hypervisor
+//! and other guests ([`HartMode::Shared`], the default) or owns it
+//! exclusively ([`HartMode::Dedicated`]). Some features are only sound on
+//! dedicated harts; they are refused at runtime with
+//! [`AxError::Unsupported`] for contexts in shared mode.
+//!
+//! | Feature                                          | Shared | Dedicated  |
+//! |--------------------------------------------------|--------|------------|
+//! | Trap-and-inject delivery (hvip.VSEIP)            | yes    | yes        |
+//! | Claim and completion forwarding                  | yes    | yes        |
+//! | Preempt masking of descheduled vCPUs             | yes    | not needed |
+//! | Direct delivery (guest external interrupt lines) | no     | yes        |
+//!
+//! Direct delivery programs hstatus.VGEIN and hgeie of the physical hart
+//! and lets the hardware raise the guest interrupt: on a shared hart the
+//! line would stay live for whatever runs there next.
+
+use axerrno::{AxError, AxResult};
+use log::warn;
+
+use crate::VPlicGlobal;
+
+/// How a guest context uses its physical hart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HartMode {
+    /// The hart also runs the hypervisor and other guests.
+    #[default]
+    Shared,
+    /// The hart runs only the vCPU owning the context.
+    Dedicated,
+}
+
+impl VPlicGlobal {
+    /// Sets the hart mode of `context_id`.
+    ///
+    /// Fails with [`AxError::ResourceBusy`] when switching to shared mode a
+    /// context whose vCPU still has a guest external interrupt line.
+    pub fn set_hart_mode(&self, context_id: usize, mode: HartMode) -> AxResult {
+        if context_id >= self.contexts_num {
+            return Err(AxError::InvalidInput);
+        }
+        let vcpu_id = self.vcpu_of_context(context_id);
+        let direct = self.direct_lines.lock().get(vcpu_id).copied().flatten().is_some();
+        if mode == HartMode::Shared && direct {
+            return Err(AxError::ResourceBusy);
+        }
+        self.contexts.lock()[context_id].hart_mode = mode;
+        Ok(())
+    }
+
+    /// Returns the hart mode of `context_id`.
+    pub fn hart_mode(&self, context_id: usize) -> HartMode {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or(HartMode::Shared, |context| context.hart_mode)
+    }
+
+    /// Returns `true` if every context of `vcpu_id` owns its hart.
+    pub fn vcpu_dedicated(&self, vcpu_id: usize) -> bool {
+        let contexts = self.contexts_of_vcpu(vcpu_id);
+        !contexts.is_empty()
+            && contexts
+                .iter()
+                .all(|&context_id| self.hart_mode(context_id) == HartMode::Dedicated)
+    }
+
+    /// Refuses `feature` for `vcpu_id` unless it runs on dedicated harts.
+    pub(crate) fn require_dedicated(&self, vcpu_id: usize, feature: &str) -> AxResult {
+        if self.vcpu_dedicated(vcpu_id) {
+            return Ok(());
+        }
+        warn!(
+            "vPlicGlobal: {} needs a dedicated hart, vCPU {} shares its hart",
+            feature, vcpu_id
+        );
+        Err(AxError::Unsupported)
+    }
+}
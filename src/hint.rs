@@ -0,0 +1,122 @@
+//! Shared pending-hint page for enlightened guests.
+//!
+//! The VMM shares a page of guest memory with the vPLIC, which keeps a copy
+//! of the pending bitmap and of the per-context eligible banks in it,
+//! updated on every pending-state change. An enlightened guest reads it to
+//! find out whether there is work without trapping on the pending
+//! registers.
+//!
+//! The page is only a hint: the authoritative state stays in the vPLIC and
+//! the guest must still claim through the claim register, which may return
+//! 0 if the hinted interrupt is gone. Updates follow the same sequence
+//! counter protocol as the mirror page: readers retry while `generation` is
+//! odd or changes under them.
+
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{fence, Ordering};
+
+use axaddrspace::HostPhysAddr;
+use axerrno::{AxError, AxResult};
+
+use crate::hal::hal;
+use crate::{VPlicGlobal, PLIC_NUM_BANKS, PLIC_NUM_SOURCES};
+
+/// Magic value identifying a pending-hint page ("VPLH").
+pub const HINT_MAGIC: u32 = 0x484c_5056;
+
+/// Layout version of [`PendingHint`].
+pub const HINT_VERSION: u32 = 1;
+
+/// Maximum number of contexts covered by a pending-hint page.
+pub const HINT_MAX_CONTEXTS: usize = 64;
+
+/// Layout of the pending-hint page.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PendingHint {
+    /// [`HINT_MAGIC`] while the page is maintained, 0 once released.
+    pub magic: u32,
+    /// Always [`HINT_VERSION`].
+    pub version: u32,
+    /// Number of interrupt sources covered by `pending`.
+    pub num_sources: u32,
+    /// Number of valid entries of `eligible`.
+    pub num_contexts: u32,
+    /// Sequence counter, odd while an update is in progress.
+    pub generation: u64,
+    /// Pending sources, one bit per source.
+    pub pending: [u32; PLIC_NUM_BANKS],
+    /// Per-context banks holding a pending source enabled for the context.
+    pub eligible: [u32; HINT_MAX_CONTEXTS],
+}
+
+impl VPlicGlobal {
+    /// Starts maintaining the pending-hint page at `page`, the host address
+    /// of the guest page shared for it.
+    ///
+    /// # Safety
+    ///
+    /// `page` must be the host physical address of memory reserved for the
+    /// hint, mapped by the HAL and at least `size_of::<PendingHint>()` bytes
+    /// long. It must stay so until [`VPlicGlobal::disable_pending_hint`]
+    /// returns, and the hypervisor must not use it for anything else
+    /// meanwhile; the guest only reads it.
+    pub unsafe fn enable_pending_hint(&self, page: HostPhysAddr) -> AxResult {
+        if self.contexts_num > HINT_MAX_CONTEXTS || page.as_usize() % 8 != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        *self.hint_page.lock() = Some(page);
+        self.publish_hint();
+        Ok(())
+    }
+
+    /// Stops maintaining the pending-hint page and marks it invalid, so the
+    /// guest falls back to the pending registers.
+    pub fn disable_pending_hint(&self) {
+        if let Some(page) = self.hint_page.lock().take() {
+            let page = hal().phys_to_virt(page).as_mut_ptr() as *mut PendingHint;
+            // SAFETY: the page stays valid until disabled, per
+            // `enable_pending_hint`.
+            unsafe { addr_of_mut!((*page).magic).write_volatile(0) };
+        }
+    }
+
+    /// Publishes the pending bits into the pending-hint page, if enabled.
+    ///
+    /// The bits are read under the page lock, so the last of concurrent
+    /// publishers leaves the latest state in the page.
+    pub(crate) fn publish_hint(&self) {
+        let hint_page = self.hint_page.lock();
+        let page = match *hint_page {
+            Some(page) => page,
+            None => return,
+        };
+        let mut words = [0; PLIC_NUM_BANKS];
+        for (bank, word) in words.iter_mut().enumerate() {
+            *word = self.pending_irqs.word(bank);
+        }
+        let mut eligible = [0; HINT_MAX_CONTEXTS];
+        for (context_id, context) in self.contexts.lock().iter().enumerate() {
+            eligible[context_id] = (0..PLIC_NUM_BANKS)
+                .filter(|&bank| words[bank] & context.enables.word(bank) != 0)
+                .fold(0, |banks, bank| banks | 1 << bank);
+        }
+
+        let page = hal().phys_to_virt(page).as_mut_ptr() as *mut PendingHint;
+        let generation = self.hint_generation.fetch_add(2, Ordering::Relaxed);
+        // SAFETY: the page stays valid while enabled, per
+        // `enable_pending_hint`.
+        unsafe {
+            addr_of_mut!((*page).generation).write_volatile(generation + 1);
+            fence(Ordering::Release);
+            addr_of_mut!((*page).magic).write_volatile(HINT_MAGIC);
+            addr_of_mut!((*page).version).write_volatile(HINT_VERSION);
+            addr_of_mut!((*page).num_sources).write_volatile(PLIC_NUM_SOURCES as u32);
+            addr_of_mut!((*page).num_contexts).write_volatile(self.contexts_num as u32);
+            addr_of_mut!((*page).pending).write_volatile(words);
+            addr_of_mut!((*page).eligible).write_volatile(eligible);
+            fence(Ordering::Release);
+            addr_of_mut!((*page).generation).write_volatile(generation + 2);
+        }
+    }
+}
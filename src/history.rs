@@ -0,0 +1,86 @@
+//! Per-context claim history for debugging lost-EOI bugs.
+//!
+//! The last [`CLAIM_HISTORY_LEN`] claims of every context are recorded with
+//! their claim and completion times, so "the guest claims but never completes
+//! IRQ 33" class of bugs is diagnosable from the host side.
+
+use alloc::vec::Vec;
+
+use crate::utils::now_ns;
+use crate::{InjectionSource, VPlicGlobal};
+
+/// Number of claims remembered per context.
+pub const CLAIM_HISTORY_LEN: usize = 16;
+
+/// One claim of an IRQ by a context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClaimRecord {
+    /// The claimed IRQ.
+    pub irq_id: usize,
+    /// Host time of the claim, in nanoseconds.
+    pub claimed_at: u64,
+    /// Host time of the completion, in nanoseconds, if completed.
+    pub completed_at: Option<u64>,
+    /// Origin of the injection that made the IRQ pending.
+    pub source: Option<InjectionSource>,
+}
+
+/// Ring buffer of the last claims of a context.
+#[derive(Clone, Default)]
+pub(crate) struct ClaimHistory {
+    records: [ClaimRecord; CLAIM_HISTORY_LEN],
+    next: usize,
+    len: usize,
+}
+
+impl ClaimHistory {
+    fn push(&mut self, record: ClaimRecord) {
+        self.records[self.next] = record;
+        self.next = (self.next + 1) % CLAIM_HISTORY_LEN;
+        self.len = (self.len + 1).min(CLAIM_HISTORY_LEN);
+    }
+
+    /// Iterates over the records, newest first.
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut ClaimRecord> {
+        let (head, tail) = self.records.split_at_mut(self.next);
+        let len = self.len;
+        head.iter_mut().rev().chain(tail.iter_mut().rev()).take(len)
+    }
+}
+
+impl VPlicGlobal {
+    /// Returns the claim history of `context_id`, oldest first.
+    pub fn claim_history(&self, context_id: usize) -> Vec<ClaimRecord> {
+        let mut histories = self.histories.lock();
+        match histories.get_mut(context_id) {
+            Some(history) => {
+                let mut records: Vec<_> = history.iter_mut().map(|record| *record).collect();
+                records.reverse();
+                records
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Records a claim of `irq_id` by `context_id`.
+    pub(crate) fn history_claim(&self, context_id: usize, irq_id: usize) {
+        let source = self.injection_source(irq_id);
+        self.histories.lock()[context_id].push(ClaimRecord {
+            irq_id,
+            claimed_at: now_ns(),
+            completed_at: None,
+            source,
+        });
+    }
+
+    /// Records the completion of `irq_id` by `context_id`.
+    pub(crate) fn history_complete(&self, context_id: usize, irq_id: usize) {
+        let mut histories = self.histories.lock();
+        if let Some(record) = histories[context_id]
+            .iter_mut()
+            .find(|record| record.irq_id == irq_id && record.completed_at.is_none())
+        {
+            record.completed_at = Some(now_ns());
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Callbacks the embedding hypervisor registers to follow vPLIC events.
+//!
+//! Hooks run synchronously in the middle of vPLIC operations: a claim, a
+//! completion, an injection, a guest enable write, or a shared line driven
+//! by an [`IrqLine`](crate::IrqLine) with its contributor count locked. They
+//! may therefore only call back into the vPLIC where that cannot wait on
+//! the operation they run in:
+//!
+//! | From a hook                                         | Allowed |
+//! |-----------------------------------------------------|---------|
+//! | queries (`should_wake`, `is_irq_pending`, stats...) | yes     |
+//! | `inject_irq`, `retract_irq`, `set_irq_level`        | yes     |
+//! | per-source and per-context configuration setters    | yes     |
+//! | guest claims and completions (incl. paravirtual)    | no      |
+//! | `IrqLine` level changes, `line_contributors`        | no      |
+//! | `reconfigure`, `reconfigure_masked`, `pause`        | no      |
+//! | `restore_state`                                     | no      |
+//!
+//! The forbidden ones wait for the claim or configuration section the hook
+//! runs in, or take a lock held around it, so they check whether they are
+//! called from a hook: debug builds assert, release builds warn and refuse
+//! the call where they can (claims return 0, the others fail with
+//! [`AxError::BadState`]). `reconfigure`, `reconfigure_masked` and `pause`
+//! cannot fail and only warn.
+//!
+//! The check relies on the HAL reporting the current hart
+//! ([`VPlicHal::hart_id`](crate::VPlicHal::hart_id)). Where it does not, or
+//! for harts beyond the 64th, the vPLIC cannot tell a hook calling back from
+//! another hart running concurrently, so the check is skipped rather than
+//! refusing legitimate calls.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+use log::warn;
+
+use crate::banks::BankIter;
+use crate::hal::hal;
+use crate::{source_bit, source_word, word_source, TracePoint, VPlicGlobal};
+
+/// Hypervisor-side callbacks. Every method defaults to a no-op.
+pub trait VPlicHooks: Send + Sync {
+    /// Called when `context_id` gets an interrupt it can take, so a vCPU
+    /// blocked in WFI on that context can be woken.
+    fn wake(&self, _context_id: usize) {}
+
+    /// Called when `vcpu_id` gains (`true`) or loses (`false`) a deliverable
+    /// interrupt, so the scheduler can boost interrupt-driven vCPUs.
+    fn deliverability_changed(&self, _vcpu_id: usize, _deliverable: bool) {}
+
+    /// Called when `context_id` claims `irq_id`, with the cookie attached to
+    /// the source.
+    fn claimed(&self, _context_id: usize, _irq_id: usize, _cookie: Option<usize>) {}
+
+    /// Called when the guest completes `irq_id`, with the cookie attached to
+    /// the source.
+    fn completed(&self, _irq_id: usize, _cookie: Option<usize>) {}
+
+    /// Called when the guest enables (`true`) or disables (`false`) the
+    /// passthrough source `irq_id` for `context_id`, so an affinity manager
+    /// can route the host interrupt to the hart running that context.
+    fn enable_changed(&self, _context_id: usize, _irq_id: usize, _enabled: bool) {}
+
+    /// Called when `irq_id` gets masked (`true`) on the host because the
+    /// vCPU handling it was descheduled, or unmasked (`false`) when it runs
+    /// again.
+    fn preempt_masked(&self, _irq_id: usize, _masked: bool) {}
+
+    /// Called when `irq_id` becomes back-pressured (`true`) because the
+    /// guest does not keep up with its injections, or is relieved (`false`)
+    /// once the guest claims it.
+    fn backpressure(&self, _irq_id: usize, _asserted: bool) {}
+
+    /// Called when the affinity hint of `irq_id` changes (see
+    /// [`VPlicGlobal::affinity_hint`]): the guest now routes it to a single
+    /// vCPU, or to none or several of them (`None`).
+    fn affinity_changed(&self, _irq_id: usize, _vcpu_id: Option<usize>) {}
+
+    /// Called on every trace point, with its source and argument.
+    fn trace(&self, _point: TracePoint, _irq_id: usize, _arg: usize) {}
+}
+
+impl VPlicGlobal {
+    /// Registers the hypervisor callbacks, replacing any previous ones.
+    pub fn register_hooks(&self, hooks: &'static dyn VPlicHooks) {
+        *self.hooks.lock() = Some(hooks);
+    }
+
+    /// Unregisters the hypervisor callbacks.
+    pub fn unregister_hooks(&self) {
+        *self.hooks.lock() = None;
+    }
+
+    /// Returns the registered hypervisor callbacks.
+    pub(crate) fn hooks(&self) -> Option<&'static dyn VPlicHooks> {
+        *self.hooks.lock()
+    }
+
+    /// Calls `f` with the registered hypervisor callbacks, if any, with the
+    /// current hart marked as running a hook meanwhile.
+    pub(crate) fn call_hooks(&self, f: impl FnOnce(&'static dyn VPlicHooks)) {
+        let hooks = match self.hooks() {
+            Some(hooks) => hooks,
+            None => return,
+        };
+        let mask = hook_hart_mask();
+        let nested = self.hook_harts.fetch_or(mask, Ordering::AcqRel) & mask != 0;
+        f(hooks);
+        if !nested {
+            self.hook_harts.fetch_and(!mask, Ordering::AcqRel);
+        }
+    }
+
+    /// Returns `true` if the current hart runs a hook, `false` if it does not
+    /// or the HAL does not report the current hart.
+    pub(crate) fn in_hook(&self) -> bool {
+        let mask = hook_hart_mask();
+        mask != 0 && self.hook_harts.load(Ordering::Acquire) & mask != 0
+    }
+
+    /// Checks that `api`, which hooks must not call, is not called from one.
+    pub(crate) fn forbid_in_hook(&self, api: &str) -> AxResult {
+        let in_hook = self.in_hook();
+        debug_assert!(!in_hook, "vPlicGlobal: {} called from a hook", api);
+        if in_hook {
+            warn!("vPlicGlobal: {} called from a hook would deadlock, refused", api);
+            return Err(AxError::BadState);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if `context_id` has a pending, enabled interrupt whose
+    /// priority is above the context threshold, i.e. a vCPU idling in WFI on
+    /// this context must not block.
+    pub fn should_wake(&self, context_id: usize) -> bool {
+        self.deliverable_priority(context_id) != 0
+    }
+
+    /// Returns the highest priority among the pending, enabled interrupts of
+    /// `context_id` above its threshold, or 0 if there is none.
+    pub fn deliverable_priority(&self, context_id: usize) -> u32 {
+        if context_id >= self.contexts_num {
+            return 0;
+        }
+        let threshold = self.threshold(context_id).unwrap_or(u32::MAX);
+        let pending = self.pending_irqs.snapshot();
+        let enables = self.enables(context_id);
+        let mut max_priority = 0;
+        for bank in BankIter::new(pending.summary() & enables.summary()) {
+            for bit in BankIter::new(pending.word(bank) & enables.word(bank)) {
+                let irq_id = word_source(bank, bit);
+                if !self.guest_owns(irq_id) {
+                    continue;
+                }
+                let priority = self.priority(irq_id);
+                if priority > threshold && priority > max_priority {
+                    max_priority = priority;
+                }
+            }
+        }
+        max_priority
+    }
+
+    /// Re-evaluates deliverability after a state change: wakes every context
+    /// that can take an interrupt and reports per-vCPU deliverability changes.
+    ///
+    /// The hooks are called once the reported state is unlocked, so they may
+    /// inject.
+    pub(crate) fn refresh_deliverability(&self) {
+        if self.hooks().is_none() || self.delivery_held() {
+            return;
+        }
+        let mut wakes = Vec::new();
+        let mut changes = Vec::new();
+        {
+            let mut deliverable = self.deliverable_vcpus.lock();
+            let mut any = vec![false; deliverable.len()];
+            for context_id in 0..self.contexts_num {
+                if self.should_wake(context_id) {
+                    wakes.push(context_id);
+                    any[self.vcpu_of_context(context_id)] = true;
+                }
+            }
+            for (vcpu_id, any) in any.into_iter().enumerate() {
+                if deliverable[vcpu_id] != any {
+                    deliverable[vcpu_id] = any;
+                    changes.push((vcpu_id, any));
+                }
+            }
+        }
+        self.report_deliverability(wakes, changes);
+    }
+
+    /// Re-evaluates deliverability after `irq_id` became pending: only the
+    /// contexts it is enabled in can gain an interrupt, and vCPUs can only
+    /// become deliverable.
+    pub(crate) fn refresh_deliverability_of(&self, irq_id: usize) {
+        if self.hooks().is_none() || self.delivery_held() {
+            return;
+        }
+        let (word, bit) = (source_word(irq_id), source_bit(irq_id));
+        let mut wakes = Vec::new();
+        let mut changes = Vec::new();
+        {
+            let mut deliverable = self.deliverable_vcpus.lock();
+            for context_id in 0..self.contexts_num {
+                if self.enable_word(context_id, word) & bit == 0 || !self.should_wake(context_id) {
+                    continue;
+                }
+                wakes.push(context_id);
+                let vcpu_id = self.vcpu_of_context(context_id);
+                if !deliverable[vcpu_id] {
+                    deliverable[vcpu_id] = true;
+                    changes.push((vcpu_id, true));
+                }
+            }
+        }
+        self.report_deliverability(wakes, changes);
+    }
+
+    /// Reports the contexts to wake and the deliverability changes to the
+    /// hooks.
+    fn report_deliverability(&self, wakes: Vec<usize>, changes: Vec<(usize, bool)>) {
+        self.call_hooks(|hooks| {
+            for context_id in wakes {
+                hooks.wake(context_id);
+            }
+            for (vcpu_id, deliverable) in changes {
+                hooks.deliverability_changed(vcpu_id, deliverable);
+            }
+        });
+    }
+}
+
+/// Returns the bit of the current hart in the set of harts running a hook,
+/// 0 if the HAL does not report it or it is not tracked.
+fn hook_hart_mask() -> u64 {
+    match hal().hart_id() {
+        Some(hart_id) if hart_id < u64::BITS as usize => 1 << hart_id,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use spin::Once;
+
+    use super::VPlicHooks;
+    use crate::mock::testing::*;
+    use crate::VPlicGlobal;
+
+    /// Calls back into the vPLIC when source 1 is claimed.
+    struct OnClaim {
+        vplic: Once<&'static VPlicGlobal>,
+        call: fn(&VPlicGlobal),
+    }
+
+    impl VPlicHooks for OnClaim {
+        fn claimed(&self, _context_id: usize, irq_id: usize, _cookie: Option<usize>) {
+            if irq_id == 1 {
+                (self.call)(self.vplic.get().unwrap());
+            }
+        }
+    }
+
+    /// Creates a mock vPLIC with sources 1 and 2 routed to context 0 and
+    /// `hooks` registered.
+    fn hooked_vplic(hooks: &'static OnClaim) -> &'static VPlicGlobal {
+        let vplic: &'static VPlicGlobal = Box::leak(Box::new(mock_vplic(1)));
+        route(vplic, 0, 1, 1);
+        route(vplic, 0, 2, 1);
+        hooks.vplic.call_once(|| vplic);
+        vplic.register_hooks(hooks);
+        vplic
+    }
+
+    /// Records the contexts woken.
+    struct Wakes(std::sync::Mutex<Vec<usize>>);
+
+    impl VPlicHooks for Wakes {
+        fn wake(&self, context_id: usize) {
+            self.0.lock().unwrap().push(context_id);
+        }
+    }
+
+    #[test]
+    fn injection_wakes_contexts_enabling_source() {
+        static HOOKS: Wakes = Wakes(std::sync::Mutex::new(Vec::new()));
+        let vplic = mock_vplic(3);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 2, 1, 1);
+        route(&vplic, 1, 2, 1);
+        vplic.register_hooks(&HOOKS);
+        vplic.inject_irq(1).unwrap();
+        assert_eq!(*HOOKS.0.lock().unwrap(), [0, 2]);
+    }
+
+    #[test]
+    fn hook_may_inject() {
+        static HOOKS: OnClaim = OnClaim {
+            vplic: Once::new(),
+            call: |vplic| vplic.inject_irq(2).unwrap(),
+        };
+        let vplic = hooked_vplic(&HOOKS);
+        vplic.inject_irq(1).unwrap();
+        assert_eq!(claim(vplic, 0), 1);
+        assert!(vplic.is_irq_pending(2));
+        assert_eq!(claim(vplic, 0), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "called from a hook")]
+    fn hook_must_not_claim() {
+        static HOOKS: OnClaim = OnClaim {
+            vplic: Once::new(),
+            call: |vplic| {
+                claim(vplic, 0);
+            },
+        };
+        let vplic = hooked_vplic(&HOOKS);
+        vplic.inject_irq(1).unwrap();
+        claim(vplic, 0);
+    }
+
+    #[test]
+    fn hook_is_only_caught_with_hart_id() {
+        static CAUGHT: AtomicBool = AtomicBool::new(false);
+        static HOOKS: OnClaim = OnClaim {
+            vplic: Once::new(),
+            call: |vplic| CAUGHT.store(vplic.in_hook(), Ordering::Relaxed),
+        };
+        let vplic = hooked_vplic(&HOOKS);
+        assert!(!vplic.in_hook());
+        vplic.inject_irq(1).unwrap();
+        assert_eq!(claim(vplic, 0), 1);
+        assert!(CAUGHT.load(Ordering::Relaxed));
+        assert!(!vplic.in_hook());
+        complete(vplic, 0, 1);
+
+        mock_hal().set_hart_id(None);
+        vplic.inject_irq(1).unwrap();
+        assert_eq!(claim(vplic, 0), 1);
+        assert!(!CAUGHT.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,131 @@
+//! Accessors for the host PLIC registers backing a vPLIC.
+//!
+//! Raw register access goes through [`HostBackend`], so other virtual
+//! interrupt controllers can reuse the layers built on top of it by only
+//! telling where their host registers are.
+
+use axaddrspace::{device::AccessWidth, HostPhysAddr};
+use axerrno::{AxError, AxResult};
+
+use crate::consts::*;
+use crate::utils::{perform_mmio_read, perform_mmio_write};
+use crate::VPlicGlobal;
+
+/// Raw access to the host interrupt controller backing a virtual one.
+pub(crate) trait HostBackend {
+    /// Returns the host address of the register at `offset`.
+    fn host_reg(&self, offset: usize) -> HostPhysAddr;
+
+    /// Reads the 32-bit host register at `offset`.
+    fn host_read(&self, offset: usize) -> AxResult<u32> {
+        perform_mmio_read(self.host_reg(offset), AccessWidth::Dword).map(|val| val as u32)
+    }
+
+    /// Writes the 32-bit host register at `offset`.
+    fn host_write(&self, offset: usize, val: u32) -> AxResult {
+        perform_mmio_write(self.host_reg(offset), AccessWidth::Dword, val as usize)
+    }
+
+    /// Forwards the completion of host source `host_irq` to the host
+    /// claim/complete register at `host_addr`, always as a 32-bit access.
+    fn host_complete(&self, host_addr: HostPhysAddr, host_irq: usize) -> AxResult {
+        perform_mmio_write(host_addr, AccessWidth::Dword, host_irq)
+    }
+}
+
+impl HostBackend for VPlicGlobal {
+    fn host_reg(&self, offset: usize) -> HostPhysAddr {
+        HostPhysAddr::from_usize(self.host_plic_addr.as_usize() + offset)
+    }
+
+    fn host_read(&self, offset: usize) -> AxResult<u32> {
+        match &self.emulated_regs {
+            Some(regs) => Ok(regs.lock().get(&offset).copied().unwrap_or(0)),
+            None => perform_mmio_read(self.host_reg(offset), AccessWidth::Dword).map(|val| val as u32),
+        }
+    }
+
+    fn host_write(&self, offset: usize, val: u32) -> AxResult {
+        match &self.emulated_regs {
+            Some(regs) => {
+                regs.lock().insert(offset, val);
+                Ok(())
+            }
+            None => perform_mmio_write(self.host_reg(offset), AccessWidth::Dword, val as usize),
+        }
+    }
+
+    fn host_complete(&self, host_addr: HostPhysAddr, host_irq: usize) -> AxResult {
+        match &self.emulated_regs {
+            Some(_) => Ok(()),
+            None => perform_mmio_write(host_addr, AccessWidth::Dword, host_irq),
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Backs guest context `context_id` with host context `host_context_id`.
+    pub fn map_context(&self, context_id: usize, host_context_id: usize) -> AxResult {
+        match self.contexts.lock().get_mut(context_id) {
+            Some(context) => {
+                context.host_context = host_context_id;
+                Ok(())
+            }
+            None => Err(AxError::InvalidInput),
+        }
+    }
+
+    /// Returns the host context backing guest context `context_id`.
+    pub fn host_context(&self, context_id: usize) -> usize {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or(context_id, |context| context.host_context)
+    }
+
+    /// Returns the host offset of enable word `word_index` of guest context
+    /// `context_id`.
+    pub(crate) fn host_enable_offset(&self, context_id: usize, word_index: usize) -> usize {
+        PLIC_ENABLE_OFFSET + self.host_context(context_id) * PLIC_ENABLE_STRIDE + word_index * 4
+    }
+
+    /// Returns the host offset of the threshold of guest context `context_id`.
+    pub(crate) fn host_threshold_offset(&self, context_id: usize) -> usize {
+        PLIC_CONTEXT_CTRL_OFFSET
+            + self.host_context(context_id) * PLIC_CONTEXT_STRIDE
+            + PLIC_CONTEXT_THRESHOLD_OFFSET
+    }
+
+    /// Returns the host offset of the claim/complete register of guest
+    /// context `context_id`.
+    pub(crate) fn host_claim_offset(&self, context_id: usize) -> usize {
+        PLIC_CONTEXT_CTRL_OFFSET
+            + self.host_context(context_id) * PLIC_CONTEXT_STRIDE
+            + PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET
+    }
+
+    /// Prepares the host PLIC for the VM in one pass: programs the host
+    /// priority of every assigned source (its shadow priority, or
+    /// `default_priority` if unset), and replaces the enables of assigned
+    /// sources left by prior owners in the host contexts backing the guest
+    /// with the guest enables, so sources stay masked until the guest (or
+    /// the builder) enables them. Enable words are written once each.
+    pub fn init_host_for_vm(&self, default_priority: u32) -> AxResult {
+        let assigned = *self.assigned_irqs.lock();
+        for host_irq in assigned.into_iter().filter(|&host_irq| host_irq != 0) {
+            if let Some(irq_id) = self.guest_irq(host_irq) {
+                let priority = match self.priority(irq_id) {
+                    0 => default_priority,
+                    priority => priority,
+                };
+                self.set_priority(irq_id, priority)?;
+            }
+        }
+        self.sync_host_enables()
+    }
+
+    /// Reads the host priority of `irq_id`.
+    pub(crate) fn host_priority(&self, irq_id: usize) -> AxResult<u32> {
+        self.host_read(PLIC_PRIORITY_OFFSET + irq_id * 4)
+    }
+}
@@ -0,0 +1,291 @@
+//! Virtual IMSIC guest interrupt files of the RISC-V Advanced Interrupt
+//! Architecture.
+//!
+//! [`VImsic`] models one guest interrupt file per hart: the eidelivery,
+//! eithreshold, eip and eie registers reached through the indirect CSRs
+//! (vsiselect/vsireg), the vstopei claim register and the memory-mapped MSI
+//! write port. On hardware with Smaia the hypervisor traps the guest's
+//! vsiselect/vsireg/vstopei accesses it does not back with a hardware file
+//! and calls [`VImsic::csr_read`], [`VImsic::csr_write`] and
+//! [`VImsic::claim_topei`]; without Smaia every access goes there. The
+//! external interrupt of each hart is driven through
+//! [`VImsicTarget::set_external_irq`].
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axaddrspace::{device::AccessWidth, GuestPhysAddr, GuestPhysAddrRange};
+use axdevice_base::{BaseDeviceOps, EmuDeviceType};
+use axerrno::{AxError, AxResult};
+use log::trace;
+
+use crate::lock::Mutex;
+
+/// Size of the MSI page of one interrupt file.
+pub const IMSIC_PAGE_SIZE: usize = 0x1000;
+
+/// Largest number of interrupt identities of a file, plus one.
+pub const IMSIC_MAX_IDS: usize = 2048;
+
+/// Offset within an MSI page of the little-endian set-pending register.
+const SETEIPNUM_LE_OFFSET: usize = 0x0;
+/// Offset within an MSI page of the big-endian set-pending register.
+const SETEIPNUM_BE_OFFSET: usize = 0x4;
+
+/// Indirect register numbers.
+const ISELECT_EIDELIVERY: usize = 0x70;
+const ISELECT_EITHRESHOLD: usize = 0x72;
+const ISELECT_EIP0: usize = 0x80;
+const ISELECT_EIP63: usize = 0xbf;
+const ISELECT_EIE0: usize = 0xc0;
+const ISELECT_EIE63: usize = 0xff;
+
+/// Interrupt identities per 32-bit register word.
+const WORD_BITS: usize = 32;
+
+/// The hypervisor side of a [`VImsic`].
+pub trait VImsicTarget: Send + Sync {
+    /// Asserts or withdraws the external interrupt of guest hart
+    /// `hart_index`.
+    fn set_external_irq(&self, hart_index: usize, asserted: bool);
+}
+
+/// State of one guest interrupt file.
+struct InterruptFile {
+    eidelivery: u32,
+    eithreshold: u32,
+    /// Pending identities, 32 per word.
+    eip: Vec<u32>,
+    /// Enabled identities, 32 per word.
+    eie: Vec<u32>,
+    /// External interrupt, as last reported.
+    asserted: bool,
+}
+
+impl InterruptFile {
+    fn new(ids_num: usize) -> Self {
+        Self {
+            eidelivery: 0,
+            eithreshold: 0,
+            eip: vec![0; ids_num.div_ceil(WORD_BITS)],
+            eie: vec![0; ids_num.div_ceil(WORD_BITS)],
+            asserted: false,
+        }
+    }
+
+    /// Returns the lowest pending, enabled identity below the threshold.
+    /// Identity 0 is never pending nor enabled.
+    fn top(&self) -> Option<usize> {
+        let top = self
+            .eip
+            .iter()
+            .zip(&self.eie)
+            .enumerate()
+            .find(|(_, (&eip, &eie))| eip & eie != 0)
+            .map(|(word, (&eip, &eie))| word * WORD_BITS + (eip & eie).trailing_zeros() as usize)?;
+        (self.eithreshold == 0 || top < self.eithreshold as usize).then_some(top)
+    }
+
+    fn interrupting(&self) -> bool {
+        self.eidelivery == 1 && self.top().is_some()
+    }
+
+    fn set_pending(&mut self, id: usize, pending: bool) {
+        let (word, bit) = (id / WORD_BITS, id % WORD_BITS);
+        if let Some(eip) = self.eip.get_mut(word) {
+            if pending {
+                *eip |= 1 << bit;
+            } else {
+                *eip &= !(1 << bit);
+            }
+        }
+    }
+}
+
+/// Virtual IMSIC interrupt files, one per hart, with their MSI pages laid
+/// out contiguously from `addr`.
+pub struct VImsic {
+    /// The address of the MSI page of hart 0 in the guest physical address
+    /// space.
+    pub addr: GuestPhysAddr,
+    /// Number of harts.
+    pub harts_num: usize,
+    /// Number of interrupt identities of each file, identity 0 included.
+    pub ids_num: usize,
+    files: Vec<Mutex<InterruptFile>>,
+    target: &'static dyn VImsicTarget,
+}
+
+impl VImsic {
+    /// Creates the files of `harts_num` harts with identities
+    /// `0..ids_num`, where `ids_num` is a multiple of 64 up to 2048.
+    pub fn new(
+        addr: GuestPhysAddr,
+        harts_num: usize,
+        ids_num: usize,
+        target: &'static dyn VImsicTarget,
+    ) -> AxResult<Self> {
+        if harts_num == 0 || ids_num == 0 || ids_num % 64 != 0 || ids_num > IMSIC_MAX_IDS {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(Self {
+            addr,
+            harts_num,
+            ids_num,
+            files: (0..harts_num).map(|_| Mutex::new(InterruptFile::new(ids_num))).collect(),
+            target,
+        })
+    }
+
+    /// Makes identity `eiid` pending in the file of `hart_index`, as an MSI
+    /// write would.
+    pub fn send_msi(&self, hart_index: usize, eiid: usize) -> AxResult {
+        if hart_index >= self.harts_num {
+            return Err(AxError::InvalidInput);
+        }
+        // Writes of identity 0 or beyond the file are ignored.
+        if eiid != 0 && eiid < self.ids_num {
+            self.update(hart_index, |file| file.set_pending(eiid, true));
+        }
+        Ok(())
+    }
+
+    /// Reads the indirect register `iselect` of the file of `hart_index`.
+    pub fn csr_read(&self, hart_index: usize, iselect: usize) -> AxResult<usize> {
+        let file = self.files.get(hart_index).ok_or(AxError::InvalidInput)?.lock();
+        match iselect {
+            ISELECT_EIDELIVERY => Ok(file.eidelivery as usize),
+            ISELECT_EITHRESHOLD => Ok(file.eithreshold as usize),
+            ISELECT_EIP0..=ISELECT_EIP63 => Self::read_array(&file.eip, iselect - ISELECT_EIP0),
+            ISELECT_EIE0..=ISELECT_EIE63 => Self::read_array(&file.eie, iselect - ISELECT_EIE0),
+            _ => Err(AxError::InvalidInput),
+        }
+    }
+
+    /// Writes the indirect register `iselect` of the file of `hart_index`.
+    pub fn csr_write(&self, hart_index: usize, iselect: usize, val: usize) -> AxResult {
+        if hart_index >= self.harts_num {
+            return Err(AxError::InvalidInput);
+        }
+        let ids_num = self.ids_num;
+        self.update(hart_index, |file| match iselect {
+            ISELECT_EIDELIVERY => {
+                file.eidelivery = (val & 1) as u32;
+                Ok(())
+            }
+            ISELECT_EITHRESHOLD => {
+                file.eithreshold = (val & (ids_num - 1)) as u32;
+                Ok(())
+            }
+            ISELECT_EIP0..=ISELECT_EIP63 => {
+                Self::write_array(&mut file.eip, iselect - ISELECT_EIP0, val)
+            }
+            ISELECT_EIE0..=ISELECT_EIE63 => {
+                Self::write_array(&mut file.eie, iselect - ISELECT_EIE0, val)
+            }
+            _ => Err(AxError::InvalidInput),
+        })
+    }
+
+    /// Returns the vstopei value of the file of `hart_index`: the top
+    /// identity in both fields, 0 if none.
+    pub fn topei(&self, hart_index: usize) -> usize {
+        self.files.get(hart_index).map_or(0, |file| {
+            file.lock().top().map_or(0, |id| id << 16 | id)
+        })
+    }
+
+    /// Claims the top identity of the file of `hart_index`, as a write to
+    /// vstopei does, returning its previous vstopei value.
+    pub fn claim_topei(&self, hart_index: usize) -> usize {
+        if hart_index >= self.harts_num {
+            return 0;
+        }
+        self.update(hart_index, |file| match file.top() {
+            Some(id) => {
+                file.set_pending(id, false);
+                id << 16 | id
+            }
+            None => 0,
+        })
+    }
+
+    /// Reads register `index` of an eip/eie array, XLEN bits wide. On RV64
+    /// odd registers do not exist.
+    fn read_array(array: &[u32], index: usize) -> AxResult<usize> {
+        let words = usize::BITS as usize / WORD_BITS;
+        if index % words != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        Ok((0..words)
+            .map(|i| array.get(index + i).copied().unwrap_or(0) as usize)
+            .enumerate()
+            .fold(0, |val, (i, word)| val | word << (i * WORD_BITS)))
+    }
+
+    fn write_array(array: &mut [u32], index: usize, val: usize) -> AxResult {
+        let words = usize::BITS as usize / WORD_BITS;
+        if index % words != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        for i in 0..words {
+            if let Some(word) = array.get_mut(index + i) {
+                *word = (val >> (i * WORD_BITS)) as u32;
+            }
+        }
+        // Identity 0 is never pending nor enabled.
+        if index == 0 {
+            if let Some(word) = array.first_mut() {
+                *word &= !1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `f` to the file of `hart_index`, then reports a change of
+    /// its external interrupt.
+    fn update<R>(&self, hart_index: usize, f: impl FnOnce(&mut InterruptFile) -> R) -> R {
+        let (ret, changed) = {
+            let mut file = self.files[hart_index].lock();
+            let ret = f(&mut file);
+            let asserted = file.interrupting();
+            let changed = (file.asserted != asserted).then_some(asserted);
+            file.asserted = asserted;
+            (ret, changed)
+        };
+        if let Some(asserted) = changed {
+            self.target.set_external_irq(hart_index, asserted);
+        }
+        ret
+    }
+}
+
+impl BaseDeviceOps<GuestPhysAddrRange> for VImsic {
+    fn emu_type(&self) -> EmuDeviceType {
+        EmuDeviceType::InterruptController
+    }
+
+    fn address_range(&self) -> GuestPhysAddrRange {
+        GuestPhysAddrRange::from_start_size(self.addr, self.harts_num * IMSIC_PAGE_SIZE)
+    }
+
+    fn handle_read(&self, addr: GuestPhysAddr, _width: AccessWidth) -> AxResult<usize> {
+        // The MSI pages are write-only.
+        trace!("vImsic read reg {:#x}", addr - self.addr);
+        Ok(0)
+    }
+
+    fn handle_write(&self, addr: GuestPhysAddr, width: AccessWidth, val: usize) -> AxResult {
+        if width != AccessWidth::Dword {
+            return Err(AxError::InvalidInput);
+        }
+        let offset = addr - self.addr;
+        let hart_index = offset / IMSIC_PAGE_SIZE;
+        trace!("vImsic write hart {hart_index} reg {:#x} val {val:#x}", offset % IMSIC_PAGE_SIZE);
+        match offset % IMSIC_PAGE_SIZE {
+            SETEIPNUM_LE_OFFSET => self.send_msi(hart_index, val as u32 as usize),
+            SETEIPNUM_BE_OFFSET => self.send_msi(hart_index, (val as u32).swap_bytes() as usize),
+            _ => Ok(()),
+        }
+    }
+}
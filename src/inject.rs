@@ -0,0 +1,168 @@
+//! Injection and completion core.
+//!
+//! Every way of making a source pending (event channel, hypervisor APIs,
+//! deadlines) ends in `deliver`, and every guest completion
+//! in `finish_complete`, so the bookkeeping lives in one place.
+
+use axaddrspace::HostPhysAddr;
+use axerrno::{AxError, AxResult};
+use log::warn;
+
+use crate::delivery::GuestNotifier;
+use crate::host::HostBackend;
+use crate::{
+    EventChannel, InjectionSource, SourceEvent, TracePoint, VPlicGlobal, PLIC_NUM_SOURCES,
+};
+
+impl VPlicGlobal {
+    /// Attaches a host event channel, replacing any previous one.
+    pub fn attach_event_channel(&self, channel: &'static dyn EventChannel) {
+        *self.event_channel.lock() = Some(channel);
+    }
+
+    /// Detaches the host event channel.
+    pub fn detach_event_channel(&self) {
+        *self.event_channel.lock() = None;
+    }
+
+    /// Drains the injections queued on the attached event channel.
+    ///
+    /// Returns the number of IRQs injected.
+    pub fn poll_event_channel(&self) -> usize {
+        let channel = *self.event_channel.lock();
+        let channel = match channel {
+            Some(channel) => channel,
+            None => return 0,
+        };
+        let mut count = 0;
+        while let Some(irq_id) = channel.next_injection() {
+            if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+                warn!("vPlicGlobal: ignore injection of invalid IRQ {}", irq_id);
+                continue;
+            }
+            self.inject(irq_id, InjectionSource::Device(channel.device_id()));
+            count += 1;
+        }
+        count
+    }
+
+    /// Injects the host interrupt forwarded as `irq_id` into the guest.
+    ///
+    /// This is the hypervisor injection path; guest writes to the pending
+    /// registers are not meant for it.
+    pub fn inject_irq(&self, irq_id: usize) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.inject(irq_id, InjectionSource::HostHardware);
+        Ok(())
+    }
+
+    /// Withdraws a pending `irq_id` before the guest claims it, e.g. when a
+    /// level-triggered device deasserted its line.
+    ///
+    /// Returns `true` if the source was pending.
+    pub fn retract_irq(&self, irq_id: usize) -> AxResult<bool> {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if !self.try_transition(irq_id, SourceEvent::Drop) {
+            return Ok(false);
+        }
+        if self.pending_irqs.is_empty() {
+            self.deassert_vseip();
+        }
+        self.refresh_deliverability();
+        Ok(true)
+    }
+
+    /// Returns `true` if `irq_id` is pending.
+    pub fn is_irq_pending(&self, irq_id: usize) -> bool {
+        irq_id < PLIC_NUM_SOURCES && self.pending_irqs.get(irq_id)
+    }
+
+    /// Injects `irq_id`, subject to error injection and simulated latency.
+    pub(crate) fn inject(&self, irq_id: usize, source: InjectionSource) {
+        if self.injection_passes(irq_id, source) {
+            self.deliver(irq_id, source);
+        }
+    }
+
+    /// Injects `irq_id` like [`VPlicGlobal::inject`], without notifying the
+    /// guest: the caller asserts VSEIP once for a batch of injections.
+    pub(crate) fn inject_quiet(&self, irq_id: usize, source: InjectionSource) {
+        if self.injection_passes(irq_id, source) {
+            let _config = self.enter_config();
+            self.make_pending(irq_id, source);
+        }
+    }
+
+    /// Returns `true` if an injection of `irq_id` is to be delivered now,
+    /// `false` if error injection drops it or simulated latency defers it.
+    fn injection_passes(&self, irq_id: usize, source: InjectionSource) -> bool {
+        self.chaos_filter_injection(irq_id, source) && !self.jitter_defer(irq_id, source)
+    }
+
+    /// Sets `irq_id` pending and asserts VSEIP, unless its gateway holds the
+    /// request.
+    pub(crate) fn deliver(&self, irq_id: usize, source: InjectionSource) {
+        let _config = self.enter_config();
+        if self.make_pending(irq_id, source) {
+            self.assert_vseip();
+            self.refresh_deliverability_of(irq_id);
+        }
+    }
+
+    /// Sets `irq_id` pending with the injection bookkeeping, unless it is
+    /// the reserved source 0 or its gateway holds the request. Returns
+    /// `true` if the source is pending.
+    ///
+    /// The caller is in a configuration section.
+    fn make_pending(&self, irq_id: usize, source: InjectionSource) -> bool {
+        if irq_id == 0 {
+            warn!("vPlicGlobal: drop {:?} injection of reserved source 0", source);
+            return false;
+        }
+        if !self.gateway_accepts(irq_id) {
+            self.note_backlog(irq_id);
+            return false;
+        }
+        let was_pending = self.set_pending(irq_id);
+        self.trace_point(TracePoint::Inject, irq_id, was_pending as usize);
+        self.count_injection(irq_id, was_pending);
+        if was_pending {
+            self.note_backlog(irq_id);
+        }
+        self.tag_injection(irq_id, source, was_pending);
+        true
+    }
+
+    /// Retires `irq_id`: clears its active bit and completes it on the host PLIC.
+    pub(crate) fn finish_complete(&self, irq_id: usize, host_addr: HostPhysAddr) -> AxResult {
+        // Clear the active bit, means the IRQ handling is complete. Guest
+        // completions of IRQs not held were filtered out already.
+        let _ = self.transition(irq_id, SourceEvent::Complete);
+        self.forget_claimant(irq_id);
+        self.stats.count_completion();
+        self.trace_point(TracePoint::Complete, irq_id, 0);
+        self.attribute_complete(irq_id);
+        let channel = *self.event_channel.lock();
+        if let Some(channel) = channel {
+            channel.completed(irq_id);
+        }
+        self.notify_complete(irq_id);
+        self.call_hooks(|hooks| hooks.completed(irq_id, self.source_cookie(irq_id)));
+
+        // Write host PLIC, with the host ID of the source: the guest wrote its
+        // own, which differs once IRQs are remapped. Direct sources are only
+        // completed if the vPLIC claimed them on the host.
+        let ret = match self.complete_target(irq_id) {
+            Some(host_irq) if self.take_direct_claim(irq_id) => {
+                self.host_complete(host_addr, host_irq)
+            }
+            _ => Ok(()),
+        };
+        self.gateway_reopen(irq_id);
+        ret
+    }
+}
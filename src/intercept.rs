@@ -0,0 +1,69 @@
+//! Claim interception.
+//!
+//! The hypervisor can register a [`ClaimInterceptor`] consulted on every
+//! guest claim, once the vPLIC picked the source to return. The interceptor
+//! lets the claim through, substitutes another pending source, or defers the
+//! picked one: the claim then returns 0 and the source stays pending. This
+//! implements policies such as hiding a device while its firmware is being
+//! updated, without tearing down the assignment.
+
+use log::warn;
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Outcome of a claim interception.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimVerdict {
+    /// Claim the source picked by the vPLIC.
+    Allow,
+    /// Claim this source instead, which must be pending; the picked one
+    /// stays pending.
+    Substitute(usize),
+    /// Return 0 to the guest and leave the picked source pending.
+    Defer,
+}
+
+/// Hypervisor policy deciding what a guest claim returns.
+pub trait ClaimInterceptor: Send + Sync {
+    /// Called when `context_id` is about to claim `irq_id`.
+    ///
+    /// Runs with the pending state locked: it must not call back into the
+    /// vPLIC.
+    fn intercept(&self, context_id: usize, irq_id: usize) -> ClaimVerdict;
+}
+
+impl VPlicGlobal {
+    /// Registers the claim interceptor, replacing any previous one.
+    pub fn set_claim_interceptor(&self, interceptor: &'static dyn ClaimInterceptor) {
+        *self.interceptor.lock() = Some(interceptor);
+    }
+
+    /// Unregisters the claim interceptor.
+    pub fn clear_claim_interceptor(&self) {
+        *self.interceptor.lock() = None;
+    }
+
+    /// Runs the claim interceptor on `irq_id`, returning the source to claim
+    /// or `None` if the claim must return 0.
+    pub(crate) fn intercept_claim(&self, context_id: usize, irq_id: usize) -> Option<usize> {
+        let interceptor = match *self.interceptor.lock() {
+            Some(interceptor) => interceptor,
+            None => return Some(irq_id),
+        };
+        match interceptor.intercept(context_id, irq_id) {
+            ClaimVerdict::Allow => Some(irq_id),
+            ClaimVerdict::Substitute(other) if other != 0 && other < PLIC_NUM_SOURCES && self.pending_irqs.get(other) => {
+                Some(other)
+            }
+            ClaimVerdict::Substitute(other) => {
+                warn!(
+                    "vPlicGlobal: claim substitute {} of {} is not pending",
+                    other,
+                    self.source_label(irq_id)
+                );
+                None
+            }
+            ClaimVerdict::Defer => None,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Priority inversion guard for contexts shared with the hypervisor.
+//!
+//! Priorities are global and thresholds gate whole host contexts, so guest
+//! writes forwarded verbatim could rank guest sources above the
+//! hypervisor's, or mask the hypervisor's sources on a hart they share.
+//! Forwarded values are therefore clamped: guest-owned sources always get a
+//! host priority below the lowest hypervisor-owned one, so they lose the
+//! PLIC's tie-break by source ID too, and the host
+//! threshold of a shared context always lets hypervisor-owned sources
+//! through. The guest keeps reading back the values it wrote.
+//!
+//! The lowest hypervisor priority is read from the host when ownership
+//! changes and by [`VPlicGlobal::enforce_priority_guard`], and cached for
+//! the guest writes in between.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+
+use crate::VPlicGlobal;
+
+impl VPlicGlobal {
+    /// Marks `context_id` as backed by a host context the hypervisor also
+    /// takes interrupts on, and reprograms the guarded values.
+    pub fn set_context_shared(&self, context_id: usize, shared: bool) -> AxResult {
+        match self.contexts.lock().get_mut(context_id) {
+            Some(context) => context.shared = shared,
+            None => return Err(AxError::InvalidInput),
+        }
+        self.enforce_priority_guard()
+    }
+
+    /// Returns `true` if `context_id` is shared with the hypervisor.
+    pub fn context_shared(&self, context_id: usize) -> bool {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .is_some_and(|context| context.shared)
+    }
+
+    /// Reprograms the guest priorities and thresholds on the host with the
+    /// guard applied, e.g. after changing the owner or the host priority of
+    /// hypervisor sources.
+    pub fn enforce_priority_guard(&self) -> AxResult {
+        self.refresh_priority_floor();
+        let assigned: Vec<usize> = self.assigned_irqs.lock().into_iter().collect();
+        for irq_id in assigned.into_iter().filter_map(|host_irq| self.guest_irq(host_irq)) {
+            if self.guest_owns(irq_id) {
+                self.set_priority(irq_id, self.priority(irq_id))?;
+            }
+        }
+        for context_id in 0..self.contexts_num {
+            self.set_threshold(context_id, self.threshold(context_id)?)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the host priorities of the hypervisor-owned sources again and
+    /// caches the lowest non-zero one.
+    pub(crate) fn refresh_priority_floor(&self) {
+        let hypervisor: Vec<usize> = self.hypervisor_irqs.lock().into_iter().collect();
+        let floor = hypervisor
+            .into_iter()
+            .map(|irq_id| self.host_irq(irq_id))
+            .filter(|&host_irq| host_irq != 0)
+            .filter_map(|host_irq| self.host_priority(host_irq).ok())
+            .filter(|&priority| priority != 0)
+            .min()
+            .unwrap_or(0);
+        self.priority_floor.store(floor, Ordering::Release);
+    }
+
+    /// Returns the lowest non-zero host priority of the hypervisor-owned
+    /// sources, if any, as last read from the host.
+    fn hypervisor_priority_floor(&self) -> Option<u32> {
+        match self.priority_floor.load(Ordering::Acquire) {
+            0 => None,
+            floor => Some(floor),
+        }
+    }
+
+    /// Clamps the host priority of guest source `irq_id`.
+    pub(crate) fn guard_priority(&self, irq_id: usize, priority: u32) -> u32 {
+        match self.hypervisor_priority_floor() {
+            Some(floor) if self.guest_owns(irq_id) => priority.min(floor - 1),
+            _ => priority,
+        }
+    }
+
+    /// Clamps the host threshold of guest context `context_id`.
+    pub(crate) fn guard_threshold(&self, context_id: usize, threshold: u32) -> u32 {
+        match self.hypervisor_priority_floor() {
+            Some(floor) if self.context_shared(context_id) => threshold.min(floor - 1),
+            _ => threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::PLIC_PRIORITY_OFFSET;
+    use crate::host::HostBackend;
+    use crate::mock::testing::*;
+    use crate::{SourceInfo, SourceOwner, VPlicGlobal};
+
+    /// Creates a mock vPLIC assigned sources 1 and 2, whose source 5 belongs
+    /// to the hypervisor, with host priority 3.
+    fn shared_vplic() -> VPlicGlobal {
+        let vplic = mock_vplic(2);
+        vplic.assign_source(1, SourceInfo::default()).unwrap();
+        vplic.assign_source(2, SourceInfo::default()).unwrap();
+        vplic.host_write(PLIC_PRIORITY_OFFSET + 5 * 4, 3).unwrap();
+        vplic.set_source_owner(5, SourceOwner::Hypervisor).unwrap();
+        vplic
+    }
+
+    #[test]
+    fn guest_priority_is_clamped_to_floor() {
+        let vplic = shared_vplic();
+        write(&vplic, priority_reg(1), 7);
+        assert_eq!(vplic.host_priority(1).unwrap(), 2);
+        assert_eq!(read(&vplic, priority_reg(1)), 7);
+        write(&vplic, priority_reg(2), 3);
+        assert_eq!(vplic.host_priority(2).unwrap(), 2);
+        write(&vplic, priority_reg(2), 1);
+        assert_eq!(vplic.host_priority(2).unwrap(), 1);
+    }
+
+    #[test]
+    fn shared_threshold_lets_hypervisor_through() {
+        let vplic = shared_vplic();
+        vplic.set_context_shared(0, true).unwrap();
+        write(&vplic, threshold_reg(0), 7);
+        assert_eq!(vplic.host_read(vplic.host_threshold_offset(0)).unwrap(), 2);
+        assert_eq!(read(&vplic, threshold_reg(0)), 7);
+        write(&vplic, threshold_reg(1), 7);
+        assert_eq!(vplic.host_read(vplic.host_threshold_offset(1)).unwrap(), 7);
+    }
+
+    #[test]
+    fn floor_is_read_again_on_enforce() {
+        let vplic = shared_vplic();
+        write(&vplic, priority_reg(1), 7);
+        vplic.host_write(PLIC_PRIORITY_OFFSET + 5 * 4, 6).unwrap();
+        write(&vplic, priority_reg(1), 7);
+        assert_eq!(vplic.host_priority(1).unwrap(), 2);
+        vplic.enforce_priority_guard().unwrap();
+        assert_eq!(vplic.host_priority(1).unwrap(), 5);
+        vplic.set_source_owner(5, SourceOwner::Guest).unwrap();
+        vplic.enforce_priority_guard().unwrap();
+        assert_eq!(vplic.host_priority(1).unwrap(), 7);
+    }
+
+    #[test]
+    fn enforce_leaves_unassigned_sources_alone() {
+        let vplic = shared_vplic();
+        vplic.host_write(PLIC_PRIORITY_OFFSET + 9 * 4, 4).unwrap();
+        vplic.enforce_priority_guard().unwrap();
+        assert_eq!(vplic.host_priority(9).unwrap(), 4);
+    }
+}
@@ -0,0 +1,101 @@
+//! Simulated interrupt latency and jitter.
+//!
+//! Adds a fixed and/or pseudo-random delay between an injection and its
+//! delivery to the guest, to reproduce timing-sensitive guest bugs or to
+//! evaluate guest real-time behavior under worst-case virtualization latency.
+//! Delayed injections go through the deadline queue, with deadlines taken
+//! from the HAL clock ([`VPlicHal::now_ns`](crate::VPlicHal::now_ns)), so the
+//! hypervisor must service [`VPlicGlobal::fire_expired`] with the same clock
+//! for them to be delivered.
+
+use core::cmp::Reverse;
+
+use crate::utils::{now_ns, XorShift64};
+use crate::{InjectionSource, VPlicGlobal};
+
+/// Simulated latency settings.
+#[derive(Debug, Clone, Copy)]
+pub struct JitterConfig {
+    /// Fixed delay added to every injection, in nanoseconds.
+    pub base_ns: u64,
+    /// Upper bound of the random extra delay, in nanoseconds; 0 makes the
+    /// delay deterministic.
+    pub jitter_ns: u64,
+    /// Seed of the pseudo-random generator.
+    pub seed: u64,
+}
+
+/// Runtime state of the simulated latency.
+pub(crate) struct Jitter {
+    config: JitterConfig,
+    rng: XorShift64,
+}
+
+impl VPlicGlobal {
+    /// Enables simulated latency with `config`, or disables it with `None`.
+    pub fn set_simulated_latency(&self, config: Option<JitterConfig>) {
+        *self.jitter.lock() = config.map(|config| Jitter {
+            config,
+            rng: XorShift64::new(config.seed),
+        });
+    }
+
+    /// Delays the delivery of `irq_id` if simulated latency is enabled.
+    ///
+    /// Returns `true` if the injection was deferred.
+    pub(crate) fn jitter_defer(&self, irq_id: usize, source: InjectionSource) -> bool {
+        let deadline = {
+            let mut jitter = self.jitter.lock();
+            let jitter = match jitter.as_mut() {
+                Some(jitter) => jitter,
+                None => return false,
+            };
+            let extra = match jitter.config.jitter_ns {
+                0 => 0,
+                jitter_ns => match jitter_ns.checked_add(1) {
+                    Some(bound) => jitter.rng.next_u64() % bound,
+                    None => jitter.rng.next_u64(),
+                },
+            };
+            now_ns().saturating_add(jitter.config.base_ns.saturating_add(extra))
+        };
+        self.scheduled.lock().push(Reverse((deadline, irq_id, source)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JitterConfig;
+    use crate::mock::testing::*;
+
+    #[test]
+    fn huge_delays_saturate() {
+        let vplic = mock_vplic(1);
+        mock_hal().advance(1000);
+        vplic.set_simulated_latency(Some(JitterConfig {
+            base_ns: u64::MAX,
+            jitter_ns: u64::MAX,
+            seed: 1,
+        }));
+        vplic.inject_irq(3).unwrap();
+        assert!(!vplic.is_irq_pending(3));
+        assert_eq!(vplic.next_deadline(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn delay_starts_at_hal_time() {
+        let vplic = mock_vplic(1);
+        mock_hal().advance(1000);
+        vplic.set_simulated_latency(Some(JitterConfig {
+            base_ns: 500,
+            jitter_ns: 0,
+            seed: 1,
+        }));
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(vplic.next_deadline(), Some(1500));
+        assert_eq!(vplic.fire_expired(1499), 0);
+        assert_eq!(vplic.fire_expired(1500), 1);
+        assert!(vplic.is_irq_pending(3));
+    }
+}
@@ -0,0 +1,137 @@
+//! Per-source latency classes.
+//!
+//! Latency-critical sources (e.g. the guest's serial console or a real-time
+//! device) are always delivered immediately and win arbitration ties, while
+//! bulk sources may be queued and delivered in batches.
+
+use axerrno::{AxError, AxResult};
+
+use crate::delivery::GuestNotifier;
+use crate::banks::BankIter;
+use crate::{word_source, InjectionSource, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Latency class of an interrupt source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyClass {
+    /// Injections may be batched.
+    #[default]
+    Bulk,
+    /// Injections are delivered immediately and win arbitration ties.
+    Critical,
+}
+
+impl VPlicGlobal {
+    /// Sets the latency class of `irq_id`.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn set_latency_class(&self, irq_id: usize, class: LatencyClass) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.critical_irqs
+            .lock()
+            .set(irq_id, class == LatencyClass::Critical);
+        Ok(())
+    }
+
+    /// Returns the latency class of `irq_id`.
+    pub fn latency_class(&self, irq_id: usize) -> LatencyClass {
+        if irq_id < PLIC_NUM_SOURCES && self.critical_irqs.lock().get(irq_id) {
+            LatencyClass::Critical
+        } else {
+            LatencyClass::Bulk
+        }
+    }
+
+    /// Queues the host interrupt `irq_id` as pending without asserting
+    /// VSEIP, unless the source is latency-critical. Call
+    /// [`VPlicGlobal::flush_queued`] to deliver the batch.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn queue_irq(&self, irq_id: usize) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if self.latency_class(irq_id) == LatencyClass::Critical {
+            self.inject(irq_id, InjectionSource::HostHardware);
+        } else {
+            self.inject_quiet(irq_id, InjectionSource::HostHardware);
+        }
+        Ok(())
+    }
+
+    /// Delivers the queued batch by asserting VSEIP if anything is pending.
+    pub fn flush_queued(&self) {
+        if !self.pending_irqs.is_empty() {
+            self.assert_vseip();
+            self.refresh_deliverability();
+        }
+    }
+
+    /// Picks the IRQ `context_id` claims among the pending ones.
+    ///
+    /// Among the pending sources enabled for the context, owned by the guest
+    /// and with a priority above the context threshold, the highest priority
+    /// wins; ties go to critical sources, then to the lowest ID or, in FIFO
+    /// order, to the oldest injection.
+    pub(crate) fn pick_pending(&self, context_id: usize) -> Option<usize> {
+        self.pick_pending_in(context_id, u32::MAX)
+    }
+
+    /// Like [`VPlicGlobal::pick_pending`], among the sources of the banks set
+    /// in `banks` only.
+    pub(crate) fn pick_pending_in(&self, context_id: usize, banks: u32) -> Option<usize> {
+        let pending = self.pending_irqs.snapshot();
+        let threshold = self.threshold(context_id).ok()?;
+        let enables = self.enables(context_id);
+        let order = self.claim_order(context_id);
+        let priorities = self.priorities.lock();
+        let critical_irqs = self.critical_irqs.lock();
+        let hypervisor_irqs = self.hypervisor_irqs.lock();
+        let injection_order = self.injection_order.lock();
+        let mut best: Option<(u32, bool, u64, usize)> = None;
+        for bank in BankIter::new(pending.summary() & enables.summary() & banks) {
+            for bit in BankIter::new(pending.word(bank) & enables.word(bank)) {
+                let irq_id = word_source(bank, bit);
+                if hypervisor_irqs.get(irq_id) {
+                    continue;
+                }
+                let priority = priorities[irq_id];
+                if priority <= threshold {
+                    continue;
+                }
+                let critical = critical_irqs.get(irq_id);
+                let key = self.order_key(irq_id, order, &injection_order);
+                // A higher priority wins, then a critical source, then the
+                // lowest key.
+                let better = best.is_none_or(|(p, c, k, _)| {
+                    (priority, critical) > (p, c) || ((priority, critical) == (p, c) && key < k)
+                });
+                if better {
+                    best = Some((priority, critical, key, irq_id));
+                }
+            }
+        }
+        best.map(|(_, _, _, irq_id)| irq_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+
+    #[test]
+    fn queued_injections_are_counted_and_flushed() {
+        let vplic = mock_vplic(1);
+        let hal = mock_hal();
+        route(&vplic, 0, 5, 1);
+        vplic.queue_irq(5).unwrap();
+        vplic.queue_irq(5).unwrap();
+        assert!(vplic.is_irq_pending(5));
+        assert!(!hal.vseip());
+        assert_eq!(vplic.stats().injections, 2);
+        vplic.flush_queued();
+        assert!(hal.vseip());
+        assert_eq!(claim(&vplic, 0), 5);
+    }
+}
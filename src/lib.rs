@@ -1,18 +1,145 @@
 #![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+
+mod affinity;
+mod aplic;
+mod attribution;
+mod audit;
+mod backpressure;
+mod banks;
+mod bridge;
+mod builder;
+mod caps;
+mod chaos;
+mod claim;
+mod combine;
 mod consts;
+mod cookie;
+mod context;
+mod debounce;
+mod delivery;
+mod devmodel;
+mod direct;
+mod emulated;
+mod enable;
+mod error;
+mod forward;
+mod gateway;
+mod generation;
+mod group;
+mod hint;
+mod hal;
+mod hart;
+mod hartmode;
+mod history;
+mod hooks;
+mod imsic;
+mod inject;
+mod intercept;
+mod inversion;
+mod host;
+mod jitter;
+mod latency;
+mod lifecycle;
+mod line;
+mod lock;
+mod mirror;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod msi;
+mod names;
+mod nested;
+mod ordering;
+mod owner;
+mod pause;
+mod power;
+mod preempt;
+mod priority;
+mod provenance;
+mod pv;
+mod quiesce;
+mod regmap;
+mod regs;
+mod remap;
+mod remote;
+mod sched;
+mod schedule;
+mod selftest;
+mod snapshot;
+mod stats;
+mod table;
+mod timeout;
+mod tracepoint;
 mod utils;
+mod w1c;
+mod waker;
 
+pub use aplic::{VAplic, VAplicTarget};
+pub use attribution::{HostIrqLatency, LatencySummary};
+pub use audit::{AuditFinding, AuditReport};
+pub use banks::{
+    source_bit, source_word, word_source, AtomicBitmap, BankedBitmap, PLIC_BANK_SIZE, PLIC_NUM_BANKS,
+};
+pub use bridge::EventChannel;
+pub use builder::VPlicBuilder;
+pub use caps::{CAP_AIA_BACKEND, CAP_DIRECT_MAP, CAP_MIGRATION, CAP_REMAP};
+pub use chaos::ChaosConfig;
+pub use claim::BackToBackClaim;
 pub use consts::*;
+pub use context::ContextSnapshot;
+pub use delivery::{
+    DeliveryBackend, DeliveryMode, GuestFileBackend, HvictlBackend, HvipBackend, RecordingBackend,
+    DEFAULT_DELIVERY_RETRIES,
+};
+pub use devmodel::h_extension_present;
+pub use error::VPlicError;
+pub use forward::HostIrqRegistry;
+pub use group::{VPlicGroup, MAX_GROUP_INSTANCES};
+pub use hal::{set_hal, VPlicHal};
+#[cfg(feature = "axvisor")]
+pub use hal::AxvisorHal;
+pub use hart::VPlicHart;
+pub use hartmode::HartMode;
+pub use hint::{PendingHint, HINT_MAGIC, HINT_MAX_CONTEXTS, HINT_VERSION};
+pub use history::{ClaimRecord, CLAIM_HISTORY_LEN};
+pub use hooks::VPlicHooks;
+pub use imsic::{VImsic, VImsicTarget, IMSIC_MAX_IDS, IMSIC_PAGE_SIZE};
+pub use intercept::{ClaimInterceptor, ClaimVerdict};
+pub use jitter::JitterConfig;
+pub use latency::LatencyClass;
+pub use lifecycle::{SourceEvent, SourceState};
+pub use line::IrqLine;
+pub use mirror::{MirrorPage, MIRROR_MAGIC, MIRROR_VERSION};
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockHal;
+pub use ordering::ClaimOrder;
+pub use owner::SourceOwner;
+pub use pause::PausedClaim;
+pub use provenance::InjectionSource;
+pub use pv::*;
+pub use quiesce::{panic_quiesce_all, MAX_QUIESCE_INSTANCES};
+pub use remote::HartRouter;
+pub use selftest::{SelfTestFailure, SelfTestReport};
+pub use snapshot::{SavedContext, VPlicState};
+pub use stats::VPlicStats;
+pub use table::{SourceDesc, SourceInfo, SourceMode, Trigger};
+pub use tracepoint::TracePoint;
+pub use w1c::PendingW1c;
+pub use waker::CompleteFuture;
 
+use alloc::vec;
+use alloc::vec::Vec;
 use core::option::Option;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize};
 
 use axaddrspace::{device::AccessWidth, GuestPhysAddr, GuestPhysAddrRange, HostPhysAddr};
 use axdevice_base::{BaseDeviceOps, EmuDeviceType};
 use bitmaps::Bitmap;
 use consts::*;
-use utils::*;
-use spin::Mutex;
+use host::HostBackend;
+use lock::Mutex;
+use log::{trace, warn};
 
 pub struct VPlicGlobal {
     /// The address of the VPlicGlobal in the guest physical address space.
@@ -24,15 +151,175 @@ pub struct VPlicGlobal {
     /// IRQs assigned to this VPlicGlobal.
     pub assigned_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
     /// Pending IRQs for this VPlicGlobal.
-    pub pending_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    pub pending_irqs: AtomicBitmap,
     /// Active IRQs for this VPlicGlobal.
-    pub active_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    pub active_irqs: AtomicBitmap,
     /// The host physical address of the PLIC.
     pub host_plic_addr: HostPhysAddr,
+    /// Per-context state.
+    contexts: Mutex<Vec<context::ContextState>>,
+    /// Per-source context holding its claim, if known.
+    claimants: Mutex<Vec<Option<usize>>>,
+    /// Channel to an out-of-core device model, if attached.
+    event_channel: Mutex<Option<&'static dyn EventChannel>>,
+    /// Latency-critical IRQs.
+    critical_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Injections scheduled for a later deadline.
+    scheduled: Mutex<schedule::DeadlineQueue>,
+    /// Sequence counter of the published mirror page.
+    mirror_generation: AtomicU64,
+    /// Error-injection state, if enabled.
+    chaos: Mutex<Option<chaos::Chaos>>,
+    /// Simulated latency state, if enabled.
+    jitter: Mutex<Option<jitter::Jitter>>,
+    /// Whether the guest polls instead of being notified through VSEIP.
+    polling: AtomicBool,
+    /// How the guest is notified about pending interrupts.
+    backend: Mutex<&'static dyn DeliveryBackend>,
+    /// Whether guest notification is held back (e.g. during early boot).
+    delivery_gated: AtomicBool,
+    /// Consecutive failed notification attempts.
+    failed_deliveries: AtomicU32,
+    /// Failed notification attempts tolerated before dropping.
+    max_delivery_retries: AtomicU32,
+    /// Hypervisor callbacks, if registered.
+    hooks: Mutex<Option<&'static dyn VPlicHooks>>,
+    /// Harts currently running a hook, one bit per hart ID.
+    hook_harts: AtomicU64,
+    /// Sources masked together with their target vCPU.
+    mask_groups: Mutex<Vec<power::MaskGroup>>,
+    /// Event counters.
+    stats: stats::Stats,
+    /// Whether a claim returns 0 while the previous one is in flight.
+    claim_zero_in_flight: AtomicBool,
+    /// Whether claims leaving more eligible IRQs keep VSEIP asserted.
+    claim_batching: AtomicBool,
+    /// Claim timeout in nanoseconds, 0 if disabled.
+    claim_timeout_ns: AtomicU64,
+    /// Per-context claim history.
+    histories: Mutex<Vec<history::ClaimHistory>>,
+    /// Offset of the paravirtual page, 0 if disabled.
+    pv_offset: AtomicUsize,
+    /// Number of virtual-only sources announced on the paravirtual page.
+    pv_virtual_sources: AtomicU32,
+    /// Paravirtual features negotiated by the guest.
+    pv_features: AtomicU32,
+    /// Whether the VMM paused the device.
+    paused: AtomicBool,
+    /// Whether the device looks all-masked to the guest.
+    guest_masked: AtomicBool,
+    /// Whether every source of the VM is masked in the host enables.
+    host_masked: AtomicBool,
+    /// What claims return while the device is paused.
+    paused_claim: Mutex<PausedClaim>,
+    /// Number of claims in progress.
+    claims_in_progress: AtomicUsize,
+    /// Per-vCPU deliverability last reported to the hooks.
+    deliverable_vcpus: Mutex<Vec<bool>>,
+    /// Per-source count of injections collapsed into a pending IRQ.
+    overflows: Mutex<Vec<u32>>,
+    /// Sources owned by the hypervisor.
+    hypervisor_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Lowest host priority of the hypervisor-owned sources, 0 if none.
+    priority_floor: AtomicU32,
+    /// Per-source origin of the injection that made it pending.
+    provenance: Mutex<Vec<Option<InjectionSource>>>,
+    /// Shadow of the source priorities.
+    priorities: Mutex<[u32; PLIC_NUM_SOURCES]>,
+    /// Translation between guest and host source numbers.
+    irq_map: Mutex<remap::IrqMap>,
+    /// Per-source count of completions, for completion futures.
+    completion_seqs: Mutex<Vec<u64>>,
+    /// Tasks waiting for completions.
+    complete_wakers: Mutex<waker::CompleteWakers>,
+    /// Period of the periodic audit in nanoseconds, 0 if disabled.
+    audit_interval_ns: AtomicU64,
+    /// Time of the next periodic audit.
+    next_audit_ns: AtomicU64,
+    /// Opaque per-source cookies.
+    cookies: Mutex<Vec<Option<usize>>>,
+    /// Per-source names.
+    source_names: Mutex<Vec<Option<&'static str>>>,
+    /// Handling of write-1-to-clear attempts on the pending registers.
+    pending_w1c: Mutex<PendingW1c>,
+    /// Number of write-1-to-clear attempts seen.
+    w1c_attempts: AtomicU64,
+    /// Whether guest writes to the pending registers inject interrupts.
+    guest_self_injection: AtomicBool,
+    /// Static descriptions of the assigned sources.
+    source_infos: Mutex<table::SourceInfos>,
+    /// Software register file replacing the host PLIC, in emulated mode.
+    emulated_regs: Option<Mutex<emulated::EmulatedRegs>>,
+    /// Claim interceptor registered by the hypervisor.
+    interceptor: Mutex<Option<&'static dyn ClaimInterceptor>>,
+    /// Guest page holding the pending hints, if enabled.
+    hint_page: Mutex<Option<HostPhysAddr>>,
+    /// Sequence counter of the pending-hint page.
+    hint_generation: AtomicU64,
+    /// Router of guest interrupts to other harts, if registered.
+    hart_router: Mutex<Option<&'static dyn HartRouter>>,
+    /// Configuration generation, odd while reconfiguring.
+    config_generation: AtomicU64,
+    /// Number of operations running against the current configuration.
+    config_users: AtomicUsize,
+    /// Latency of the forwarded host interrupts.
+    host_latency: Mutex<attribution::HostLatency>,
+    /// Edge-triggered sources; the others are level-triggered.
+    edge_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Level lines driven asserted by emulated devices.
+    asserted_lines: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Level sources requested while their gateway was closed.
+    held_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Per-source edges latched by the gateway.
+    latched_edges: Mutex<Vec<u32>>,
+    /// Whether claimed level sources are masked while their vCPU is descheduled.
+    preempt_masking: AtomicBool,
+    /// Sources masked while their vCPU is descheduled, with that vCPU.
+    preempt_masked: Mutex<Vec<(usize, usize)>>,
+    /// Host interrupt subsystem used to bind host sources, if set.
+    irq_registry: Mutex<Option<&'static dyn HostIrqRegistry>>,
+    /// Host sources forwarded automatically.
+    bound_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Backlog at which a source is back-pressured, 0 if disabled.
+    backpressure_threshold: AtomicU32,
+    /// Per-source injections not consumed by the guest yet.
+    backlog: Mutex<Vec<u32>>,
+    /// Next injection sequence number.
+    injection_seq: AtomicU64,
+    /// Per-source sequence number of the injection that made it pending.
+    injection_order: Mutex<Vec<u64>>,
+    /// Per-source number of shared-line contributors raising it.
+    line_levels: Mutex<Vec<u32>>,
+    /// Per-source affinity hint last reported to the hooks.
+    affinity_hints: Mutex<Vec<Option<usize>>>,
+    /// Routes of MSI writes to wired sources.
+    msi_routes: Mutex<msi::MsiRoutes>,
+    /// Sources delivered through guest external interrupt lines.
+    direct_irqs: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Direct sources claimed on the host and not completed yet.
+    direct_claims: Mutex<Bitmap<{ PLIC_NUM_SOURCES }>>,
+    /// Per-vCPU guest external interrupt line, if any.
+    direct_lines: Mutex<Vec<Option<usize>>>,
+    /// Enable debounce window in nanoseconds, 0 if disabled.
+    enable_debounce_ns: AtomicU64,
+    /// Guest enable writes not programmed into the host yet.
+    dirty_enables: Mutex<debounce::DirtyEnables>,
 }
 
 impl VPlicGlobal {
     pub fn new(addr: GuestPhysAddr, size: Option<usize>, contexts_num: usize) -> Self {
+        Self::new_with_host(addr, size, contexts_num, HostPhysAddr::from_usize(addr.as_usize()))
+    }
+
+    /// Creates a vPLIC seen by the guest at `addr`, backed by the host PLIC
+    /// at `host_plic_addr`. Guest register offsets are forwarded to the same
+    /// offsets from the host base.
+    pub fn new_with_host(
+        addr: GuestPhysAddr,
+        size: Option<usize>,
+        contexts_num: usize,
+        host_plic_addr: HostPhysAddr,
+    ) -> Self {
         let addr_end = addr.as_usize()
             + contexts_num * PLIC_CONTEXT_STRIDE
             + PLIC_CONTEXT_CTRL_OFFSET
@@ -49,13 +336,102 @@ impl VPlicGlobal {
             addr,
             size,
             assigned_irqs: Mutex::new(Bitmap::new()),
-            pending_irqs: Mutex::new(Bitmap::new()),
-            active_irqs: Mutex::new(Bitmap::new()),
+            pending_irqs: AtomicBitmap::new(),
+            active_irqs: AtomicBitmap::new(),
             contexts_num,
-            host_plic_addr: HostPhysAddr::from_usize(addr.as_usize()), // Currently we assume host_plic_addr = guest_vplic_addr
+            host_plic_addr,
+            contexts: Mutex::new((0..contexts_num).map(context::ContextState::new).collect()),
+            claimants: Mutex::new(vec![None; PLIC_NUM_SOURCES]),
+            event_channel: Mutex::new(None),
+            critical_irqs: Mutex::new(Bitmap::new()),
+            scheduled: Mutex::new(schedule::DeadlineQueue::new()),
+            mirror_generation: AtomicU64::new(0),
+            chaos: Mutex::new(None),
+            jitter: Mutex::new(None),
+            polling: AtomicBool::new(false),
+            backend: Mutex::new(&delivery::HVIP_BACKEND),
+            delivery_gated: AtomicBool::new(false),
+            failed_deliveries: AtomicU32::new(0),
+            max_delivery_retries: AtomicU32::new(delivery::DEFAULT_DELIVERY_RETRIES),
+            hooks: Mutex::new(None),
+            hook_harts: AtomicU64::new(0),
+            mask_groups: Mutex::new(Vec::new()),
+            stats: stats::Stats::default(),
+            claim_zero_in_flight: AtomicBool::new(false),
+            claim_batching: AtomicBool::new(false),
+            claim_timeout_ns: AtomicU64::new(0),
+            histories: Mutex::new(vec![history::ClaimHistory::default(); contexts_num]),
+            pv_offset: AtomicUsize::new(0),
+            pv_virtual_sources: AtomicU32::new(0),
+            pv_features: AtomicU32::new(0),
+            paused: AtomicBool::new(false),
+            guest_masked: AtomicBool::new(false),
+            host_masked: AtomicBool::new(false),
+            paused_claim: Mutex::new(PausedClaim::Zero),
+            claims_in_progress: AtomicUsize::new(0),
+            deliverable_vcpus: Mutex::new(vec![false; contexts_num]),
+            overflows: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            hypervisor_irqs: Mutex::new(Bitmap::new()),
+            priority_floor: AtomicU32::new(0),
+            provenance: Mutex::new(vec![None; PLIC_NUM_SOURCES]),
+            priorities: Mutex::new([0; PLIC_NUM_SOURCES]),
+            irq_map: Mutex::new(remap::IrqMap::identity()),
+            completion_seqs: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            complete_wakers: Mutex::new(Vec::new()),
+            audit_interval_ns: AtomicU64::new(0),
+            next_audit_ns: AtomicU64::new(0),
+            cookies: Mutex::new(vec![None; PLIC_NUM_SOURCES]),
+            source_names: Mutex::new(vec![None; PLIC_NUM_SOURCES]),
+            pending_w1c: Mutex::new(PendingW1c::Inject),
+            w1c_attempts: AtomicU64::new(0),
+            guest_self_injection: AtomicBool::new(false),
+            source_infos: Mutex::new(table::SourceInfos::new()),
+            emulated_regs: None,
+            interceptor: Mutex::new(None),
+            hint_page: Mutex::new(None),
+            hint_generation: AtomicU64::new(0),
+            hart_router: Mutex::new(None),
+            config_generation: AtomicU64::new(0),
+            config_users: AtomicUsize::new(0),
+            host_latency: Mutex::new(attribution::HostLatency::new()),
+            edge_irqs: Mutex::new(Bitmap::new()),
+            asserted_lines: Mutex::new(Bitmap::new()),
+            held_irqs: Mutex::new(Bitmap::new()),
+            latched_edges: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            preempt_masking: AtomicBool::new(false),
+            preempt_masked: Mutex::new(Vec::new()),
+            irq_registry: Mutex::new(None),
+            bound_irqs: Mutex::new(Bitmap::new()),
+            backpressure_threshold: AtomicU32::new(0),
+            backlog: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            injection_seq: AtomicU64::new(0),
+            injection_order: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            line_levels: Mutex::new(vec![0; PLIC_NUM_SOURCES]),
+            affinity_hints: Mutex::new(vec![None; PLIC_NUM_SOURCES]),
+            msi_routes: Mutex::new(Vec::new()),
+            direct_irqs: Mutex::new(Bitmap::new()),
+            direct_claims: Mutex::new(Bitmap::new()),
+            direct_lines: Mutex::new(vec![None; contexts_num]),
+            enable_debounce_ns: AtomicU64::new(0),
+            dirty_enables: Mutex::new(debounce::DirtyEnables::new(contexts_num)),
         }
     }
 
+    /// Returns the banks holding at least one source enabled for `context_id`,
+    /// none if it does not exist.
+    pub fn enabled_banks(&self, context_id: usize) -> u32 {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or(0, |context| context.enables.summary())
+    }
+
+    /// Returns the banks holding at least one pending source enabled for
+    /// `context_id`, none if it does not exist.
+    pub fn eligible_banks(&self, context_id: usize) -> u32 {
+        self.pending_irqs.summary() & self.enabled_banks(context_id)
+    }
+
     // pub fn assign_irq(&self, irq: u32, cpu_phys_id: usize, target_cpu_affinity: (u8, u8, u8, u8)) {
     //     warn!(
     //         "Assigning IRQ {} to vGICD at addr {:#x} for CPU phys id {} is not supported yet",
@@ -78,59 +454,41 @@ impl BaseDeviceOps<GuestPhysAddrRange> for VPlicGlobal {
         addr: <GuestPhysAddrRange as axaddrspace::device::DeviceAddrRange>::Addr,
         width: axaddrspace::device::AccessWidth,
     ) -> axerrno::AxResult<usize> {
-        assert_eq!(width, AccessWidth::Dword);
-        let reg = addr - self.addr;
-        let host_addr = HostPhysAddr::from_usize(reg + self.host_plic_addr.as_usize());
-        // info!("vPlicGlobal read reg {reg:#x} width {width:?}");
-        match reg {
-            // priority
-            PLIC_PRIORITY_OFFSET..PLIC_PENDING_OFFSET => {
-                perform_mmio_read(host_addr, width)
-            }
-            // pending
-            PLIC_PENDING_OFFSET..PLIC_ENABLE_OFFSET => {
-                let reg_index = reg - PLIC_PENDING_OFFSET / 4;
-                let bit_index_start = reg_index * 32;
-                let mut val: u32 = 0;
-                let mut bit_mask: u32 = 1;
-                let pending_irqs = self.pending_irqs.lock();
-                for i in 0..32 {
-                    if pending_irqs.get(bit_index_start + i as usize) {
-                        val |= bit_mask;
-                    }
-                    bit_mask <<= 1;
+        if width == AccessWidth::Qword {
+            let reg = addr - self.addr;
+            return match regmap::decode_qword(reg, |reg| self.host_reg(reg), self.contexts_num) {
+                Some(halves) => {
+                    trace!("vPlicGlobal Qword read {:?} reg {reg:#x}", halves[0].0.kind);
+                    self.read_qword(&halves).map_err(error::guest_access_error)
                 }
-                Ok(val as usize)
-            }
-            // enable
-            PLIC_ENABLE_OFFSET..PLIC_CONTEXT_CTRL_OFFSET => {
-                perform_mmio_read(host_addr, width)
+                None => {
+                    warn!("vPlicGlobal: unsupported Qword read of reg {reg:#x}");
+                    Err(axerrno::AxError::InvalidInput)
+                }
+            };
+        }
+        let (reg, lane) = match regmap::Lane::of(addr - self.addr, width) {
+            Some(found) => found,
+            None => {
+                warn!("vPlicGlobal: unsupported {width:?} read at {:#x}", addr - self.addr);
+                return Err(axerrno::AxError::InvalidInput);
             }
-            // threshold
-            offset if offset >= PLIC_CONTEXT_CTRL_OFFSET && (offset - PLIC_CONTEXT_CTRL_OFFSET) % PLIC_CONTEXT_STRIDE == 0 => {
-                perform_mmio_read(host_addr, width)
+        };
+        let host_addr = self.host_reg(reg);
+        if let Some(pv_reg) = self.pv_reg(reg) {
+            if !lane.is_full() {
+                return Err(axerrno::AxError::InvalidInput);
             }
-            // claim/complete
-            offset if offset >= PLIC_CONTEXT_CTRL_OFFSET && (offset - PLIC_CONTEXT_CTRL_OFFSET - PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET) % PLIC_CONTEXT_STRIDE == 0 =>
-            {
-                let context_id = (offset - PLIC_CONTEXT_CTRL_OFFSET - PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET) / PLIC_CONTEXT_STRIDE;
-                assert!(context_id < self.contexts_num, "Invalid context id {}", context_id);
-                let mut pending_irqs = self.pending_irqs.lock();
-                let irq_id = match pending_irqs.first_index() {
-                    Some(id) => id,
-                    None => return Ok(0),
-                };
-                
-                // Check if the IRQ is belong to this context_id, check if is enabled, etc.
-                // TODO: check enable bit and priority, threshold. 
-
-                // Clear the pending bit and set the active bit, means the IRQ is being handling.
-                pending_irqs.set(irq_id, false);
-                self.active_irqs.lock().set(irq_id, true);
-                Ok(irq_id as usize)
+            return self.pv_read(pv_reg).map_err(error::guest_access_error);
+        }
+        match regmap::decode(reg, AccessWidth::Dword, host_addr, self.contexts_num) {
+            Some((desc, access)) => {
+                trace!("vPlicGlobal {width:?} read {:?} reg {reg:#x}", desc.kind);
+                self.read_lane(desc, &access, lane).map_err(error::guest_access_error)
             }
-            _ => {
-                unimplemented!("Unsupported vPlicGlobal read for reg {reg:#x}")
+            None => {
+                warn!("vPlicGlobal: unsupported read of reg {reg:#x}");
+                Err(axerrno::AxError::InvalidInput)
             }
         }
     }
@@ -141,68 +499,41 @@ impl BaseDeviceOps<GuestPhysAddrRange> for VPlicGlobal {
         width: axaddrspace::device::AccessWidth,
         val: usize,
     ) -> axerrno::AxResult {
-        assert_eq!(width, AccessWidth::Dword);
-        let reg = addr - self.addr;
-        let host_addr = HostPhysAddr::from_usize(reg + self.host_plic_addr.as_usize());
-        // info!("vPlicGlobal write reg {reg:#x} width {width:?} val {val:#x}");
-        match reg {
-            // priority
-            PLIC_PRIORITY_OFFSET..PLIC_PENDING_OFFSET => {
-                perform_mmio_write(host_addr, width, val)
-            }
-            // pending (Here is uesd for hyperivosr to inject pending IRQs, later should move it to a separate interface)
-            PLIC_PENDING_OFFSET..PLIC_ENABLE_OFFSET => {
-                // Note: here append, not overwrite.
-                let reg_index = (reg - PLIC_PENDING_OFFSET) / 4;
-                let val = val as u32;
-                let mut bit_mask: u32 = 1;
-                let mut pending_irqs = self.pending_irqs.lock();
-                for i in 0..32 {
-                    if (val & bit_mask) != 0 {
-                        let irq_id = reg_index * 32 + i;
-                        // Set the pending bit.
-                        pending_irqs.set(irq_id as usize, true);
-                        // info!("vPlicGlobal: IRQ {} set to pending", irq_id);
-                    }
-                    bit_mask <<= 1;
+        if width == AccessWidth::Qword {
+            let reg = addr - self.addr;
+            return match regmap::decode_qword(reg, |reg| self.host_reg(reg), self.contexts_num) {
+                Some(halves) => {
+                    trace!("vPlicGlobal Qword write {:?} reg {reg:#x}", halves[0].0.kind);
+                    self.write_qword(&halves, val).map_err(error::guest_access_error)
                 }
-
-                // Inject the interrupt to the hart by setting the VSEIP bit in HVIP register.
-                if pending_irqs.is_empty() == false {
-                    unsafe {riscv_h::register::hvip::set_vseip(); }
+                None => {
+                    warn!("vPlicGlobal: unsupported Qword write of reg {reg:#x}");
+                    Err(axerrno::AxError::InvalidInput)
                 }
-
-                Ok(())
-            }
-            // enable
-            PLIC_ENABLE_OFFSET..PLIC_CONTEXT_CTRL_OFFSET => {
-                perform_mmio_write(host_addr, width, val)
+            };
+        }
+        let (reg, lane) = match regmap::Lane::of(addr - self.addr, width) {
+            Some(found) => found,
+            None => {
+                warn!("vPlicGlobal: unsupported {width:?} write at {:#x}", addr - self.addr);
+                return Err(axerrno::AxError::InvalidInput);
             }
-            // threshold
-            offset if offset >= PLIC_CONTEXT_CTRL_OFFSET && (offset - PLIC_CONTEXT_CTRL_OFFSET) % PLIC_CONTEXT_STRIDE == 0 => {
-                perform_mmio_write(host_addr, width, val)
+        };
+        let host_addr = self.host_reg(reg);
+        if let Some(pv_reg) = self.pv_reg(reg) {
+            if !lane.is_full() {
+                return Err(axerrno::AxError::InvalidInput);
             }
-            // claim/complete
-            offset if offset >= PLIC_CONTEXT_CTRL_OFFSET && (offset - PLIC_CONTEXT_CTRL_OFFSET - PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET) % PLIC_CONTEXT_STRIDE == 0 =>
-            {
-                // info!("vPlicGlobal: Writing to CLAIM/COMPLETE reg {reg:#x} val {val:#x}");
-                let context_id = (offset - PLIC_CONTEXT_CTRL_OFFSET - PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET) / PLIC_CONTEXT_STRIDE;
-                assert!(context_id < self.contexts_num, "Invalid context id {}", context_id);
-                let irq_id = val;
-
-                // There is no irq to handle.
-                if self.pending_irqs.lock().is_empty() {
-                    unsafe { riscv_h::register::hvip::clear_vseip(); }
-                }
-
-                // Clear the active bit, means the IRQ handling is complete.
-                self.active_irqs.lock().set(irq_id, false);
-
-                // Write host PLIC.
-                perform_mmio_write(host_addr, width, irq_id)
+            return self.pv_write(pv_reg, val).map_err(error::guest_access_error);
+        }
+        match regmap::decode(reg, AccessWidth::Dword, host_addr, self.contexts_num) {
+            Some((desc, access)) => {
+                trace!("vPlicGlobal {width:?} write {:?} reg {reg:#x} val {val:#x}", desc.kind);
+                self.write_lane(desc, &access, lane, val).map_err(error::guest_access_error)
             }
-            _ => {
-                unimplemented!("Unsupported vPlicGlobal read for reg {reg:#x}")
+            None => {
+                warn!("vPlicGlobal: unsupported write of reg {reg:#x} val {val:#x}");
+                Err(axerrno::AxError::InvalidInput)
             }
         }
     }
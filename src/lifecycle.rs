@@ -0,0 +1,176 @@
+//! Lifecycle of an interrupt source.
+//!
+//! The pending and active bitmaps together encode the state of each source.
+//! Every change goes through a single transition function, which validates it
+//! against the state machine below, so an illegal transition (e.g. completing
+//! an IRQ that was never claimed) is detected and reported in one place.
+//!
+//! The bitmaps are atomic and no lock serializes transitions: each event
+//! consumes or sets a single bit with one atomic operation (Claim and Drop
+//! clear the pending bit, Complete and Release the active bit, Inject sets
+//! the pending bit), so of two racing claims of a source only one succeeds.
+//! An illegal event finds its bit clear and changes nothing. A claim also
+//! sets the active bit, together with clearing the pending bit under the
+//! lock the gateway tests the source under.
+//!
+//! | State         | Inject        | Claim  | Complete / Release | Drop     |
+//! |---------------|---------------|--------|--------------------|----------|
+//! | Inactive      | Pending       | -      | -                  | -        |
+//! | Pending       | Pending       | Active | -                  | Inactive |
+//! | Active        | PendingActive | -      | Inactive           | -        |
+//! | PendingActive | PendingActive | Active | Pending            | Active   |
+//!
+//! The reserved source 0 stays Inactive: injecting it is illegal.
+
+use axerrno::{AxError, AxResult};
+use log::warn;
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// State of an interrupt source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceState {
+    /// Neither pending nor claimed.
+    Inactive,
+    /// Waiting to be claimed.
+    Pending,
+    /// Claimed, not completed yet.
+    Active,
+    /// Claimed, and pending again (e.g. a level source still asserted).
+    PendingActive,
+}
+
+/// Event changing the state of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceEvent {
+    /// The source was injected.
+    Inject,
+    /// The guest claimed the source.
+    Claim,
+    /// The guest completed the source.
+    Complete,
+    /// The source was handed back without completion (see
+    /// [`VPlicGlobal::reinject`]).
+    Release,
+    /// The pending injection was discarded as undeliverable.
+    Drop,
+}
+
+impl SourceState {
+    fn from_bits(pending: bool, active: bool) -> Self {
+        match (pending, active) {
+            (false, false) => Self::Inactive,
+            (true, false) => Self::Pending,
+            (false, true) => Self::Active,
+            (true, true) => Self::PendingActive,
+        }
+    }
+
+    /// Returns `true` if the source waits to be claimed.
+    pub fn is_pending(self) -> bool {
+        matches!(self, Self::Pending | Self::PendingActive)
+    }
+
+    /// Returns `true` if the source is claimed.
+    pub fn is_active(self) -> bool {
+        matches!(self, Self::Active | Self::PendingActive)
+    }
+
+    /// Returns the state after `event`, or `None` if the transition is
+    /// illegal.
+    pub fn next(self, event: SourceEvent) -> Option<Self> {
+        use SourceEvent::*;
+        use SourceState::*;
+        match (self, event) {
+            (Inactive | Pending, Inject) => Some(Pending),
+            (Active | PendingActive, Inject) => Some(PendingActive),
+            (Pending | PendingActive, Claim) => Some(Active),
+            (Active, Complete | Release) => Some(Inactive),
+            (PendingActive, Complete | Release) => Some(Pending),
+            (Pending, Drop) => Some(Inactive),
+            (PendingActive, Drop) => Some(Active),
+            _ => None,
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Returns the state of `irq_id`.
+    pub fn source_state(&self, irq_id: usize) -> SourceState {
+        if irq_id >= PLIC_NUM_SOURCES {
+            return SourceState::Inactive;
+        }
+        let pending = self.pending_irqs.get(irq_id);
+        let active = self.active_irqs.get(irq_id);
+        SourceState::from_bits(pending, active)
+    }
+
+    /// Applies `event` to `irq_id`.
+    ///
+    /// Returns the previous state. Illegal transitions leave the state
+    /// untouched, are reported and fail with [`AxError::BadState`].
+    pub(crate) fn transition(&self, irq_id: usize, event: SourceEvent) -> AxResult<SourceState> {
+        match self.apply_event(irq_id, event) {
+            Ok(state) => Ok(state),
+            Err(state) => {
+                warn!(
+                    "vPlicGlobal: illegal {:?} of {} in state {:?}",
+                    event,
+                    self.source_label(irq_id),
+                    state
+                );
+                self.stats.count_illegal_transition();
+                Err(AxError::BadState)
+            }
+        }
+    }
+
+    /// Applies `event` to `irq_id` if it is legal, returning `false` without
+    /// reporting it otherwise: for events that race with others, such as two
+    /// harts claiming the same source.
+    pub(crate) fn try_transition(&self, irq_id: usize, event: SourceEvent) -> bool {
+        self.apply_event(irq_id, event).is_ok()
+    }
+
+    /// Applies `event` to the bits of `irq_id`, returning the previous state,
+    /// as an error if the transition is illegal.
+    fn apply_event(&self, irq_id: usize, event: SourceEvent) -> Result<SourceState, SourceState> {
+        let (pending, active) = (&self.pending_irqs, &self.active_irqs);
+        // Source 0 is reserved: its pending bit is hardwired to 0.
+        if irq_id == 0 && event == SourceEvent::Inject {
+            return Err(SourceState::Inactive);
+        }
+        let state = match event {
+            SourceEvent::Inject => SourceState::from_bits(pending.set(irq_id, true), active.get(irq_id)),
+            // Under the gateway lock, so a request never finds the source
+            // neither pending nor active mid-claim.
+            SourceEvent::Claim => self.with_gateway_locked(irq_id, || {
+                let state = SourceState::from_bits(pending.set(irq_id, false), active.get(irq_id));
+                if state.is_pending() {
+                    active.set(irq_id, true);
+                }
+                state
+            }),
+            SourceEvent::Drop => SourceState::from_bits(pending.set(irq_id, false), active.get(irq_id)),
+            SourceEvent::Complete | SourceEvent::Release => {
+                SourceState::from_bits(pending.get(irq_id), active.set(irq_id, false))
+            }
+        };
+        let next = state.next(event).ok_or(state)?;
+        if next.is_pending() != state.is_pending() {
+            self.publish_hint();
+        }
+        Ok(state)
+    }
+
+    /// Injects `irq_id`, returning `true` if it was already pending.
+    pub(crate) fn set_pending(&self, irq_id: usize) -> bool {
+        let was_pending = self
+            .transition(irq_id, SourceEvent::Inject)
+            .is_ok_and(SourceState::is_pending);
+        if !was_pending {
+            self.stamp_injection(irq_id);
+        }
+        was_pending
+    }
+}
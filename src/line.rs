@@ -0,0 +1,115 @@
+//! Level lines shared by several emulated devices.
+//!
+//! When several emulated devices share one level-triggered source, the line
+//! is the wired OR of their outputs: it stays asserted until every
+//! contributor lowered its own output. Each contributor drives the source
+//! through its own [`IrqLine`] handle; the vPLIC counts the raised handles
+//! of each source and only deasserts the line, withdrawing the source if the
+//! guest has not claimed it yet, once the count drops to zero.
+//!
+//! A source driven through handles should not also be driven with
+//! [`VPlicGlobal::set_irq_level`], which bypasses the count.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{AxError, AxResult};
+
+use crate::{Trigger, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// One contributor's output on a shared level source.
+///
+/// Dropping the handle lowers the output.
+pub struct IrqLine {
+    vplic: &'static VPlicGlobal,
+    irq_id: usize,
+    device_id: u32,
+    raised: AtomicBool,
+}
+
+impl IrqLine {
+    /// Returns the source the handle drives.
+    pub fn irq_id(&self) -> usize {
+        self.irq_id
+    }
+
+    /// Returns the emulated device the handle belongs to.
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// Returns `true` if this contributor's output is raised.
+    pub fn is_raised(&self) -> bool {
+        self.raised.load(Ordering::Acquire)
+    }
+
+    /// Sets this contributor's output.
+    pub fn set_level(&self, raised: bool) -> AxResult {
+        self.vplic.forbid_in_hook("IrqLine::set_level")?;
+        if self.raised.swap(raised, Ordering::AcqRel) == raised {
+            return Ok(());
+        }
+        self.vplic.drive_shared_line(self.irq_id, raised, self.device_id)
+    }
+
+    /// Raises this contributor's output.
+    pub fn raise(&self) -> AxResult {
+        self.set_level(true)
+    }
+
+    /// Lowers this contributor's output.
+    pub fn lower(&self) -> AxResult {
+        self.set_level(false)
+    }
+}
+
+impl Drop for IrqLine {
+    fn drop(&mut self) {
+        let _ = self.lower();
+    }
+}
+
+impl VPlicGlobal {
+    /// Creates the handle of the emulated device `device_id` contributing to
+    /// the level-triggered source `irq_id`.
+    pub fn irq_line(&'static self, irq_id: usize, device_id: u32) -> AxResult<IrqLine> {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES || self.source_trigger(irq_id) != Trigger::Level {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(IrqLine {
+            vplic: self,
+            irq_id,
+            device_id,
+            raised: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the number of contributors raising `irq_id`.
+    pub fn line_contributors(&self, irq_id: usize) -> u32 {
+        if self.forbid_in_hook("line_contributors").is_err() {
+            return 0;
+        }
+        self.line_levels.lock().get(irq_id).copied().unwrap_or(0)
+    }
+
+    /// Accounts one contributor raising or lowering its output on `irq_id`,
+    /// driving the line on the first raise and the last lower.
+    ///
+    /// The count stays locked while driving the line, so a concurrent first
+    /// raise cannot be overtaken by the last lower.
+    fn drive_shared_line(&self, irq_id: usize, raised: bool, device_id: u32) -> AxResult {
+        let mut levels = self.line_levels.lock();
+        levels[irq_id] = if raised {
+            levels[irq_id].saturating_add(1)
+        } else {
+            levels[irq_id].saturating_sub(1)
+        };
+        match (raised, levels[irq_id]) {
+            (true, 1) => self.set_irq_level(irq_id, true, device_id),
+            (false, 0) => {
+                self.set_irq_level(irq_id, false, device_id)?;
+                self.retract_irq(irq_id).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Lock primitive guarding the internal state.
+//!
+//! The vPLIC state sits behind many small locks taken on guest MMIO exits,
+//! on injection and from host interrupt handlers. Which primitive fits
+//! depends on the host, and is chosen with a feature:
+//!
+//! - none: plain spinlocks, the cheapest when contention is rare;
+//! - `lock-ticket`: fair ticket locks, bounding how long each contender
+//!   waits, for hypervisors with real-time vCPUs;
+//! - `lock-irq-save`: spinlocks masking host interrupts while held (through
+//!   [`VPlicHal::irq_save`](crate::VPlicHal::irq_save)), so a host handler
+//!   forwarding an interrupt never spins on a lock taken by the code it
+//!   interrupted.
+//!
+//! The lock is a property of the whole build, not of one vPLIC, and the
+//! features stay additive: with both enabled, the irq-save lock wraps a
+//! ticket lock, masking host interrupts and serving contenders in order.
+//! The top-level hypervisor crate is the one to choose.
+//!
+//! There is no lock-free variant. The hot pending and active state is kept
+//! in atomic bitmaps already and never locked; what remains behind locks
+//! (per-context state, tables, queues) is updated several words at a time,
+//! which no lock-free primitive offering this `Mutex` API could do.
+//!
+//! Locks nested in one another are always taken in this order:
+//!
+//! 1. `contexts`;
+//! 2. `priorities`;
+//! 3. `critical_irqs`, `hypervisor_irqs`, `assigned_irqs`, `irq_map`,
+//!    `injection_order`, `preempt_masked`, in this order;
+//! 4. the software register file of an emulated vPLIC.
+//!
+//! Gateways take `held_irqs` before `asserted_lines`. A claim holds the
+//! gateway lock of its source (`held_irqs` or `latched_edges`) while it
+//! moves the source from pending to active, and takes no other lock then.
+//!
+//! In particular a lock of step 3 is never held while taking `priorities`:
+//! claim arbitration holds `priorities` while it looks up ownership and
+//! classes, so priority updates resolve those before locking `priorities`.
+//!
+//! All of them expose the same `Mutex` API to the rest of the crate,
+//! including `as_mut_ptr` for the unlocked reads of the panic path (see
+//! [`VPlicGlobal::panic_quiesce`](crate::VPlicGlobal::panic_quiesce)).
+
+// `spin::Mutex` hides `as_mut_ptr`; the spinlock it wraps has it.
+#[cfg(not(any(feature = "lock-ticket", feature = "lock-irq-save")))]
+pub(crate) type Mutex<T> = spin::mutex::SpinMutex<T>;
+
+#[cfg(all(feature = "lock-ticket", not(feature = "lock-irq-save")))]
+pub(crate) type Mutex<T> = spin::mutex::TicketMutex<T>;
+
+#[cfg(feature = "lock-irq-save")]
+pub(crate) use irq_save::Mutex;
+
+// Also built for the tests, which exercise every variant.
+#[cfg(any(test, feature = "lock-irq-save"))]
+#[cfg_attr(not(feature = "lock-irq-save"), allow(dead_code))]
+mod irq_save {
+    use core::mem::ManuallyDrop;
+    use core::ops::{Deref, DerefMut};
+
+    use crate::hal::hal;
+
+    #[cfg(not(feature = "lock-ticket"))]
+    use spin::mutex::{SpinMutex as RawMutex, SpinMutexGuard as RawMutexGuard};
+    #[cfg(feature = "lock-ticket")]
+    use spin::mutex::{TicketMutex as RawMutex, TicketMutexGuard as RawMutexGuard};
+
+    /// Spinlock, or ticket lock with `lock-ticket`, keeping host interrupts
+    /// masked while held.
+    pub struct Mutex<T> {
+        inner: RawMutex<T>,
+    }
+
+    /// Guard of a [`Mutex`], restoring the interrupt state once unlocked.
+    pub struct MutexGuard<'a, T> {
+        guard: ManuallyDrop<RawMutexGuard<'a, T>>,
+        flags: usize,
+    }
+
+    impl<T> Mutex<T> {
+        pub const fn new(value: T) -> Self {
+            Self {
+                inner: RawMutex::new(value),
+            }
+        }
+
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            let flags = hal().irq_save();
+            MutexGuard {
+                guard: ManuallyDrop::new(self.inner.lock()),
+                flags,
+            }
+        }
+
+        pub fn as_mut_ptr(&self) -> *mut T {
+            self.inner.as_mut_ptr()
+        }
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            // Unlock before unmasking, or a pending handler could spin on
+            // the lock right away.
+            unsafe { ManuallyDrop::drop(&mut self.guard) };
+            hal().irq_restore(self.flags);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ops::DerefMut;
+
+    use crate::mock::testing::mock_vplic;
+
+    /// The `Mutex` API shared by every variant.
+    trait Lock<T>: Sync {
+        fn new(value: T) -> Self;
+        fn lock(&self) -> impl DerefMut<Target = T>;
+    }
+
+    impl<T: Send> Lock<T> for spin::mutex::SpinMutex<T> {
+        fn new(value: T) -> Self {
+            Self::new(value)
+        }
+
+        fn lock(&self) -> impl DerefMut<Target = T> {
+            self.lock()
+        }
+    }
+
+    #[cfg(feature = "lock-ticket")]
+    impl<T: Send> Lock<T> for spin::mutex::TicketMutex<T> {
+        fn new(value: T) -> Self {
+            Self::new(value)
+        }
+
+        fn lock(&self) -> impl DerefMut<Target = T> {
+            self.lock()
+        }
+    }
+
+    impl<T: Send> Lock<T> for super::irq_save::Mutex<T> {
+        fn new(value: T) -> Self {
+            Self::new(value)
+        }
+
+        fn lock(&self) -> impl DerefMut<Target = T> {
+            self.lock()
+        }
+    }
+
+    fn contended_lock_is_exclusive<L: Lock<usize>>() {
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 1_000;
+        // The irq-save lock masks interrupts through the HAL.
+        let _vplic = mock_vplic(1);
+        let counter = L::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        let mut guard = counter.lock();
+                        let seen = *guard;
+                        std::thread::yield_now();
+                        *guard = seen + 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*counter.lock(), THREADS * ROUNDS);
+    }
+
+    #[test]
+    fn contended_spin_lock_is_exclusive() {
+        contended_lock_is_exclusive::<spin::mutex::SpinMutex<usize>>();
+    }
+
+    #[cfg(feature = "lock-ticket")]
+    #[test]
+    fn contended_ticket_lock_is_exclusive() {
+        contended_lock_is_exclusive::<spin::mutex::TicketMutex<usize>>();
+    }
+
+    #[test]
+    fn contended_irq_save_lock_is_exclusive() {
+        contended_lock_is_exclusive::<super::irq_save::Mutex<usize>>();
+    }
+
+    #[test]
+    fn selected_lock_is_exclusive() {
+        contended_lock_is_exclusive::<super::Mutex<usize>>();
+    }
+}
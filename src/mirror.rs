@@ -0,0 +1,86 @@
+//! Read-only mirror of a vPLIC's interrupt state.
+//!
+//! The VMM maps a host page read-only into a monitoring VM and asks the
+//! vPLIC to publish snapshots into it on demand. The page is
+//! self-describing (magic, version, geometry) and protected by a sequence
+//! counter: readers retry while `generation` is odd or changes under them.
+
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{fence, Ordering};
+
+use axaddrspace::HostPhysAddr;
+use bitmaps::Bitmap;
+
+use crate::hal::hal;
+use crate::{source_bit, source_word, VPlicGlobal, PLIC_NUM_BANKS, PLIC_NUM_SOURCES};
+
+/// Magic value identifying a mirror page ("VPLM").
+pub const MIRROR_MAGIC: u32 = 0x4d4c_5056;
+
+/// Layout version of [`MirrorPage`].
+pub const MIRROR_VERSION: u32 = 1;
+
+/// Layout of a published mirror page.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MirrorPage {
+    /// Always [`MIRROR_MAGIC`].
+    pub magic: u32,
+    /// Always [`MIRROR_VERSION`].
+    pub version: u32,
+    /// Number of interrupt sources covered by the bitmaps.
+    pub num_sources: u32,
+    /// Number of contexts of the mirrored vPLIC.
+    pub num_contexts: u32,
+    /// Sequence counter, odd while an update is in progress.
+    pub generation: u64,
+    /// Assigned sources, one bit per source.
+    pub assigned: [u32; PLIC_NUM_BANKS],
+    /// Pending sources, one bit per source.
+    pub pending: [u32; PLIC_NUM_BANKS],
+    /// Active (claimed, not completed) sources, one bit per source.
+    pub active: [u32; PLIC_NUM_BANKS],
+}
+
+impl VPlicGlobal {
+    /// Publishes a snapshot of the interrupt state into the mirror page at
+    /// `page`.
+    ///
+    /// # Safety
+    ///
+    /// `page` must be the host physical address of memory reserved for the
+    /// mirror, mapped by the HAL, aligned to 8 bytes and at least
+    /// `size_of::<MirrorPage>()` bytes long. Nothing but the mirror readers
+    /// may access it meanwhile.
+    pub unsafe fn publish_mirror(&self, page: HostPhysAddr) {
+        let page = hal().phys_to_virt(page).as_mut_ptr() as *mut MirrorPage;
+        let assigned = bitmap_words(&self.assigned_irqs.lock());
+        let pending = self.pending_irqs.words();
+        let active = self.active_irqs.words();
+
+        let generation = self.mirror_generation.fetch_add(2, Ordering::Relaxed);
+        // SAFETY: the caller guarantees that `page` is a valid mirror page.
+        unsafe {
+            addr_of_mut!((*page).generation).write_volatile(generation + 1);
+            fence(Ordering::Release);
+            addr_of_mut!((*page).magic).write_volatile(MIRROR_MAGIC);
+            addr_of_mut!((*page).version).write_volatile(MIRROR_VERSION);
+            addr_of_mut!((*page).num_sources).write_volatile(PLIC_NUM_SOURCES as u32);
+            addr_of_mut!((*page).num_contexts).write_volatile(self.contexts_num as u32);
+            addr_of_mut!((*page).assigned).write_volatile(assigned);
+            addr_of_mut!((*page).pending).write_volatile(pending);
+            addr_of_mut!((*page).active).write_volatile(active);
+            fence(Ordering::Release);
+            addr_of_mut!((*page).generation).write_volatile(generation + 2);
+        }
+    }
+}
+
+/// Packs a source bitmap into register-sized words.
+fn bitmap_words(bitmap: &Bitmap<{ PLIC_NUM_SOURCES }>) -> [u32; PLIC_NUM_BANKS] {
+    let mut words = [0; PLIC_NUM_BANKS];
+    for irq_id in bitmap.into_iter() {
+        words[source_word(irq_id)] |= source_bit(irq_id);
+    }
+    words
+}
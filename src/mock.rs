@@ -0,0 +1,340 @@
+//! In-memory host backend for development machines.
+//!
+//! With the `mock` feature, [`MockHal`] stands in for the hypervisor: the
+//! guest interrupt line is a flag, time is a counter advanced by hand and
+//! physical addresses are used as is. Together with the software register
+//! file of an emulated vPLIC (see [`VPlicGlobal::new_mock`]), the claim,
+//! complete, pending and enable paths of `handle_read`/`handle_write` run
+//! on the build host, e.g. under `cargo test`, without touching any PLIC.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use axaddrspace::{GuestPhysAddr, HostPhysAddr, HostVirtAddr};
+use axerrno::AxResult;
+
+use crate::consts::*;
+use crate::hal::{set_hal, VPlicHal};
+use crate::VPlicGlobal;
+
+/// Hart ID standing for "unknown" in [`MockHal`].
+const NO_HART: usize = usize::MAX;
+
+/// A HAL recording the guest interrupt line and running a manual clock.
+pub struct MockHal {
+    vseip: AtomicBool,
+    now_ns: AtomicU64,
+    hart_id: AtomicUsize,
+    hvictl: AtomicUsize,
+}
+
+impl MockHal {
+    /// Creates a HAL with VSEIP clear, the clock at 0, running on hart 0.
+    pub const fn new() -> Self {
+        Self {
+            vseip: AtomicBool::new(false),
+            now_ns: AtomicU64::new(0),
+            hart_id: AtomicUsize::new(0),
+            hvictl: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the last value written to hvictl.
+    pub fn hvictl(&self) -> usize {
+        self.hvictl.load(Ordering::Acquire)
+    }
+
+    /// Sets the hart ID reported to the vPLIC, `None` for a host that does
+    /// not report it.
+    pub fn set_hart_id(&self, hart_id: Option<usize>) {
+        self.hart_id.store(hart_id.unwrap_or(NO_HART), Ordering::Relaxed);
+    }
+
+    /// Returns `true` if VSEIP is asserted.
+    pub fn vseip(&self) -> bool {
+        self.vseip.load(Ordering::Acquire)
+    }
+
+    /// Advances the clock by `ns` nanoseconds.
+    pub fn advance(&self, ns: u64) {
+        self.now_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+}
+
+impl Default for MockHal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VPlicHal for MockHal {
+    fn phys_to_virt(&self, paddr: HostPhysAddr) -> HostVirtAddr {
+        HostVirtAddr::from_usize(paddr.as_usize())
+    }
+
+    fn set_vseip(&self) {
+        self.vseip.store(true, Ordering::Release);
+    }
+
+    fn clear_vseip(&self) {
+        self.vseip.store(false, Ordering::Release);
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.now_ns.load(Ordering::Relaxed)
+    }
+
+    fn set_hvictl(&self, val: usize) -> AxResult {
+        self.hvictl.store(val, Ordering::Release);
+        Ok(())
+    }
+
+    fn hart_id(&self) -> Option<usize> {
+        match self.hart_id.load(Ordering::Relaxed) {
+            NO_HART => None,
+            hart_id => Some(hart_id),
+        }
+    }
+}
+
+static MOCK_HAL: MockHal = MockHal::new();
+
+impl VPlicGlobal {
+    /// Creates an emulated vPLIC with `contexts_num` contexts at guest
+    /// address 0, and installs the shared [`MockHal`], returned alongside.
+    ///
+    /// Fails with [`AxError::AlreadyExists`](axerrno::AxError::AlreadyExists)
+    /// if another HAL is installed already.
+    pub fn new_mock(contexts_num: usize) -> AxResult<(Self, &'static MockHal)> {
+        set_hal(&MOCK_HAL)?;
+        let size = PLIC_CONTEXT_CTRL_OFFSET + (contexts_num + 1) * PLIC_CONTEXT_STRIDE;
+        let vplic = Self::new_emulated(GuestPhysAddr::from_usize(0), Some(size), contexts_num);
+        Ok((vplic, &MOCK_HAL))
+    }
+}
+
+/// Guest register accessors shared by the unit tests.
+///
+/// All mock vPLICs share [`MOCK_HAL`], so its VSEIP flag and clock would be
+/// raced by tests running in parallel. The first mock created by a test
+/// takes a lock held until the test's thread exits and resets the HAL, so
+/// tests using it run one at a time and start from a clean HAL.
+#[cfg(test)]
+pub(crate) mod testing {
+    use core::cell::RefCell;
+    use core::sync::atomic::Ordering;
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    use axaddrspace::device::AccessWidth;
+    use axaddrspace::GuestPhysAddr;
+    use axdevice_base::BaseDeviceOps;
+    use axerrno::AxResult;
+
+    use super::{MockHal, MOCK_HAL};
+    use crate::consts::*;
+    use crate::VPlicGlobal;
+
+    /// Held by the test currently using the mock HAL.
+    static MOCK_HAL_USER: Mutex<()> = Mutex::new(());
+
+    std::thread_local! {
+        static MOCK_HAL_GUARD: RefCell<Option<MutexGuard<'static, ()>>> = const { RefCell::new(None) };
+    }
+
+    /// Serializes the calling test against the other users of the mock HAL
+    /// and returns the HAL, reset on first use by the test.
+    pub(crate) fn mock_hal() -> &'static MockHal {
+        MOCK_HAL_GUARD.with(|guard| {
+            let mut guard = guard.borrow_mut();
+            if guard.is_none() {
+                // A failed test poisons the lock, which is no reason to fail
+                // the others.
+                *guard = Some(MOCK_HAL_USER.lock().unwrap_or_else(PoisonError::into_inner));
+                MOCK_HAL.vseip.store(false, Ordering::Release);
+                MOCK_HAL.now_ns.store(0, Ordering::Relaxed);
+                MOCK_HAL.hart_id.store(0, Ordering::Relaxed);
+                MOCK_HAL.hvictl.store(0, Ordering::Release);
+            }
+        });
+        &MOCK_HAL
+    }
+
+    /// Creates a mock vPLIC with `contexts_num` contexts.
+    pub(crate) fn mock_vplic(contexts_num: usize) -> VPlicGlobal {
+        mock_hal();
+        VPlicGlobal::new_mock(contexts_num).expect("mock HAL not installed").0
+    }
+
+    /// Reads the guest register at `offset` with an access of `width`.
+    pub(crate) fn read_width(vplic: &VPlicGlobal, offset: usize, width: AccessWidth) -> AxResult<usize> {
+        vplic.handle_read(GuestPhysAddr::from_usize(offset), width)
+    }
+
+    /// Reads the 32-bit guest register at `offset`.
+    pub(crate) fn read(vplic: &VPlicGlobal, offset: usize) -> usize {
+        read_width(vplic, offset, AccessWidth::Dword).unwrap()
+    }
+
+    /// Writes the guest register at `offset` with an access of `width`.
+    pub(crate) fn write_width(
+        vplic: &VPlicGlobal,
+        offset: usize,
+        width: AccessWidth,
+        val: usize,
+    ) -> AxResult {
+        vplic.handle_write(GuestPhysAddr::from_usize(offset), width, val)
+    }
+
+    /// Writes the 32-bit guest register at `offset`.
+    pub(crate) fn write(vplic: &VPlicGlobal, offset: usize, val: usize) {
+        write_width(vplic, offset, AccessWidth::Dword, val).unwrap()
+    }
+
+    pub(crate) fn priority_reg(irq_id: usize) -> usize {
+        PLIC_PRIORITY_OFFSET + irq_id * 4
+    }
+
+    pub(crate) fn pending_reg(word_index: usize) -> usize {
+        PLIC_PENDING_OFFSET + word_index * 4
+    }
+
+    pub(crate) fn enable_reg(context_id: usize, word_index: usize) -> usize {
+        PLIC_ENABLE_OFFSET + context_id * PLIC_ENABLE_STRIDE + word_index * 4
+    }
+
+    pub(crate) fn threshold_reg(context_id: usize) -> usize {
+        PLIC_CONTEXT_CTRL_OFFSET + context_id * PLIC_CONTEXT_STRIDE + PLIC_CONTEXT_THRESHOLD_OFFSET
+    }
+
+    pub(crate) fn claim_reg(context_id: usize) -> usize {
+        PLIC_CONTEXT_CTRL_OFFSET + context_id * PLIC_CONTEXT_STRIDE + PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET
+    }
+
+    /// Gives `irq_id` `priority` and enables it on `context_id`, the way the
+    /// guest does.
+    pub(crate) fn route(vplic: &VPlicGlobal, context_id: usize, irq_id: usize, priority: usize) {
+        write(vplic, priority_reg(irq_id), priority);
+        let reg = enable_reg(context_id, irq_id / 32);
+        let enables = read(vplic, reg);
+        write(vplic, reg, enables | 1 << (irq_id % 32));
+    }
+
+    /// Claims on `context_id`, returning the claimed IRQ or 0.
+    pub(crate) fn claim(vplic: &VPlicGlobal, context_id: usize) -> usize {
+        read(vplic, claim_reg(context_id))
+    }
+
+    /// Completes `irq_id` on `context_id`.
+    pub(crate) fn complete(vplic: &VPlicGlobal, context_id: usize, irq_id: usize) {
+        write(vplic, claim_reg(context_id), irq_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axaddrspace::device::AccessWidth;
+
+    use super::testing::*;
+    use crate::{set_hal, MockHal, SourceState, VPlicGlobal};
+
+    #[test]
+    fn mock_hal_install_is_checked() {
+        static OTHER: MockHal = MockHal::new();
+        mock_hal();
+        let (_, hal) = VPlicGlobal::new_mock(1).unwrap();
+        assert!(set_hal(hal).is_ok());
+        assert!(set_hal(&OTHER).is_err());
+    }
+
+    #[test]
+    fn claim_and_complete() {
+        let vplic = mock_vplic(2);
+        route(&vplic, 0, 3, 2);
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(read(&vplic, pending_reg(0)), 1 << 3);
+        assert_eq!(claim(&vplic, 1), 0);
+        assert_eq!(claim(&vplic, 0), 3);
+        assert_eq!(read(&vplic, pending_reg(0)), 0);
+        assert_eq!(vplic.source_state(3), SourceState::Active);
+        assert_eq!(claim(&vplic, 0), 0);
+        complete(&vplic, 0, 3);
+        assert_eq!(vplic.source_state(3), SourceState::Inactive);
+    }
+
+    #[test]
+    fn vseip_follows_pending_interrupts() {
+        let vplic = mock_vplic(1);
+        let hal = mock_hal();
+        route(&vplic, 0, 3, 1);
+        assert!(!hal.vseip());
+        vplic.inject_irq(3).unwrap();
+        assert!(hal.vseip());
+        assert_eq!(claim(&vplic, 0), 3);
+        complete(&vplic, 0, 3);
+        assert!(!hal.vseip());
+    }
+
+    #[test]
+    fn highest_priority_claimed_first() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 4, 1);
+        route(&vplic, 0, 40, 5);
+        vplic.inject_irq(4).unwrap();
+        vplic.inject_irq(40).unwrap();
+        assert_eq!(read(&vplic, pending_reg(1)), 1 << 8);
+        assert_eq!(claim(&vplic, 0), 40);
+        assert_eq!(claim(&vplic, 0), 4);
+    }
+
+    #[test]
+    fn priority_and_threshold_registers() {
+        let vplic = mock_vplic(1);
+        write(&vplic, priority_reg(5), 3);
+        assert_eq!(read(&vplic, priority_reg(5)), 3);
+        write(&vplic, priority_reg(5), 100);
+        assert_eq!(read(&vplic, priority_reg(5)), 7);
+        write(&vplic, threshold_reg(0), 2);
+        assert_eq!(read(&vplic, threshold_reg(0)), 2);
+    }
+
+    #[test]
+    fn threshold_gates_claims() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 6, 2);
+        vplic.inject_irq(6).unwrap();
+        write(&vplic, threshold_reg(0), 2);
+        assert_eq!(claim(&vplic, 0), 0);
+        write(&vplic, threshold_reg(0), 1);
+        assert_eq!(claim(&vplic, 0), 6);
+    }
+
+    #[test]
+    fn enables_are_per_context() {
+        let vplic = mock_vplic(2);
+        write(&vplic, enable_reg(1, 2), 0x8000_0001);
+        assert_eq!(read(&vplic, enable_reg(1, 2)), 0x8000_0001);
+        assert_eq!(read(&vplic, enable_reg(0, 2)), 0);
+        write(&vplic, priority_reg(64), 1);
+        vplic.inject_irq(64).unwrap();
+        assert_eq!(claim(&vplic, 0), 0);
+        assert_eq!(claim(&vplic, 1), 64);
+    }
+
+    #[test]
+    fn pending_writes_need_self_injection() {
+        let vplic = mock_vplic(1);
+        write(&vplic, pending_reg(0), 1 << 7);
+        assert_eq!(read(&vplic, pending_reg(0)), 0);
+        vplic.set_guest_self_injection(true);
+        write(&vplic, pending_reg(0), 1 << 7);
+        assert_eq!(read(&vplic, pending_reg(0)), 1 << 7);
+    }
+
+    #[test]
+    fn narrow_claim_is_rejected() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 2, 1);
+        vplic.inject_irq(2).unwrap();
+        assert!(read_width(&vplic, claim_reg(0), AccessWidth::Byte).is_err());
+        assert_eq!(vplic.source_state(2), SourceState::Pending);
+    }
+}
@@ -0,0 +1,94 @@
+//! Translation of MSI writes into wired sources.
+//!
+//! MSI-only device models (virtio-pci and the like) signal interrupts by
+//! writing a data word to an address. For guests using the wired PLIC, the
+//! VMM installs a route per (address, data) pair the guest programmed into
+//! the device, and the device model hands its MSI writes to
+//! [`VPlicGlobal::deliver_msi`], which asserts the routed source as an edge.
+
+use alloc::vec::Vec;
+
+use axaddrspace::GuestPhysAddr;
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, Trigger, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Route of one MSI to a wired source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MsiRoute {
+    addr: GuestPhysAddr,
+    data: u32,
+    irq_id: usize,
+    device_id: u32,
+}
+
+/// Routes of MSI writes to wired sources.
+pub(crate) type MsiRoutes = Vec<MsiRoute>;
+
+impl VPlicGlobal {
+    /// Routes MSI writes of `data` to `addr` by device `device_id` to source
+    /// `irq_id`, which becomes edge-triggered.
+    pub fn add_msi_route(
+        &self,
+        addr: GuestPhysAddr,
+        data: u32,
+        irq_id: usize,
+        device_id: u32,
+    ) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        let mut routes = self.msi_routes.lock();
+        if routes.iter().any(|route| route.addr == addr && route.data == data) {
+            return Err(AxError::AlreadyExists);
+        }
+        self.set_source_trigger(irq_id, Trigger::Edge)?;
+        routes.push(MsiRoute {
+            addr,
+            data,
+            irq_id,
+            device_id,
+        });
+        Ok(())
+    }
+
+    /// Removes the route of MSI writes of `data` to `addr`.
+    pub fn remove_msi_route(&self, addr: GuestPhysAddr, data: u32) -> AxResult {
+        let mut routes = self.msi_routes.lock();
+        let len = routes.len();
+        routes.retain(|route| route.addr != addr || route.data != data);
+        if routes.len() == len {
+            return Err(AxError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Returns the source MSI writes of `data` to `addr` are routed to.
+    pub fn msi_route(&self, addr: GuestPhysAddr, data: u32) -> Option<usize> {
+        self.find_msi_route(addr, data).map(|route| route.irq_id)
+    }
+
+    /// Delivers an MSI write of `data` to `addr` by asserting the routed
+    /// source.
+    ///
+    /// Returns `false` if no route matches, e.g. the guest has not
+    /// programmed the device yet; the write is then dropped, as a write to
+    /// unmapped memory would be.
+    pub fn deliver_msi(&self, addr: GuestPhysAddr, data: u32) -> bool {
+        match self.find_msi_route(addr, data) {
+            Some(route) => {
+                self.inject(route.irq_id, InjectionSource::Device(route.device_id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn find_msi_route(&self, addr: GuestPhysAddr, data: u32) -> Option<MsiRoute> {
+        self.msi_routes
+            .lock()
+            .iter()
+            .find(|route| route.addr == addr && route.data == data)
+            .copied()
+    }
+}
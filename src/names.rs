@@ -0,0 +1,65 @@
+//! Human-readable source names.
+//!
+//! The VMM names guest sources after the devices behind them ("virtio-net
+//! queue 0", "xhci"). Logs, the source table, the stats export and the
+//! audit report then reference devices instead of bare IRQ numbers. A
+//! source without a registered name falls back to the name of its assigned
+//! host source, if any.
+
+use core::fmt;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// A source as printed in logs: its name, if known, and its number.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SourceLabel {
+    irq_id: usize,
+    name: Option<&'static str>,
+}
+
+impl fmt::Display for SourceLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "IRQ {} ({})", self.irq_id, name),
+            None => write!(f, "IRQ {}", self.irq_id),
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Names `irq_id`, or removes its name with `None`.
+    pub fn set_source_name(&self, irq_id: usize, name: Option<&'static str>) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.source_names.lock()[irq_id] = name;
+        Ok(())
+    }
+
+    /// Returns the name of `irq_id`: the registered one, or else the
+    /// non-empty name its host source was assigned with.
+    pub fn source_name(&self, irq_id: usize) -> Option<&'static str> {
+        if let Some(name) = self.source_names.lock().get(irq_id).copied().flatten() {
+            return Some(name);
+        }
+        let host_irq = self.host_irq(irq_id);
+        if host_irq == 0 {
+            return None;
+        }
+        self.source_infos
+            .lock()
+            .get(&host_irq)
+            .map(|info| info.name)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Returns `irq_id` labelled with its name, for logs.
+    pub(crate) fn source_label(&self, irq_id: usize) -> SourceLabel {
+        SourceLabel {
+            irq_id,
+            name: self.source_name(irq_id),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Support for guests that are themselves hypervisors (nested virtualization).
+//!
+//! An L1 hypervisor typically claims from more than one context per vCPU and
+//! forwards some of the claimed interrupts to its L2 guests. When the L2 cannot
+//! take such an interrupt right away, the L1 hands it back to the vPLIC, which
+//! re-injects it without touching the host gateway.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, SourceEvent, VPlicGlobal, PLIC_NUM_SOURCES};
+
+impl VPlicGlobal {
+    /// Binds groups of `contexts_per_vcpu` consecutive contexts to
+    /// consecutive vCPUs.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if the contexts cannot be split
+    /// into groups of `contexts_per_vcpu`.
+    pub fn set_contexts_per_vcpu(&self, contexts_per_vcpu: usize) -> AxResult {
+        if contexts_per_vcpu == 0 || self.contexts_num % contexts_per_vcpu != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        for (context_id, context) in self.contexts.lock().iter_mut().enumerate() {
+            context.vcpu_id = context_id / contexts_per_vcpu;
+        }
+        Ok(())
+    }
+
+    /// Binds `context_id` to `vcpu_id`.
+    pub fn bind_context(&self, context_id: usize, vcpu_id: usize) -> AxResult {
+        if vcpu_id >= self.contexts_num {
+            return Err(AxError::InvalidInput);
+        }
+        match self.contexts.lock().get_mut(context_id) {
+            Some(context) => {
+                context.vcpu_id = vcpu_id;
+                Ok(())
+            }
+            None => Err(AxError::InvalidInput),
+        }
+    }
+
+    /// Returns the vCPU owning `context_id`.
+    pub fn vcpu_of_context(&self, context_id: usize) -> usize {
+        self.contexts
+            .lock()
+            .get(context_id)
+            .map_or(context_id, |context| context.vcpu_id)
+    }
+
+    /// Returns the contexts owned by `vcpu_id`.
+    pub fn contexts_of_vcpu(&self, vcpu_id: usize) -> Vec<usize> {
+        self.contexts
+            .lock()
+            .iter()
+            .enumerate()
+            .filter(|(_, context)| context.vcpu_id == vcpu_id)
+            .map(|(context_id, _)| context_id)
+            .collect()
+    }
+
+    /// Returns the number of vCPUs owning contexts, i.e. the highest bound
+    /// vCPU ID plus one.
+    pub fn vcpus_num(&self) -> usize {
+        self.contexts
+            .lock()
+            .iter()
+            .map(|context| context.vcpu_id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Re-injects an IRQ the guest claimed but forwarded elsewhere (e.g. to
+    /// its own L2 guest) without completing it.
+    ///
+    /// The IRQ goes back from active to pending and the context that
+    /// claimed it no longer holds it; the host source stays claimed, so the
+    /// physical gateway remains closed until the final complete. The
+    /// original injection origin is kept.
+    pub fn reinject(&self, irq_id: usize) -> AxResult {
+        if irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.transition(irq_id, SourceEvent::Release)?;
+        self.release_claim(irq_id);
+        let source = self.injection_source(irq_id).unwrap_or(InjectionSource::Guest);
+        self.inject(irq_id, source);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Source ownership on contexts shared with the hypervisor.
+//!
+//! On a hart shared between the hypervisor and a guest, a pending host
+//! interrupt may belong to the hypervisor. Such sources are never handed out
+//! by guest claims, and guest completions of them are not forwarded, so the
+//! host interrupt stays untouched for the hypervisor to handle.
+//!
+//! A passthrough source can also be borrowed by the hypervisor for a while
+//! (e.g. during a firmware update of the device): the guest sees it masked,
+//! and its guest-visible state is reprogrammed on the host when it is
+//! returned.
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Owner of an interrupt source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceOwner {
+    /// The guest handles the source.
+    #[default]
+    Guest,
+    /// The hypervisor handles the source.
+    Hypervisor,
+}
+
+impl VPlicGlobal {
+    /// Sets the owner of `irq_id`.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn set_source_owner(&self, irq_id: usize, owner: SourceOwner) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.hypervisor_irqs.lock().set(irq_id, owner == SourceOwner::Hypervisor);
+        self.refresh_priority_floor();
+        Ok(())
+    }
+
+    /// Returns the owner of `irq_id`.
+    pub fn source_owner(&self, irq_id: usize) -> SourceOwner {
+        if irq_id < PLIC_NUM_SOURCES && self.hypervisor_irqs.lock().get(irq_id) {
+            SourceOwner::Hypervisor
+        } else {
+            SourceOwner::Guest
+        }
+    }
+
+    /// Temporarily hands the guest source `irq_id` over to the hypervisor.
+    ///
+    /// The source is masked on the host for the guest contexts, and guest
+    /// priority and enable writes only update the shadows until it is
+    /// returned. Fails with [`AxError::ResourceBusy`] while the guest has
+    /// the source claimed.
+    pub fn borrow_source(&self, irq_id: usize) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if !self.guest_owns(irq_id) {
+            return Err(AxError::BadState);
+        }
+        if self.active_irqs.get(irq_id) {
+            return Err(AxError::ResourceBusy);
+        }
+        self.set_source_owner(irq_id, SourceOwner::Hypervisor)?;
+        self.resync_host_enables(irq_id)
+    }
+
+    /// Returns a source borrowed with [`VPlicGlobal::borrow_source`],
+    /// reprogramming its guest priority and enables on the host.
+    pub fn return_source(&self, irq_id: usize) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if self.guest_owns(irq_id) {
+            return Err(AxError::BadState);
+        }
+        self.set_source_owner(irq_id, SourceOwner::Guest)?;
+        self.set_priority(irq_id, self.priority(irq_id))?;
+        self.resync_host_enables(irq_id)?;
+        self.refresh_deliverability();
+        Ok(())
+    }
+
+    /// Returns `true` if the guest may claim or complete `irq_id`.
+    pub(crate) fn guest_owns(&self, irq_id: usize) -> bool {
+        self.source_owner(irq_id) == SourceOwner::Guest
+    }
+}
@@ -0,0 +1,92 @@
+//! Claim handling while the VMM pauses the device.
+//!
+//! While the VMM pauses the vPLIC (e.g. to serialize it at a migration
+//! point), no claim may change the pending/active bookkeeping. Pausing waits
+//! for the claims already in progress, and [`PausedClaim`] decides what later
+//! claims observe until the device is resumed.
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{AxError, AxResult};
+
+use crate::VPlicGlobal;
+
+/// What a claim read returns while the device is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PausedClaim {
+    /// Return 0, as if nothing was pending.
+    #[default]
+    Zero,
+    /// Fail the access and let the dispatcher decide.
+    Error,
+    /// Spin up to the given number of iterations waiting for the resume,
+    /// then return 0.
+    Wait(usize),
+}
+
+/// Marks a claim in progress; pausing waits for it to be dropped.
+pub(crate) struct ClaimGuard<'a>(&'a AtomicUsize);
+
+impl Drop for ClaimGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl VPlicGlobal {
+    /// Sets what claims return while the device is paused.
+    pub fn set_paused_claim(&self, behavior: PausedClaim) {
+        *self.paused_claim.lock() = behavior;
+    }
+
+    /// Pauses the device, waiting for the claims in progress to finish.
+    pub fn pause(&self) {
+        let _ = self.forbid_in_hook("pause");
+        self.paused.store(true, Ordering::SeqCst);
+        while self.claims_in_progress.load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+    }
+
+    /// Resumes the device.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the device is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Enters a claim.
+    ///
+    /// Returns `None` if the claim must return 0 because the device is paused
+    /// or all-masked.
+    pub(crate) fn enter_claim(&self) -> AxResult<Option<ClaimGuard<'_>>> {
+        let mut spins = match *self.paused_claim.lock() {
+            PausedClaim::Wait(spins) => spins,
+            _ => 0,
+        };
+        loop {
+            self.claims_in_progress.fetch_add(1, Ordering::SeqCst);
+            let guard = ClaimGuard(&self.claims_in_progress);
+            if self.guest_masked() {
+                return Ok(None);
+            }
+            if !self.is_paused() {
+                return Ok(Some(guard));
+            }
+            drop(guard);
+            match *self.paused_claim.lock() {
+                PausedClaim::Error => return Err(AxError::WouldBlock),
+                _ if spins == 0 => return Ok(None),
+                _ => {}
+            }
+            while self.is_paused() && spins > 0 {
+                spins -= 1;
+                spin_loop();
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Mask groups tied to vCPU power states.
+//!
+//! Sources of a mask group are host-masked (priority forced to 0) while
+//! their target vCPU is offline or in deep idle, so physical interrupts do
+//! not keep waking a hart for a vCPU that isn't running. Guest priority
+//! writes meanwhile only update the shadow, which is what the host gets
+//! back when the vCPU wakes up.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::host::HostBackend;
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES, PLIC_PRIORITY_OFFSET};
+
+/// A group of sources masked together with their target vCPU.
+pub(crate) struct MaskGroup {
+    vcpu_id: usize,
+    irqs: Vec<usize>,
+    /// Whether the group is host-masked.
+    masked: bool,
+}
+
+impl VPlicGlobal {
+    /// Defines a mask group of `irqs` targeting `vcpu_id`.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if a source is not backed by a
+    /// host source assigned to the VM.
+    pub fn add_mask_group(&self, vcpu_id: usize, irqs: &[usize]) -> AxResult {
+        if irqs.iter().any(|&irq_id| {
+            irq_id == 0 || irq_id >= PLIC_NUM_SOURCES || !self.host_assigned(self.host_irq(irq_id))
+        }) {
+            return Err(AxError::InvalidInput);
+        }
+        self.mask_groups.lock().push(MaskGroup {
+            vcpu_id,
+            irqs: irqs.to_vec(),
+            masked: false,
+        });
+        Ok(())
+    }
+
+    /// Removes the mask groups of `vcpu_id`, unmasking them first.
+    pub fn remove_mask_groups(&self, vcpu_id: usize) -> AxResult {
+        self.vcpu_online(vcpu_id)?;
+        self.mask_groups.lock().retain(|group| group.vcpu_id != vcpu_id);
+        Ok(())
+    }
+
+    /// Host-masks the groups of `vcpu_id` when it goes offline or deep idle.
+    ///
+    /// The groups are marked masked before the host is written, so the
+    /// sources a failed write left masked are restored on wake-up as well.
+    pub fn vcpu_offline(&self, vcpu_id: usize) -> AxResult {
+        let mut irqs = Vec::new();
+        for group in self
+            .mask_groups
+            .lock()
+            .iter_mut()
+            .filter(|group| group.vcpu_id == vcpu_id && !group.masked)
+        {
+            group.masked = true;
+            irqs.extend_from_slice(&group.irqs);
+        }
+        for irq_id in irqs {
+            let host_irq = self.host_irq(irq_id);
+            if self.host_assigned(host_irq) && self.guest_owns(irq_id) {
+                self.host_write(PLIC_PRIORITY_OFFSET + host_irq * 4, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if `irq_id` is host-masked with its mask group.
+    pub(crate) fn power_masked(&self, irq_id: usize) -> bool {
+        self.mask_groups
+            .lock()
+            .iter()
+            .any(|group| group.masked && group.irqs.contains(&irq_id))
+    }
+
+    /// Restores the groups of `vcpu_id` from the shadow priorities when it
+    /// wakes up, except the sources another masked group still holds.
+    pub fn vcpu_online(&self, vcpu_id: usize) -> AxResult {
+        let mut irqs = Vec::new();
+        for group in self
+            .mask_groups
+            .lock()
+            .iter_mut()
+            .filter(|group| group.vcpu_id == vcpu_id && group.masked)
+        {
+            group.masked = false;
+            irqs.extend_from_slice(&group.irqs);
+        }
+        for irq_id in irqs {
+            self.set_priority(irq_id, self.priority(irq_id))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+    use crate::SourceInfo;
+
+    #[test]
+    fn masked_group_is_restored_from_the_shadow() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(40, SourceInfo::default()).unwrap();
+        assert_eq!(vplic.compact_irqs(), [(1, 40)]);
+        assert!(vplic.add_mask_group(0, &[2]).is_err());
+        vplic.add_mask_group(0, &[1]).unwrap();
+        route(&vplic, 0, 1, 3);
+        vplic.vcpu_offline(0).unwrap();
+        assert_eq!(vplic.host_priority(40).unwrap(), 0);
+        write(&vplic, priority_reg(1), 5);
+        assert_eq!(vplic.host_priority(40).unwrap(), 0);
+        assert_eq!(read(&vplic, priority_reg(1)), 5);
+        vplic.vcpu_online(0).unwrap();
+        assert_eq!(vplic.host_priority(40).unwrap(), 5);
+    }
+}
@@ -0,0 +1,108 @@
+//! Masking of claimed level sources while their vCPU is descheduled.
+//!
+//! A vCPU preempted while handling a passthrough level interrupt leaves the
+//! physical line asserted; once the host completes other work the source
+//! may fire again into a hart now running a different guest. With preempt
+//! masking enabled, the VMM reports deschedules and reschedules, and the
+//! level sources claimed by the descheduled vCPU are masked in the host
+//! enables until it runs again.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use axerrno::AxResult;
+
+use crate::{TracePoint, Trigger, VPlicGlobal};
+
+impl VPlicGlobal {
+    /// Enables or disables masking the claimed level sources of descheduled
+    /// vCPUs.
+    pub fn set_preempt_masking(&self, enable: bool) {
+        self.preempt_masking.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if preempt masking is enabled.
+    pub fn preempt_masking(&self) -> bool {
+        self.preempt_masking.load(Ordering::Relaxed)
+    }
+
+    /// Called when `vcpu_id` is descheduled: masks on the host the
+    /// passthrough level sources its contexts claimed and did not complete.
+    /// Nothing else runs on dedicated harts, so their vCPUs need no masking.
+    pub fn vcpu_descheduled(&self, vcpu_id: usize) -> AxResult {
+        if !self.preempt_masking() || self.vcpu_dedicated(vcpu_id) {
+            return Ok(());
+        }
+        for context_id in self.contexts_of_vcpu(vcpu_id) {
+            let irq_id = match self.last_claim(context_id) {
+                Some(irq_id) => irq_id,
+                None => continue,
+            };
+            if self.host_irq(irq_id) == 0 || self.source_trigger(irq_id) != Trigger::Level {
+                continue;
+            }
+            // Descheduling twice in a row, or several contexts holding the
+            // same claim, masks the source once.
+            {
+                let mut masked = self.preempt_masked.lock();
+                if masked.contains(&(vcpu_id, irq_id)) {
+                    continue;
+                }
+                masked.push((vcpu_id, irq_id));
+            }
+            self.resync_host_enables(irq_id)?;
+            self.trace_point(TracePoint::Mask, irq_id, 1);
+            self.call_hooks(|hooks| hooks.preempt_masked(irq_id, true));
+        }
+        Ok(())
+    }
+
+    /// Called when `vcpu_id` runs again: unmasks the sources masked when it
+    /// was descheduled.
+    pub fn vcpu_rescheduled(&self, vcpu_id: usize) -> AxResult {
+        let unmasked: Vec<usize> = {
+            let mut masked = self.preempt_masked.lock();
+            let unmasked = masked
+                .iter()
+                .filter(|&&(owner, _)| owner == vcpu_id)
+                .map(|&(_, irq_id)| irq_id)
+                .collect();
+            masked.retain(|&(owner, _)| owner != vcpu_id);
+            unmasked
+        };
+        for irq_id in unmasked {
+            self.resync_host_enables(irq_id)?;
+            self.trace_point(TracePoint::Mask, irq_id, 0);
+            self.call_hooks(|hooks| hooks.preempt_masked(irq_id, false));
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if `irq_id` is masked because its vCPU is descheduled.
+    pub(crate) fn preempt_masked(&self, irq_id: usize) -> bool {
+        self.preempt_masked
+            .lock()
+            .iter()
+            .any(|&(_, masked)| masked == irq_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+
+    #[test]
+    fn repeated_deschedule_masks_once() {
+        let vplic = mock_vplic(1);
+        vplic.set_preempt_masking(true);
+        route(&vplic, 0, 3, 1);
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(claim(&vplic, 0), 3);
+        vplic.vcpu_descheduled(0).unwrap();
+        vplic.vcpu_descheduled(0).unwrap();
+        assert_eq!(vplic.preempt_masked.lock().len(), 1);
+        assert!(vplic.preempt_masked(3));
+        vplic.vcpu_rescheduled(0).unwrap();
+        assert!(!vplic.preempt_masked(3));
+    }
+}
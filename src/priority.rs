@@ -0,0 +1,97 @@
+//! Shadowed source priorities.
+//!
+//! The priorities of all sources are kept in one contiguous array mirroring
+//! what the host PLIC holds, so arbitration reads no host register and the
+//! whole array can be saved or reprogrammed in bulk (snapshots, restoring the
+//! host after a reset, initial configuration).
+
+use axerrno::{AxError, AxResult};
+
+use crate::host::HostBackend;
+use crate::{VPlicGlobal, PLIC_MAX_PRIORITY, PLIC_NUM_SOURCES, PLIC_PRIORITY_OFFSET};
+
+impl VPlicGlobal {
+    /// Sets the priority of `irq_id`, updating both the shadow and the host
+    /// register.
+    ///
+    /// The shadow keeps the value read back from the host, so it holds only
+    /// the priority bits the host implements, unless the inversion guard
+    /// clamped the forwarded value. Sources without a host IRQ, whose host
+    /// IRQ is not assigned to the VM, borrowed by the hypervisor or masked
+    /// with their vCPU only update the shadow. Priorities above
+    /// [`PLIC_MAX_PRIORITY`] are clamped. Source 0 is reserved and its
+    /// priority hardwired to 0, so writes to it are ignored.
+    pub fn set_priority(&self, irq_id: usize, priority: u32) -> AxResult {
+        if irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if irq_id == 0 {
+            return Ok(());
+        }
+        let priority = priority.min(PLIC_MAX_PRIORITY);
+        // Ownership and the guard take locks ordered before `priorities`.
+        let host_irq = self.host_irq(irq_id);
+        let forwarded =
+            self.host_assigned(host_irq) && self.guest_owns(irq_id) && !self.power_masked(irq_id);
+        let guarded = self.guard_priority(irq_id, priority);
+        let mut priorities = self.priorities.lock();
+        if !forwarded {
+            priorities[irq_id] = priority;
+            return Ok(());
+        }
+        let offset = PLIC_PRIORITY_OFFSET + host_irq * 4;
+        self.host_write(offset, guarded)?;
+        priorities[irq_id] = if guarded == priority {
+            self.host_read(offset)?
+        } else {
+            priority
+        };
+        Ok(())
+    }
+
+    /// Returns the shadow priority of `irq_id`, always 0 for the reserved
+    /// source 0.
+    pub fn priority(&self, irq_id: usize) -> u32 {
+        if irq_id == 0 {
+            return 0;
+        }
+        self.priorities.lock().get(irq_id).copied().unwrap_or(0)
+    }
+
+    /// Loads the priorities of sources `0..priorities.len()` and reprograms
+    /// the ones assigned to the VM on the host.
+    pub fn load_priorities(&self, priorities: &[u32]) -> AxResult {
+        if priorities.len() > PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        for (irq_id, &priority) in priorities.iter().enumerate().skip(1) {
+            self.set_priority(irq_id, priority)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the shadow priorities of sources `0..priorities.len()`.
+    pub fn store_priorities(&self, priorities: &mut [u32]) {
+        let shadow = self.priorities.lock();
+        let len = priorities.len().min(PLIC_NUM_SOURCES);
+        priorities[..len].copy_from_slice(&shadow[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::HostBackend;
+    use crate::mock::testing::*;
+    use crate::{SourceInfo, PLIC_PRIORITY_OFFSET};
+
+    #[test]
+    fn only_assigned_priorities_reach_the_host() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(2, SourceInfo::default()).unwrap();
+        vplic.host_write(PLIC_PRIORITY_OFFSET + 3 * 4, 6).unwrap();
+        vplic.load_priorities(&[0, 0, 4, 5]).unwrap();
+        assert_eq!(vplic.host_priority(2).unwrap(), 4);
+        assert_eq!(vplic.host_priority(3).unwrap(), 6);
+        assert_eq!(vplic.priority(3), 5);
+    }
+}
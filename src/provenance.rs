@@ -0,0 +1,60 @@
+//! Provenance of pending interrupts.
+//!
+//! Every injection is tagged with where it came from, and the tag of the
+//! injection that made a source pending is kept until the next one, so the
+//! origin of an unexpected interrupt in a guest can be audited afterwards.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Origin of an injection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InjectionSource {
+    /// A host hardware interrupt forwarded by the hypervisor.
+    HostHardware,
+    /// An emulated device, identified by the hypervisor-assigned id.
+    Device(u32),
+    /// The hypervisor itself (monitor, timers, debug interfaces).
+    Monitor,
+    /// The guest, by writing its own pending registers.
+    Guest,
+}
+
+impl VPlicGlobal {
+    /// Injects `irq_id` on behalf of `source`.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn inject_from(&self, irq_id: usize, source: InjectionSource) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.inject(irq_id, source);
+        Ok(())
+    }
+
+    /// Returns the origin of the injection that last made `irq_id` pending,
+    /// if it ever was.
+    pub fn injection_source(&self, irq_id: usize) -> Option<InjectionSource> {
+        self.provenance.lock().get(irq_id).copied().flatten()
+    }
+
+    /// Returns the pending sources along with the origin of their injection.
+    pub fn pending_provenance(&self) -> Vec<(usize, Option<InjectionSource>)> {
+        let pending = self.pending_irqs.snapshot();
+        let provenance = self.provenance.lock();
+        pending.iter().map(|irq_id| (irq_id, provenance[irq_id])).collect()
+    }
+
+    /// Records that `source` injected `irq_id`; `was_pending` tells whether
+    /// the injection was collapsed into an already pending interrupt, in
+    /// which case the original origin is kept.
+    pub(crate) fn tag_injection(&self, irq_id: usize, source: InjectionSource, was_pending: bool) {
+        self.attribute_injection(irq_id, source, was_pending);
+        if !was_pending {
+            self.provenance.lock()[irq_id] = Some(source);
+        }
+    }
+}
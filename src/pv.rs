@@ -0,0 +1,215 @@
+//! Optional paravirtual identification/feature page.
+//!
+//! The page sits at a configurable offset outside the standard PLIC map and
+//! lets enlightened guests discover the vPLIC version, the number of
+//! virtual-only sources and the supported features, then negotiate faster
+//! paths by writing the feature bits they want to use, such as claiming or
+//! completing several IRQs with a single access (batched claim and EOI).
+
+use core::sync::atomic::Ordering;
+
+use axerrno::{AxError, AxResult};
+
+use crate::banks::BankIter;
+use crate::host::HostBackend;
+use crate::{
+    source_bit, word_source, VPlicGlobal, PLIC_CONTEXT_CTRL_OFFSET, PLIC_CONTEXT_STRIDE, PLIC_NUM_BANKS,
+};
+
+/// Size of the paravirtual page in bytes.
+pub const PV_PAGE_SIZE: usize = 0x1000;
+
+/// Magic value of [`PV_MAGIC_OFFSET`] ("VPLC").
+pub const PV_MAGIC: u32 = 0x434c_5056;
+
+/// Version of the paravirtual interface.
+pub const PV_VERSION: u32 = 1;
+
+/// Offset of the magic register (RO).
+pub const PV_MAGIC_OFFSET: usize = 0x00;
+/// Offset of the version register (RO).
+pub const PV_VERSION_OFFSET: usize = 0x04;
+/// Offset of the number of virtual-only sources (RO).
+pub const PV_VIRTUAL_SOURCES_OFFSET: usize = 0x08;
+/// Offset of the supported feature bits (RO).
+pub const PV_FEATURES_OFFSET: usize = 0x0c;
+/// Offset of the negotiated feature bits (RW, masked by the supported ones).
+pub const PV_ENABLED_FEATURES_OFFSET: usize = 0x10;
+
+/// Offset of the first batched claim/EOI window (RW).
+///
+/// Context C owns [`PV_BATCH_EOI_STRIDE`] bytes at
+/// `PV_BATCH_EOI_OFFSET + C * PV_BATCH_EOI_STRIDE`; writing a mask to word W
+/// of that window completes every source `W * 32 + bit` set in the mask, and
+/// reading word W claims every eligible source of that word and returns
+/// their mask.
+pub const PV_BATCH_EOI_OFFSET: usize = 0x100;
+/// Stride between the batched claim/EOI windows of two contexts.
+pub const PV_BATCH_EOI_STRIDE: usize = 0x80;
+
+/// Feature bit: batched EOI through the [`PV_BATCH_EOI_OFFSET`] windows.
+pub const PV_FEATURE_BATCH_EOI: u32 = 1 << 0;
+/// Feature bit: batched claim through the [`PV_BATCH_EOI_OFFSET`] windows.
+pub const PV_FEATURE_BATCH_CLAIM: u32 = 1 << 1;
+
+/// Feature bits supported by this implementation.
+pub const PV_SUPPORTED_FEATURES: u32 = PV_FEATURE_BATCH_EOI | PV_FEATURE_BATCH_CLAIM;
+
+impl VPlicGlobal {
+    /// Exposes the paravirtual page at `offset` from the vPLIC base,
+    /// announcing `virtual_sources` virtual-only sources.
+    ///
+    /// The page must lie within the device region, after the context control
+    /// registers.
+    pub fn enable_pv_page(&self, offset: usize, virtual_sources: u32) -> AxResult {
+        let map_end = PLIC_CONTEXT_CTRL_OFFSET + self.contexts_num * PLIC_CONTEXT_STRIDE;
+        if offset < map_end || offset % PV_PAGE_SIZE != 0 || offset + PV_PAGE_SIZE > self.size {
+            return Err(AxError::InvalidInput);
+        }
+        self.pv_virtual_sources.store(virtual_sources, Ordering::Relaxed);
+        self.pv_features.store(0, Ordering::Relaxed);
+        self.pv_offset.store(offset, Ordering::Release);
+        Ok(())
+    }
+
+    /// Hides the paravirtual page and drops the negotiated features.
+    pub fn disable_pv_page(&self) {
+        self.pv_offset.store(0, Ordering::Release);
+        self.pv_features.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the features negotiated by the guest.
+    pub fn pv_features(&self) -> u32 {
+        self.pv_features.load(Ordering::Relaxed)
+    }
+
+    /// Returns the offset of `reg` within the paravirtual page, if it hits it.
+    pub(crate) fn pv_reg(&self, reg: usize) -> Option<usize> {
+        let offset = self.pv_offset.load(Ordering::Acquire);
+        if offset != 0 && (offset..offset + PV_PAGE_SIZE).contains(&reg) {
+            Some(reg - offset)
+        } else {
+            None
+        }
+    }
+
+    /// Handles a guest read of the paravirtual page.
+    pub(crate) fn pv_read(&self, reg: usize) -> AxResult<usize> {
+        let val = match reg {
+            PV_MAGIC_OFFSET => PV_MAGIC,
+            PV_VERSION_OFFSET => PV_VERSION,
+            PV_VIRTUAL_SOURCES_OFFSET => self.pv_virtual_sources.load(Ordering::Relaxed),
+            PV_FEATURES_OFFSET => PV_SUPPORTED_FEATURES,
+            PV_ENABLED_FEATURES_OFFSET => self.pv_features(),
+            PV_BATCH_EOI_OFFSET.. => {
+                let context_id = (reg - PV_BATCH_EOI_OFFSET) / PV_BATCH_EOI_STRIDE;
+                let word_index = (reg - PV_BATCH_EOI_OFFSET) % PV_BATCH_EOI_STRIDE / 4;
+                self.batch_claim(context_id, word_index)?
+            }
+            _ => 0,
+        };
+        Ok(val as usize)
+    }
+
+    /// Handles a guest write of the paravirtual page.
+    pub(crate) fn pv_write(&self, reg: usize, val: usize) -> AxResult {
+        match reg {
+            PV_ENABLED_FEATURES_OFFSET => {
+                self.pv_features.store(val as u32 & PV_SUPPORTED_FEATURES, Ordering::Relaxed);
+                Ok(())
+            }
+            PV_BATCH_EOI_OFFSET.. => {
+                let context_id = (reg - PV_BATCH_EOI_OFFSET) / PV_BATCH_EOI_STRIDE;
+                let word_index = (reg - PV_BATCH_EOI_OFFSET) % PV_BATCH_EOI_STRIDE / 4;
+                self.batch_complete(context_id, word_index, val as u32)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Claims every eligible source of word `word_index` on behalf of
+    /// `context_id`, returning their mask.
+    ///
+    /// Each source goes through the claim path of the claim register, so the
+    /// batch holds exactly what back-to-back claims would return: sources
+    /// above the context threshold, subject to the back-to-back claim policy
+    /// and the claim interceptor.
+    fn batch_claim(&self, context_id: usize, word_index: usize) -> AxResult<u32> {
+        if self.pv_features() & PV_FEATURE_BATCH_CLAIM == 0 {
+            return Err(AxError::Unsupported);
+        }
+        if context_id >= self.contexts_num || word_index >= PLIC_NUM_BANKS {
+            return Err(AxError::InvalidInput);
+        }
+        self.forbid_in_hook("paravirtual claim")?;
+        let _guard = match self.enter_claim()? {
+            Some(guard) => guard,
+            None => return Ok(0),
+        };
+        let _config = self.enter_config();
+        self.sync_direct(context_id);
+        let mut mask = 0;
+        loop {
+            match self.claim_in(context_id, 1 << word_index) {
+                0 => return Ok(mask),
+                irq_id => mask |= source_bit(irq_id),
+            }
+        }
+    }
+
+    /// Completes every source of word `word_index` set in `mask` on behalf of
+    /// `context_id`.
+    ///
+    /// The whole batch is rejected unless the feature was negotiated and every
+    /// source in it is active.
+    fn batch_complete(&self, context_id: usize, word_index: usize, mask: u32) -> AxResult {
+        if self.pv_features() & PV_FEATURE_BATCH_EOI == 0 {
+            return Err(AxError::Unsupported);
+        }
+        if context_id >= self.contexts_num {
+            return Err(AxError::InvalidInput);
+        }
+        let _config = self.enter_config();
+        let irqs = BankIter::new(mask).map(|bit| word_source(word_index, bit));
+        if irqs.clone().any(|irq_id| irq_id == 0 || !self.active_irqs.get(irq_id)) {
+            return Err(AxError::InvalidInput);
+        }
+        let host_addr = self.host_reg(self.host_claim_offset(context_id));
+        for irq_id in irqs {
+            self.complete(context_id, irq_id, host_addr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PV_BATCH_EOI_OFFSET, PV_ENABLED_FEATURES_OFFSET, PV_SUPPORTED_FEATURES};
+    use crate::mock::testing::*;
+    use crate::{BackToBackClaim, PLIC_CONTEXT_CTRL_OFFSET, PLIC_CONTEXT_STRIDE};
+
+    #[test]
+    fn batch_claim_follows_claim_rules() {
+        let vplic = mock_vplic(1);
+        let page = PLIC_CONTEXT_CTRL_OFFSET + PLIC_CONTEXT_STRIDE;
+        vplic.enable_pv_page(page, 0).unwrap();
+        write(&vplic, page + PV_ENABLED_FEATURES_OFFSET, PV_SUPPORTED_FEATURES as usize);
+        route(&vplic, 0, 1, 1);
+        route(&vplic, 0, 2, 2);
+        route(&vplic, 0, 3, 0);
+        for irq_id in 1..=3 {
+            vplic.inject_irq(irq_id).unwrap();
+        }
+        write(&vplic, threshold_reg(0), 1);
+        assert_eq!(read(&vplic, page + PV_BATCH_EOI_OFFSET), 1 << 2);
+        assert!(vplic.is_irq_pending(1));
+        assert!(vplic.is_irq_pending(3));
+
+        vplic.set_back_to_back_claim(BackToBackClaim::Zero);
+        write(&vplic, threshold_reg(0), 0);
+        assert_eq!(read(&vplic, page + PV_BATCH_EOI_OFFSET), 0);
+        write(&vplic, page + PV_BATCH_EOI_OFFSET, 1 << 2);
+        assert_eq!(read(&vplic, page + PV_BATCH_EOI_OFFSET), 1 << 1);
+        assert!(vplic.is_irq_pending(3));
+    }
+}
@@ -0,0 +1,102 @@
+//! Emergency quiesce of the host PLIC.
+//!
+//! When the hypervisor panics or shuts down in a hurry, the physical devices
+//! passed through to guests keep interrupting a system nobody services any
+//! more. [`VPlicGlobal::panic_quiesce`] masks them on the host PLIC by
+//! zeroing the host priority of every assigned source: one register per
+//! source, so no enable word shared with the hypervisor's own sources is
+//! read back and rewritten.
+//!
+//! Quiescing runs in a panic handler, possibly on a hart that panicked
+//! with vPLIC locks held, so it takes no lock, allocates nothing, reads
+//! the assignments without locking them and gives up quietly if no HAL is
+//! installed. vPLICs registered with
+//! [`VPlicGlobal::register_panic_quiesce`] are all quiesced by
+//! [`panic_quiesce_all`], a plain `fn()` for the host's panic and shutdown
+//! hooks.
+
+use core::ptr::{self, null_mut};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use axerrno::{AxError, AxResult};
+
+use crate::hal::try_hal;
+use crate::host::HostBackend;
+use crate::{VPlicGlobal, PLIC_PRIORITY_OFFSET};
+
+/// Maximum number of vPLICs registered for the emergency quiesce.
+pub const MAX_QUIESCE_INSTANCES: usize = 16;
+
+/// vPLICs quiesced by [`panic_quiesce_all`], null for a free slot.
+static QUIESCE_INSTANCES: [AtomicPtr<VPlicGlobal>; MAX_QUIESCE_INSTANCES] =
+    [const { AtomicPtr::new(null_mut()) }; MAX_QUIESCE_INSTANCES];
+
+impl VPlicGlobal {
+    /// Masks every source assigned to the guest on the host PLIC.
+    ///
+    /// Safe from a panic handler: takes no lock and never panics itself.
+    /// The assignments are read unlocked, so a source assigned concurrently
+    /// may be missed. Emulated vPLICs have nothing to quiesce, nor has a
+    /// vPLIC without a HAL to reach the host with.
+    pub fn panic_quiesce(&self) {
+        if self.is_emulated() {
+            return;
+        }
+        let hal = match try_hal() {
+            Some(hal) => hal,
+            None => return,
+        };
+        // SAFETY: a racy copy of a plain bitmap; the lock may be held by the
+        // code that panicked and is never released.
+        let assigned = unsafe { ptr::read_volatile(self.assigned_irqs.as_mut_ptr()) };
+        for host_irq in assigned.into_iter().filter(|&host_irq| host_irq != 0) {
+            let addr = hal.phys_to_virt(self.host_reg(PLIC_PRIORITY_OFFSET + host_irq * 4));
+            // SAFETY: the priority register of an assigned source of the
+            // host PLIC, mapped by the HAL.
+            unsafe { (addr.as_mut_ptr() as *mut u32).write_volatile(0) };
+        }
+    }
+
+    /// Registers this vPLIC to be quiesced by [`panic_quiesce_all`].
+    ///
+    /// Registering twice has no effect. Fails with [`AxError::NoMemory`]
+    /// once [`MAX_QUIESCE_INSTANCES`] vPLICs are registered.
+    pub fn register_panic_quiesce(&'static self) -> AxResult {
+        let this = self as *const Self as *mut Self;
+        if QUIESCE_INSTANCES
+            .iter()
+            .any(|slot| slot.load(Ordering::Acquire) == this)
+        {
+            return Ok(());
+        }
+        QUIESCE_INSTANCES
+            .iter()
+            .find(|slot| {
+                slot.compare_exchange(null_mut(), this, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+            })
+            .map(|_| ())
+            .ok_or(AxError::NoMemory)
+    }
+
+    /// Unregisters this vPLIC from [`panic_quiesce_all`].
+    pub fn unregister_panic_quiesce(&self) {
+        let this = self as *const Self as *mut Self;
+        for slot in &QUIESCE_INSTANCES {
+            let _ = slot.compare_exchange(this, null_mut(), Ordering::AcqRel, Ordering::Acquire);
+        }
+    }
+}
+
+/// Quiesces every vPLIC registered with
+/// [`VPlicGlobal::register_panic_quiesce`]. Meant to be installed as (or
+/// called from) the host's panic and emergency shutdown hooks.
+pub fn panic_quiesce_all() {
+    for slot in &QUIESCE_INSTANCES {
+        let vplic = slot.load(Ordering::Acquire);
+        // SAFETY: only `&'static` vPLICs are registered.
+        if let Some(vplic) = unsafe { vplic.as_ref() } {
+            vplic.panic_quiesce();
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Table-driven decoding of the PLIC register map.
+//!
+//! Every region of the map is described once (offsets, per-context window,
+//! handler functions), so adding a region does not mean growing a `match`
+//! with duplicated offset math in both access paths.
+//!
+//! The registers are 32 bits wide. A byte or halfword access (e.g. from
+//! firmware probing the priorities) reaches one [`Lane`] of a register: it
+//! reads the register and extracts the lane, or writes it back with the lane
+//! replaced. A doubleword access to the pending or enable arrays is split
+//! into accesses to two adjacent registers, the lower one first.
+
+use axaddrspace::{device::AccessWidth, HostPhysAddr};
+use axerrno::AxResult;
+
+use crate::consts::*;
+use crate::VPlicGlobal;
+
+/// Kind of a register region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RegionKind {
+    Priority,
+    Pending,
+    Enable,
+    Threshold,
+    ClaimComplete,
+    /// Reserved offsets of a context's control page, read as zero and
+    /// ignoring writes.
+    ContextReserved,
+    /// Reserved holes of the map (past the pending words, windows of
+    /// contexts that do not exist), read as zero and ignoring writes, as
+    /// probes scan over them.
+    Reserved,
+}
+
+/// A decoded guest access.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RegAccess {
+    /// Offset of the access from the vPLIC base.
+    pub reg: usize,
+    /// Context owning the register, 0 for global regions. Always a context
+    /// of the vPLIC: [`decode`] maps the windows of the others to the
+    /// reserved region.
+    pub context_id: usize,
+    /// Index of the 32-bit register within the region (or context window).
+    pub index: usize,
+    /// Width of the access.
+    pub width: AccessWidth,
+    /// Host address backing the register.
+    pub host_addr: HostPhysAddr,
+}
+
+/// The bits of a 32-bit register reached by an access narrower than it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Lane {
+    shift: u32,
+    mask: u32,
+}
+
+impl Lane {
+    /// Returns the offset of the register holding `reg` and the lane a
+    /// `width` access to `reg` reaches, or `None` if the access is misaligned
+    /// or wider than a register.
+    pub(crate) fn of(reg: usize, width: AccessWidth) -> Option<(usize, Self)> {
+        let size = match width {
+            AccessWidth::Byte => 1,
+            AccessWidth::Word => 2,
+            AccessWidth::Dword => 4,
+            AccessWidth::Qword => return None,
+        };
+        if reg % size != 0 {
+            return None;
+        }
+        let shift = (reg % 4 * 8) as u32;
+        let mask = (u32::MAX >> (32 - size * 8)) << shift;
+        Some((reg & !3, Self { shift, mask }))
+    }
+
+    /// Returns `true` if the lane is the whole register.
+    pub(crate) fn is_full(self) -> bool {
+        self.mask == u32::MAX
+    }
+
+    /// Returns the lane of register value `word`.
+    pub(crate) fn extract(self, word: u32) -> usize {
+        ((word & self.mask) >> self.shift) as usize
+    }
+
+    /// Returns register value `word` with the lane replaced by `val`.
+    pub(crate) fn insert(self, word: u32, val: usize) -> u32 {
+        (word & !self.mask) | (((val as u32) << self.shift) & self.mask)
+    }
+}
+
+type ReadFn = fn(&VPlicGlobal, &RegAccess) -> AxResult<usize>;
+type WriteFn = fn(&VPlicGlobal, &RegAccess, usize) -> AxResult;
+
+/// Description of a register region.
+pub(crate) struct RegionDesc {
+    pub kind: RegionKind,
+    /// First offset of the region.
+    pub start: usize,
+    /// End offset of the region (exclusive).
+    pub end: usize,
+    /// Size of each context's window, 0 if the region is global.
+    pub context_stride: usize,
+    /// Offset of the only register within each window, if any.
+    pub reg_offset: Option<usize>,
+    /// Number of registers of the region (or of each window); the offsets
+    /// past them are holes.
+    pub regs_num: usize,
+    pub read: ReadFn,
+    pub write: WriteFn,
+}
+
+/// The PLIC register map.
+pub(crate) static REGIONS: [RegionDesc; 7] = [
+    RegionDesc {
+        kind: RegionKind::Priority,
+        start: PLIC_PRIORITY_OFFSET,
+        end: PLIC_PENDING_OFFSET,
+        context_stride: 0,
+        reg_offset: None,
+        regs_num: PLIC_NUM_SOURCES,
+        read: VPlicGlobal::read_priority,
+        write: VPlicGlobal::write_priority,
+    },
+    RegionDesc {
+        kind: RegionKind::Pending,
+        start: PLIC_PENDING_OFFSET,
+        end: PLIC_ENABLE_OFFSET,
+        context_stride: 0,
+        reg_offset: None,
+        regs_num: PLIC_NUM_BANKS,
+        read: VPlicGlobal::read_pending,
+        write: VPlicGlobal::write_pending,
+    },
+    RegionDesc {
+        kind: RegionKind::Enable,
+        start: PLIC_ENABLE_OFFSET,
+        end: PLIC_CONTEXT_CTRL_OFFSET,
+        context_stride: PLIC_ENABLE_STRIDE,
+        reg_offset: None,
+        regs_num: PLIC_NUM_BANKS,
+        read: VPlicGlobal::read_enable,
+        write: VPlicGlobal::write_enable,
+    },
+    RegionDesc {
+        kind: RegionKind::Threshold,
+        start: PLIC_CONTEXT_CTRL_OFFSET,
+        end: usize::MAX,
+        context_stride: PLIC_CONTEXT_STRIDE,
+        reg_offset: Some(PLIC_CONTEXT_THRESHOLD_OFFSET),
+        regs_num: usize::MAX,
+        read: VPlicGlobal::read_threshold,
+        write: VPlicGlobal::write_threshold,
+    },
+    RegionDesc {
+        kind: RegionKind::ClaimComplete,
+        start: PLIC_CONTEXT_CTRL_OFFSET,
+        end: usize::MAX,
+        context_stride: PLIC_CONTEXT_STRIDE,
+        reg_offset: Some(PLIC_CONTEXT_CLAIM_COMPLETE_OFFSET),
+        regs_num: usize::MAX,
+        read: VPlicGlobal::read_claim,
+        write: VPlicGlobal::write_complete,
+    },
+    RegionDesc {
+        kind: RegionKind::ContextReserved,
+        start: PLIC_CONTEXT_CTRL_OFFSET,
+        end: usize::MAX,
+        context_stride: PLIC_CONTEXT_STRIDE,
+        reg_offset: None,
+        regs_num: usize::MAX,
+        read: VPlicGlobal::read_zero,
+        write: VPlicGlobal::write_ignore,
+    },
+    RegionDesc {
+        kind: RegionKind::Reserved,
+        start: 0,
+        end: usize::MAX,
+        context_stride: 0,
+        reg_offset: None,
+        regs_num: usize::MAX,
+        read: VPlicGlobal::read_zero,
+        write: VPlicGlobal::write_ignore,
+    },
+];
+
+/// Decodes a doubleword access at `reg` into its two register accesses,
+/// lower first, or returns `None` unless it is aligned and both registers
+/// belong to the same pending or enable array, or to holes.
+pub(crate) fn decode_qword(
+    reg: usize,
+    host_reg: impl Fn(usize) -> HostPhysAddr,
+    contexts_num: usize,
+) -> Option<[(&'static RegionDesc, RegAccess); 2]> {
+    if reg % 8 != 0 {
+        return None;
+    }
+    let low = decode(reg, AccessWidth::Dword, host_reg(reg), contexts_num)?;
+    let high = decode(reg + 4, AccessWidth::Dword, host_reg(reg + 4), contexts_num)?;
+    let splittable = matches!(
+        low.0.kind,
+        RegionKind::Pending | RegionKind::Enable | RegionKind::Reserved
+    )
+        && low.0.kind == high.0.kind
+        && low.1.context_id == high.1.context_id;
+    splittable.then_some([low, high])
+}
+
+/// Finds the region holding `reg` and decodes the context and register index,
+/// given the number of contexts of the vPLIC.
+///
+/// Regions are matched in order, so a region may be shadowed by the more
+/// specific ones listed before it; holes fall through to the reserved region
+/// last.
+pub(crate) fn decode(
+    reg: usize,
+    width: AccessWidth,
+    host_addr: HostPhysAddr,
+    contexts_num: usize,
+) -> Option<(&'static RegionDesc, RegAccess)> {
+    REGIONS.iter().find_map(|desc| {
+        if !(desc.start..desc.end).contains(&reg) {
+            return None;
+        }
+        let offset = reg - desc.start;
+        let (context_id, within) = match desc.context_stride {
+            0 => (0, offset),
+            stride => (offset / stride, offset % stride),
+        };
+        if desc.reg_offset.is_some_and(|reg_offset| reg_offset != within) {
+            return None;
+        }
+        if within / 4 >= desc.regs_num || (desc.context_stride != 0 && context_id >= contexts_num) {
+            return None;
+        }
+        let access = RegAccess {
+            reg,
+            context_id,
+            index: within / 4,
+            width,
+            host_addr,
+        };
+        Some((desc, access))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::*;
+    use crate::mock::testing::*;
+
+    #[test]
+    fn holes_read_as_zero_and_ignore_writes() {
+        let vplic = mock_vplic(1);
+        let holes = [
+            PLIC_PENDING_OFFSET + PLIC_NUM_BANKS * 4,
+            PLIC_ENABLE_OFFSET + PLIC_NUM_BANKS * 4,
+            PLIC_CONTEXT_CTRL_OFFSET + 8,
+            threshold_reg(1),
+        ];
+        for hole in holes {
+            write(&vplic, hole, u32::MAX as usize);
+            assert_eq!(read(&vplic, hole), 0, "hole {:#x}", hole);
+        }
+        assert_eq!(vplic.threshold(0).unwrap(), 0);
+        assert_eq!(read(&vplic, enable_reg(0, 0)), 0);
+    }
+}
@@ -0,0 +1,256 @@
+//! Handlers of the guest-visible PLIC registers.
+//!
+//! [`crate::regmap`] decodes an access to one of these; each handler then
+//! works on the shadow state and forwards to the host backend as needed.
+
+use axerrno::{AxError, AxResult};
+use log::trace;
+
+use crate::banks::BankIter;
+use crate::delivery::GuestNotifier;
+use crate::host::HostBackend;
+use crate::regmap::{Lane, RegAccess, RegionDesc, RegionKind};
+use crate::utils::normalize_word_access;
+use crate::{word_source, InjectionSource, PendingW1c, SourceEvent, VPlicGlobal};
+
+impl VPlicGlobal {
+    pub(crate) fn read_priority(&self, access: &RegAccess) -> AxResult<usize> {
+        Ok(self.priority(access.index) as usize)
+    }
+
+    pub(crate) fn write_priority(&self, access: &RegAccess, val: usize) -> AxResult {
+        self.set_priority(access.index, val as u32)
+    }
+
+    pub(crate) fn read_pending(&self, access: &RegAccess) -> AxResult<usize> {
+        let mut pending = self.pending_irqs.word(access.index);
+        // Pending bit 0 belongs to the reserved source 0 and reads as 0.
+        if access.index == 0 {
+            pending &= !1;
+        }
+        Ok(pending as usize)
+    }
+
+    // Guest write to its own pending registers, read-only unless self-injection is allowed;
+    // the hypervisor injects through `inject_irq`.
+    pub(crate) fn write_pending(&self, access: &RegAccess, val: usize) -> AxResult {
+        let pending = self.pending_irqs.word(access.index);
+        let w1c = self.detect_w1c(access.index, pending, val as u32);
+        if !self.guest_self_injection() {
+            trace!("vPlicGlobal: ignore guest write {val:#x} to pending word {}", access.index);
+            return Ok(());
+        }
+        match w1c {
+            Some(PendingW1c::Ignore) => return Ok(()),
+            Some(PendingW1c::Clear) => {
+                for bit in BankIter::new(val as u32 & pending) {
+                    let irq_id = word_source(access.index, bit);
+                    self.try_transition(irq_id, SourceEvent::Drop);
+                }
+                self.refresh_deliverability();
+                return Ok(());
+            }
+            Some(PendingW1c::Inject) | None => {}
+        }
+        for bit in BankIter::new(val as u32) {
+            let irq_id = word_source(access.index, bit);
+            if irq_id != 0 && self.chaos_filter_injection(irq_id, InjectionSource::Guest) {
+                // Set the pending bit.
+                let was_pending = self.set_pending(irq_id);
+                self.count_injection(irq_id, was_pending);
+                self.tag_injection(irq_id, InjectionSource::Guest, was_pending);
+            }
+        }
+
+        // Inject the interrupt to the hart by setting the VSEIP bit in HVIP register.
+        if !self.pending_irqs.is_empty() {
+            self.assert_vseip();
+        }
+        self.refresh_deliverability();
+
+        Ok(())
+    }
+
+    pub(crate) fn read_enable(&self, access: &RegAccess) -> AxResult<usize> {
+        Ok(self.enable_word(access.context_id, access.index) as usize)
+    }
+
+    pub(crate) fn write_enable(&self, access: &RegAccess, val: usize) -> AxResult {
+        let (old, new) = match self.defer_enable(access.context_id, access.index, val as u32)? {
+            Some(old) => (old, val as u32),
+            None => {
+                let mut old = 0;
+                let new = self.modify_enable(access.context_id, access.index, |word| {
+                    old = word;
+                    val as u32
+                })?;
+                (old, new)
+            }
+        };
+        self.notify_enable_changes(access.context_id, access.index, old, new);
+        self.refresh_affinity(access.index, old ^ new);
+        Ok(())
+    }
+
+    pub(crate) fn read_threshold(&self, access: &RegAccess) -> AxResult<usize> {
+        self.threshold(access.context_id).map(|val| val as usize)
+    }
+
+    pub(crate) fn write_threshold(&self, access: &RegAccess, val: usize) -> AxResult {
+        self.set_threshold(access.context_id, val as u32)
+    }
+
+    pub(crate) fn read_claim(&self, access: &RegAccess) -> AxResult<usize> {
+        let context_id = access.context_id;
+        let _guard = match self.enter_claim()? {
+            Some(guard) => guard,
+            None => return Ok(0),
+        };
+        let _config = self.enter_config();
+        self.sync_direct(context_id);
+        Ok(self.claim(context_id))
+    }
+
+    pub(crate) fn write_complete(&self, access: &RegAccess, val: usize) -> AxResult {
+        // info!("vPlicGlobal: Writing to CLAIM/COMPLETE reg {reg:#x} val {val:#x}");
+        let context_id = access.context_id;
+        let irq_id = normalize_word_access(access.width, val)? as usize;
+        let _config = self.enter_config();
+        self.complete(context_id, irq_id, self.host_reg(self.host_claim_offset(context_id)))
+    }
+
+    /// Reads `lane` of the register of `access` in region `desc`.
+    pub(crate) fn read_lane(
+        &self,
+        desc: &RegionDesc,
+        access: &RegAccess,
+        lane: Lane,
+    ) -> AxResult<usize> {
+        if lane.is_full() {
+            return (desc.read)(self, access);
+        }
+        self.check_lane(desc)?;
+        (desc.read)(self, access).map(|val| lane.extract(val as u32))
+    }
+
+    /// Writes `val` into `lane` of the register of `access` in region
+    /// `desc`, by a read-modify-write of the register.
+    pub(crate) fn write_lane(
+        &self,
+        desc: &RegionDesc,
+        access: &RegAccess,
+        lane: Lane,
+        val: usize,
+    ) -> AxResult {
+        if lane.is_full() {
+            return (desc.write)(self, access, val);
+        }
+        self.check_lane(desc)?;
+        // Pending writes add to the pending bits: write the lane alone, not
+        // the bits already pending.
+        let word = match desc.kind {
+            RegionKind::Pending => 0,
+            _ => (desc.read)(self, access)? as u32,
+        };
+        (desc.write)(self, access, lane.insert(word, val) as usize)
+    }
+
+    /// Reads the two registers of a doubleword access, the lower one in the
+    /// low half.
+    pub(crate) fn read_qword(&self, halves: &[(&RegionDesc, RegAccess); 2]) -> AxResult<usize> {
+        let [(low_desc, low), (high_desc, high)] = halves;
+        let low = (low_desc.read)(self, low)? as u32;
+        let high = (high_desc.read)(self, high)? as u32;
+        Ok((((high as u64) << 32) | low as u64) as usize)
+    }
+
+    /// Writes the two registers of a doubleword access, the lower one first.
+    pub(crate) fn write_qword(
+        &self,
+        halves: &[(&RegionDesc, RegAccess); 2],
+        val: usize,
+    ) -> AxResult {
+        let [(low_desc, low), (high_desc, high)] = halves;
+        (low_desc.write)(self, low, val as u32 as usize)?;
+        (high_desc.write)(self, high, ((val as u64) >> 32) as usize)
+    }
+
+    /// Fails narrow accesses to registers that cannot be read back without
+    /// side effects: a narrow claim would claim the IRQ and return part of
+    /// its ID.
+    fn check_lane(&self, desc: &RegionDesc) -> AxResult {
+        match desc.kind {
+            RegionKind::ClaimComplete => Err(AxError::InvalidInput),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn read_zero(&self, _access: &RegAccess) -> AxResult<usize> {
+        Ok(0)
+    }
+
+    pub(crate) fn write_ignore(&self, _access: &RegAccess, _val: usize) -> AxResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axaddrspace::device::AccessWidth;
+
+    use crate::mock::testing::*;
+    use crate::{word_source, PendingW1c, PLIC_NUM_BANKS};
+
+    #[test]
+    fn pending_words_map_injected_sources() {
+        let vplic = mock_vplic(1);
+        // One source per word, at a different bit each time; bit 0 of word 0
+        // is the reserved source 0.
+        let bit = |word: usize| (word * 7) % 31 + 1;
+        for word in 0..PLIC_NUM_BANKS {
+            vplic.inject_irq(word_source(word, bit(word))).unwrap();
+        }
+        for word in 0..PLIC_NUM_BANKS {
+            assert_eq!(read(&vplic, pending_reg(word)), 1 << bit(word), "pending word {}", word);
+        }
+        assert!(vplic.is_irq_pending(32 * 31 + bit(31)));
+    }
+
+    #[test]
+    fn w1c_clear_needs_writable_pending() {
+        let vplic = mock_vplic(1);
+        vplic.set_pending_w1c(PendingW1c::Clear);
+        vplic.inject_irq(7).unwrap();
+        write(&vplic, pending_reg(0), 1 << 7);
+        assert!(vplic.is_irq_pending(7));
+        assert_eq!(vplic.w1c_attempts(), 1);
+        vplic.set_guest_self_injection(true);
+        write(&vplic, pending_reg(0), 1 << 7);
+        assert!(!vplic.is_irq_pending(7));
+    }
+
+    #[test]
+    fn narrow_accesses_reach_one_lane() {
+        let vplic = mock_vplic(1);
+        write_width(&vplic, priority_reg(3), AccessWidth::Byte, 5).unwrap();
+        assert_eq!(read(&vplic, priority_reg(3)), 5);
+        write(&vplic, enable_reg(0, 0), 0x1234_5678);
+        assert_eq!(read_width(&vplic, enable_reg(0, 0) + 2, AccessWidth::Word).unwrap(), 0x1234);
+        write_width(&vplic, enable_reg(0, 0) + 1, AccessWidth::Byte, 0xab).unwrap();
+        assert_eq!(read(&vplic, enable_reg(0, 0)), 0x1234_ab78);
+        assert!(read_width(&vplic, priority_reg(3) + 1, AccessWidth::Word).is_err());
+    }
+
+    #[test]
+    fn doubleword_accesses_split_into_two_registers() {
+        let vplic = mock_vplic(1);
+        vplic.inject_irq(3).unwrap();
+        vplic.inject_irq(36).unwrap();
+        assert_eq!(read_width(&vplic, pending_reg(0), AccessWidth::Qword).unwrap(), 1 << 36 | 1 << 3);
+        write_width(&vplic, enable_reg(0, 0), AccessWidth::Qword, 1 << 33 | 1 << 2).unwrap();
+        assert_eq!(read(&vplic, enable_reg(0, 0)), 1 << 2);
+        assert_eq!(read(&vplic, enable_reg(0, 1)), 1 << 1);
+        assert!(read_width(&vplic, priority_reg(2), AccessWidth::Qword).is_err());
+        assert!(read_width(&vplic, pending_reg(1), AccessWidth::Qword).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Compaction of sparse IRQ assignments.
+//!
+//! Host IRQ numbers assigned to a guest are often large and scattered. With
+//! compaction, the guest sees them as a dense range starting at 1; the vPLIC
+//! translates guest source numbers to host ones wherever it touches the host
+//! PLIC (priorities, enables, completions). Injection APIs keep taking guest
+//! numbers, [`VPlicGlobal::guest_irq`] translates host numbers for them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Translation table between guest and host source numbers.
+pub(crate) struct IrqMap {
+    /// Host source of each guest source.
+    to_host: Vec<usize>,
+    /// Guest source of each host source, 0 if not mapped.
+    to_guest: Vec<usize>,
+}
+
+impl IrqMap {
+    /// Creates the identity mapping.
+    pub(crate) fn identity() -> Self {
+        Self {
+            to_host: (0..PLIC_NUM_SOURCES).collect(),
+            to_guest: (0..PLIC_NUM_SOURCES).collect(),
+        }
+    }
+}
+
+impl VPlicGlobal {
+    /// Presents the assigned host IRQs to the guest as the dense range
+    /// `1..=n`, in ascending host order.
+    ///
+    /// Must be called before the guest boots. Returns the (guest IRQ, host
+    /// IRQ) pairs of the new mapping.
+    pub fn compact_irqs(&self) -> Vec<(usize, usize)> {
+        let pairs: Vec<_> = self
+            .assigned_irqs
+            .lock()
+            .into_iter()
+            .filter(|&host_irq| host_irq != 0)
+            .enumerate()
+            .map(|(index, host_irq)| (index + 1, host_irq))
+            .collect();
+        let mut to_host = vec![0; PLIC_NUM_SOURCES];
+        let mut to_guest = vec![0; PLIC_NUM_SOURCES];
+        for &(guest_irq, host_irq) in &pairs {
+            to_host[guest_irq] = host_irq;
+            to_guest[host_irq] = guest_irq;
+        }
+        *self.irq_map.lock() = IrqMap { to_host, to_guest };
+        pairs
+    }
+
+    /// Restores the identity mapping between guest and host IRQs.
+    pub fn reset_irq_map(&self) {
+        *self.irq_map.lock() = IrqMap::identity();
+    }
+
+    /// Returns the host IRQ backing guest source `irq_id`, 0 if none.
+    pub fn host_irq(&self, irq_id: usize) -> usize {
+        self.irq_map.lock().to_host.get(irq_id).copied().unwrap_or(0)
+    }
+
+    /// Returns the guest source presenting `host_irq`, if mapped.
+    pub fn guest_irq(&self, host_irq: usize) -> Option<usize> {
+        match self.irq_map.lock().to_guest.get(host_irq) {
+            Some(&irq_id) if irq_id != 0 => Some(irq_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the host IRQ whose completion a guest completion of `irq_id`
+    /// forwards, if any: the mapped host source, provided the reverse lookup
+    /// maps it back to `irq_id`. Guest sources without a host source after
+    /// compaction forward nothing.
+    pub(crate) fn complete_target(&self, irq_id: usize) -> Option<usize> {
+        let irq_map = self.irq_map.lock();
+        let host_irq = irq_map.to_host.get(irq_id).copied().unwrap_or(0);
+        match irq_map.to_guest.get(host_irq) {
+            Some(&guest_irq) if host_irq != 0 && guest_irq == irq_id => Some(host_irq),
+            _ => None,
+        }
+    }
+
+    /// Translates the host IRQs of a device into the values of its guest
+    /// device-tree `interrupts` property.
+    pub fn dt_interrupts(&self, host_irqs: &[usize]) -> AxResult<Vec<u32>> {
+        host_irqs
+            .iter()
+            .map(|&host_irq| {
+                self.guest_irq(host_irq)
+                    .map(|irq_id| irq_id as u32)
+                    .ok_or(AxError::NotFound)
+            })
+            .collect()
+    }
+
+    /// Returns the value of the guest device-tree `riscv,ndev` property.
+    pub fn dt_ndev(&self) -> u32 {
+        let irq_map = self.irq_map.lock();
+        irq_map.to_host.iter().rposition(|&host_irq| host_irq != 0).unwrap_or(0) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::testing::*;
+    use crate::{SourceInfo, SourceState};
+
+    #[test]
+    fn remapped_completion_targets_host_source() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(700, SourceInfo::default()).unwrap();
+        vplic.assign_source(40, SourceInfo::default()).unwrap();
+        assert_eq!(vplic.compact_irqs(), [(1, 40), (2, 700)]);
+        assert_eq!(vplic.complete_target(1), Some(40));
+        assert_eq!(vplic.complete_target(2), Some(700));
+        assert_eq!(vplic.complete_target(3), None);
+        assert_eq!(vplic.guest_irq(700), Some(2));
+
+        route(&vplic, 0, 2, 5);
+        assert_eq!(vplic.host_priority(700).unwrap(), 5);
+        vplic.inject_irq(2).unwrap();
+        assert_eq!(claim(&vplic, 0), 2);
+        complete(&vplic, 0, 2);
+        assert_eq!(vplic.source_state(2), SourceState::Inactive);
+
+        vplic.reset_irq_map();
+        assert_eq!(vplic.complete_target(2), Some(2));
+    }
+}
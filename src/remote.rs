@@ -0,0 +1,110 @@
+//! Delivery to vCPUs running on other physical harts.
+//!
+//! hvip.VSEIP only exists on the hart executing the code, while injections
+//! may come from device emulation running on another core. With a
+//! [`HartRouter`] registered, asserting the guest interrupt targets each
+//! vCPU with a deliverable interrupt: on the current hart the delivery
+//! backend is used directly, on another hart the router sends an IPI whose
+//! handler calls [`VPlicGlobal::sync_vseip`]. vCPUs not running anywhere
+//! pick their interrupts up with the same call when they are next loaded.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::delivery::GuestNotifier;
+use crate::{DeliveryMode, VPlicGlobal};
+
+/// Hypervisor services locating vCPUs and signalling other harts.
+pub trait HartRouter: Send + Sync {
+    /// Returns the ID of the hart executing the caller.
+    fn current_hart(&self) -> usize;
+    /// Returns the hart currently running `vcpu_id`, if it is running.
+    fn running_hart(&self, vcpu_id: usize) -> Option<usize>;
+    /// Asks `hart_id` to call [`VPlicGlobal::sync_vseip`] for `vcpu_id`,
+    /// typically through an IPI.
+    fn kick(&self, hart_id: usize, vcpu_id: usize) -> AxResult;
+}
+
+impl VPlicGlobal {
+    /// Registers the hart router, or removes it with `None` to always
+    /// deliver on the current hart.
+    pub fn set_hart_router(&self, router: Option<&'static dyn HartRouter>) {
+        *self.hart_router.lock() = router;
+    }
+
+    /// Updates the guest interrupt of `vcpu_id` on the current hart, which
+    /// must be running it: asserts it if one of its contexts has a
+    /// deliverable interrupt, withdraws it otherwise.
+    ///
+    /// Called on vCPU load and from the IPI sent by [`HartRouter::kick`].
+    pub fn sync_vseip(&self, vcpu_id: usize) -> AxResult {
+        if self.delivery_mode() != DeliveryMode::Interrupt || self.delivery_held() {
+            return Ok(());
+        }
+        let backend = *self.backend.lock();
+        if self.vcpu_deliverable(vcpu_id) {
+            backend.assert()
+        } else {
+            backend.deassert();
+            Ok(())
+        }
+    }
+
+    /// Re-evaluates the guest interrupt after a change of what the contexts
+    /// can take (e.g. a threshold change), asserting or withdrawing it.
+    pub(crate) fn resync_vseip(&self) {
+        if (0..self.vcpus_num()).any(|vcpu_id| self.vcpu_deliverable(vcpu_id)) {
+            self.assert_vseip();
+        } else {
+            self.deassert_vseip();
+        }
+        self.refresh_deliverability();
+    }
+
+    /// Returns `true` if a context of `vcpu_id` has a deliverable interrupt.
+    fn vcpu_deliverable(&self, vcpu_id: usize) -> bool {
+        self.contexts_of_vcpu(vcpu_id)
+            .into_iter()
+            .any(|context_id| self.should_wake(context_id))
+    }
+
+    /// Asserts the guest interrupt, routing it to the harts running the
+    /// vCPUs that have deliverable interrupts.
+    ///
+    /// Every such vCPU is notified even if another one fails; on failure,
+    /// returns the last error and the vCPUs that could not be notified.
+    pub(crate) fn assert_routed(&self) -> Result<(), (AxError, Vec<usize>)> {
+        let backend = *self.backend.lock();
+        let deliverable: Vec<usize> = (0..self.vcpus_num())
+            .filter(|&vcpu_id| self.vcpu_deliverable(vcpu_id))
+            .collect();
+        let router = match *self.hart_router.lock() {
+            Some(router) => router,
+            None if deliverable.is_empty() => {
+                backend.deassert();
+                return Ok(());
+            }
+            // The current hart stands for every vCPU.
+            None => return backend.assert().map_err(|err| (err, deliverable)),
+        };
+        let current_hart = router.current_hart();
+        let mut failure: Option<(AxError, Vec<usize>)> = None;
+        for vcpu_id in deliverable {
+            let ret = match router.running_hart(vcpu_id) {
+                Some(hart_id) if hart_id == current_hart => backend.assert(),
+                Some(hart_id) => router.kick(hart_id, vcpu_id),
+                None => Ok(()),
+            };
+            if let Err(err) = ret {
+                let (last_err, vcpus) = failure.get_or_insert_with(|| (err, Vec::new()));
+                *last_err = err;
+                vcpus.push(vcpu_id);
+            }
+        }
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+}
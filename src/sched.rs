@@ -0,0 +1,23 @@
+//! Scheduler hints derived from the interrupt state.
+
+use crate::VPlicGlobal;
+
+impl VPlicGlobal {
+    /// Returns the highest deliverable interrupt priority across the
+    /// contexts of `vcpu_id`, or 0 if none, so the scheduler can run
+    /// interrupt-driven vCPUs ahead of batch ones.
+    pub fn vcpu_pending_priority(&self, vcpu_id: usize) -> u32 {
+        self.contexts_of_vcpu(vcpu_id)
+            .into_iter()
+            .map(|context_id| self.deliverable_priority(context_id))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns `true` if `vcpu_id` has a deliverable interrupt of at least
+    /// `min_priority`.
+    pub fn vcpu_has_urgent_irq(&self, vcpu_id: usize, min_priority: u32) -> bool {
+        let priority = self.vcpu_pending_priority(vcpu_id);
+        priority != 0 && priority >= min_priority
+    }
+}
@@ -0,0 +1,71 @@
+//! Deadline-based injection scheduling.
+//!
+//! Device models may ask for an interrupt to be raised at a given time (e.g.
+//! a device signalling completion after N microseconds). Scheduled
+//! injections are kept ordered by deadline; the hypervisor arms its own timer
+//! for [`VPlicGlobal::next_deadline`] and calls
+//! [`VPlicGlobal::fire_expired`] when it goes off.
+
+use alloc::collections::BinaryHeap;
+use core::cmp::Reverse;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{InjectionSource, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Injections waiting for their deadline, earliest first.
+pub(crate) type DeadlineQueue = BinaryHeap<Reverse<(u64, usize, InjectionSource)>>;
+
+impl VPlicGlobal {
+    /// Schedules `irq_id` to become pending at `deadline_ns`, on behalf of
+    /// the hypervisor.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn inject_at(&self, irq_id: usize, deadline_ns: u64) -> AxResult {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.scheduled
+            .lock()
+            .push(Reverse((deadline_ns, irq_id, InjectionSource::Monitor)));
+        Ok(())
+    }
+
+    /// Schedules `irq_id` to become pending `delay_ns` after `now_ns`.
+    pub fn inject_after(&self, irq_id: usize, now_ns: u64, delay_ns: u64) -> AxResult {
+        self.inject_at(irq_id, now_ns.saturating_add(delay_ns))
+    }
+
+    /// Returns the earliest scheduled deadline, if any.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.scheduled.lock().peek().map(|Reverse((deadline, ..))| *deadline)
+    }
+
+    /// Injects every scheduled IRQ whose deadline is not after `now_ns`.
+    ///
+    /// Returns the number of IRQs injected.
+    pub fn fire_expired(&self, now_ns: u64) -> usize {
+        let mut count = 0;
+        loop {
+            let (irq_id, source) = {
+                let mut scheduled = self.scheduled.lock();
+                match scheduled.peek() {
+                    Some(Reverse((deadline, ..))) if *deadline <= now_ns => {}
+                    _ => break,
+                }
+                let Reverse((_, irq_id, source)) = scheduled.pop().unwrap();
+                (irq_id, source)
+            };
+            self.deliver(irq_id, source);
+            count += 1;
+        }
+        count
+    }
+
+    /// Drops every scheduled injection of `irq_id`.
+    pub fn cancel_scheduled(&self, irq_id: usize) {
+        self.scheduled
+            .lock()
+            .retain(|Reverse((_, id, _))| *id != irq_id);
+    }
+}
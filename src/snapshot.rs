@@ -0,0 +1,180 @@
+//! Save and restore of the vPLIC state for VM snapshots.
+//!
+//! The saved state is plain data: the source bitmaps as register-sized
+//! words, the priorities, and per context the threshold, the enable words
+//! and the in-flight claim. Save it with the device paused (see
+//! [`VPlicGlobal::pause`]) so no claim changes it meanwhile; restoring
+//! reprograms the host PLIC and notifies the guest of what is pending.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+use crate::delivery::GuestNotifier;
+use crate::{source_bit, source_word, VPlicGlobal, PLIC_NUM_BANKS, PLIC_NUM_SOURCES};
+
+/// Saved state of one context.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedContext {
+    /// Priority threshold.
+    pub threshold: u32,
+    /// Enable words.
+    pub enables: [u32; PLIC_NUM_BANKS],
+    /// IRQ claimed and not completed yet, 0 if none.
+    pub claimed: usize,
+}
+
+/// Saved state of a vPLIC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VPlicState {
+    /// Pending sources.
+    pub pending: [u32; PLIC_NUM_BANKS],
+    /// Active (claimed, not completed) sources.
+    pub active: [u32; PLIC_NUM_BANKS],
+    /// Host sources assigned to the VM.
+    pub assigned: [u32; PLIC_NUM_BANKS],
+    /// Priorities, indexed by source.
+    pub priorities: Vec<u32>,
+    /// Per-context state, indexed by context.
+    pub contexts: Vec<SavedContext>,
+}
+
+impl VPlicGlobal {
+    /// Saves the interrupt state.
+    pub fn save_state(&self) -> VPlicState {
+        let pending = self.pending_irqs.words();
+        let active = self.active_irqs.words();
+        let mut assigned = [0; PLIC_NUM_BANKS];
+        for host_irq in self.assigned_irqs.lock().into_iter() {
+            assigned[source_word(host_irq)] |= source_bit(host_irq);
+        }
+        let mut priorities = vec![0; PLIC_NUM_SOURCES];
+        self.store_priorities(&mut priorities);
+        let contexts = self
+            .contexts
+            .lock()
+            .iter()
+            .map(|context| {
+                let mut enables = [0; PLIC_NUM_BANKS];
+                for (bank, word) in enables.iter_mut().enumerate() {
+                    *word = context.enables.word(bank);
+                }
+                SavedContext {
+                    threshold: context.threshold,
+                    enables,
+                    claimed: context.last_claim,
+                }
+            })
+            .collect();
+        VPlicState {
+            pending,
+            active,
+            assigned,
+            priorities,
+            contexts,
+        }
+    }
+
+    /// Restores a state saved by [`VPlicGlobal::save_state`], reprogramming
+    /// the host PLIC.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if the state was saved from a
+    /// vPLIC with a different number of contexts, or records a claim of a
+    /// source that does not exist.
+    pub fn restore_state(&self, state: &VPlicState) -> AxResult {
+        self.forbid_in_hook("restore_state")?;
+        if state.contexts.len() != self.contexts_num
+            || state.priorities.len() > PLIC_NUM_SOURCES
+            || state.contexts.iter().any(|saved| saved.claimed >= PLIC_NUM_SOURCES)
+        {
+            return Err(AxError::InvalidInput);
+        }
+        self.reconfigure(|vplic| -> AxResult {
+            {
+                let mut assigned_irqs = vplic.assigned_irqs.lock();
+                for irq_id in 0..PLIC_NUM_SOURCES {
+                    let (word, bit) = (source_word(irq_id), source_bit(irq_id));
+                    assigned_irqs.set(irq_id, state.assigned[word] & bit != 0);
+                }
+            }
+            for bank in 0..PLIC_NUM_BANKS {
+                vplic.active_irqs.set_word(bank, state.active[bank]);
+                vplic.pending_irqs.set_word(bank, state.pending[bank]);
+            }
+            // Source 0 is reserved, whatever the saved state says.
+            vplic.active_irqs.set(0, false);
+            vplic.pending_irqs.set(0, false);
+            // Unassigned sources belong to other VMs: only their shadow
+            // priority is restored.
+            vplic.load_priorities(&state.priorities)?;
+            *vplic.claimants.lock() = vec![None; PLIC_NUM_SOURCES];
+            for (context_id, saved) in state.contexts.iter().enumerate() {
+                vplic.set_threshold(context_id, saved.threshold)?;
+                if saved.claimed != 0 {
+                    vplic.claimants.lock()[saved.claimed] = Some(context_id);
+                }
+                let mut contexts = vplic.contexts.lock();
+                for (bank, &word) in saved.enables.iter().enumerate() {
+                    contexts[context_id].enables.set_word(bank, word);
+                }
+                contexts[context_id].last_claim = saved.claimed;
+            }
+            vplic.sync_host_enables()
+        })?;
+        if !self.pending_irqs.is_empty() {
+            self.assert_vseip();
+        }
+        self.refresh_deliverability();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axerrno::AxError;
+
+    use crate::host::HostBackend;
+    use crate::mock::testing::*;
+    use crate::{SourceInfo, PLIC_NUM_SOURCES, PLIC_PRIORITY_OFFSET};
+
+    #[test]
+    fn restored_claim_can_be_completed() {
+        let vplic = mock_vplic(1);
+        route(&vplic, 0, 3, 1);
+        vplic.inject_irq(3).unwrap();
+        assert_eq!(claim(&vplic, 0), 3);
+        let state = vplic.save_state();
+
+        let restored = mock_vplic(1);
+        restored.restore_state(&state).unwrap();
+        assert_eq!(restored.last_claim(0), Some(3));
+        complete(&restored, 0, 3);
+        assert_eq!(restored.last_claim(0), None);
+    }
+
+    #[test]
+    fn claim_of_missing_source_is_rejected() {
+        let vplic = mock_vplic(1);
+        let mut state = vplic.save_state();
+        state.contexts[0].claimed = PLIC_NUM_SOURCES;
+        assert_eq!(vplic.restore_state(&state), Err(AxError::InvalidInput));
+    }
+
+    #[test]
+    fn restore_leaves_unassigned_host_priorities_alone() {
+        let vplic = mock_vplic(1);
+        vplic.assign_source(2, SourceInfo::default()).unwrap();
+        route(&vplic, 0, 2, 5);
+        route(&vplic, 0, 3, 6);
+        let state = vplic.save_state();
+
+        let restored = mock_vplic(1);
+        restored.host_write(PLIC_PRIORITY_OFFSET + 3 * 4, 1).unwrap();
+        restored.restore_state(&state).unwrap();
+        assert_eq!(restored.host_priority(2).unwrap(), 5);
+        assert_eq!(restored.host_priority(3).unwrap(), 1);
+        assert_eq!(restored.priority(3), 6);
+    }
+}
@@ -0,0 +1,153 @@
+//! Event counters.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::VPlicGlobal;
+
+/// Live event counters of a vPLIC.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    epoch: AtomicU64,
+    injections: AtomicU64,
+    claims: AtomicU64,
+    empty_claims: AtomicU64,
+    completions: AtomicU64,
+    batched_claims: AtomicU64,
+    spurious_wakes: AtomicU64,
+    illegal_transitions: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A point-in-time copy of the event counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VPlicStats {
+    /// Number of resets since creation; counters only compare within an epoch.
+    pub epoch: u64,
+    /// Injections, including those collapsed into an already pending IRQ.
+    pub injections: u64,
+    /// Claims that returned an IRQ.
+    pub claims: u64,
+    /// Claims that returned 0.
+    pub empty_claims: u64,
+    /// Completions written by the guest.
+    pub completions: u64,
+    /// Claims that left more eligible IRQs and kept VSEIP asserted.
+    pub batched_claims: u64,
+    /// Claim timeouts that withdrew VSEIP from a vCPU with nothing left to
+    /// claim.
+    pub spurious_wakes: u64,
+    /// Rejected source state transitions (e.g. completions of IRQs that
+    /// were never claimed).
+    pub illegal_transitions: u64,
+    /// Pending interrupts dropped because the guest could not be notified.
+    pub dropped: u64,
+}
+
+impl Stats {
+    pub(crate) fn count_injection(&self) {
+        self.injections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_claim(&self, irq_id: usize) {
+        if irq_id == 0 {
+            self.empty_claims.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.claims.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn count_completion(&self) {
+        self.completions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_batched_claim(&self) {
+        self.batched_claims.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_spurious_wake(&self) {
+        self.spurious_wakes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_illegal_transition(&self) {
+        self.illegal_transitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_dropped(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+impl VPlicGlobal {
+    /// Returns a copy of the event counters.
+    pub fn stats(&self) -> VPlicStats {
+        let stats = &self.stats;
+        VPlicStats {
+            epoch: stats.epoch.load(Ordering::Acquire),
+            injections: stats.injections.load(Ordering::Relaxed),
+            claims: stats.claims.load(Ordering::Relaxed),
+            empty_claims: stats.empty_claims.load(Ordering::Relaxed),
+            completions: stats.completions.load(Ordering::Relaxed),
+            batched_claims: stats.batched_claims.load(Ordering::Relaxed),
+            spurious_wakes: stats.spurious_wakes.load(Ordering::Relaxed),
+            illegal_transitions: stats.illegal_transitions.load(Ordering::Relaxed),
+            dropped: stats.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the event counters and starts a new epoch.
+    ///
+    /// Returns the new epoch, so monitoring tools can compute rates over the
+    /// interval since the reset.
+    pub fn reset_stats(&self) -> u64 {
+        let stats = &self.stats;
+        stats.injections.store(0, Ordering::Relaxed);
+        stats.claims.store(0, Ordering::Relaxed);
+        stats.empty_claims.store(0, Ordering::Relaxed);
+        stats.completions.store(0, Ordering::Relaxed);
+        stats.batched_claims.store(0, Ordering::Relaxed);
+        stats.spurious_wakes.store(0, Ordering::Relaxed);
+        stats.illegal_transitions.store(0, Ordering::Relaxed);
+        stats.dropped.store(0, Ordering::Relaxed);
+        self.overflows.lock().iter_mut().for_each(|count| *count = 0);
+        stats.epoch.fetch_add(1, Ordering::Release) + 1
+    }
+
+    /// Returns how many injections of `irq_id` were collapsed into an
+    /// already pending interrupt.
+    pub fn overflow_count(&self, irq_id: usize) -> u32 {
+        self.overflows.lock().get(irq_id).copied().unwrap_or(0)
+    }
+
+    /// Returns the sources with collapsed injections, as (IRQ id, count).
+    pub fn overflow_counters(&self) -> Vec<(usize, u32)> {
+        self.overflows
+            .lock()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count != 0)
+            .map(|(irq_id, &count)| (irq_id, count))
+            .collect()
+    }
+
+    /// Writes the overflow counters as CSV, one header line then one line
+    /// per source with collapsed injections.
+    pub fn write_overflow_counters<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "irq_id,name,overflows")?;
+        for (irq_id, count) in self.overflow_counters() {
+            writeln!(out, "{},{},{}", irq_id, self.source_name(irq_id).unwrap_or(""), count)?;
+        }
+        Ok(())
+    }
+
+    /// Accounts an injection of `irq_id`; `was_pending` tells whether it was
+    /// collapsed into an already pending interrupt.
+    pub(crate) fn count_injection(&self, irq_id: usize, was_pending: bool) {
+        self.stats.count_injection();
+        if was_pending {
+            let mut overflows = self.overflows.lock();
+            overflows[irq_id] = overflows[irq_id].saturating_add(1);
+        }
+    }
+}
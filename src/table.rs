@@ -0,0 +1,142 @@
+//! Interrupt source description table.
+//!
+//! Sources assigned through [`VPlicGlobal::assign_source`] are described
+//! once (name, mode, trigger, target context), and the table combines this
+//! with the live ownership and numbering state. It is the one place the VMM
+//! inventory and guest device-tree generation read the interrupt topology
+//! from.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+
+use axerrno::{AxError, AxResult};
+
+use crate::{SourceOwner, VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// How a source is backed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceMode {
+    /// A host source passed through to the guest.
+    #[default]
+    Passthrough,
+    /// A source raised by an emulated device.
+    Emulated,
+}
+
+/// Trigger type of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Trigger {
+    /// Level-triggered.
+    #[default]
+    Level,
+    /// Edge-triggered.
+    Edge,
+}
+
+/// Static description of an assigned source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceInfo {
+    /// Human-readable name, e.g. the device node name.
+    pub name: &'static str,
+    /// How the source is backed.
+    pub mode: SourceMode,
+    /// Trigger type.
+    pub trigger: Trigger,
+    /// Guest context the source is meant for, if fixed.
+    pub target_context: Option<usize>,
+}
+
+/// One row of the source table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceDesc {
+    /// Host source number.
+    pub host_irq: usize,
+    /// Source number seen by the guest, if mapped.
+    pub guest_irq: Option<usize>,
+    /// Current owner.
+    pub owner: SourceOwner,
+    /// Static description.
+    pub info: SourceInfo,
+}
+
+impl VPlicGlobal {
+    /// Assigns host source `host_irq` to the VM, described by `info`.
+    pub fn assign_source(&self, host_irq: usize, info: SourceInfo) -> AxResult {
+        if host_irq == 0 || host_irq >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        if info.target_context.is_some_and(|context_id| context_id >= self.contexts_num) {
+            return Err(AxError::InvalidInput);
+        }
+        self.assigned_irqs.lock().set(host_irq, true);
+        self.source_infos.lock().insert(host_irq, info);
+        match self.guest_irq(host_irq) {
+            Some(irq_id) => self.set_source_trigger(irq_id, info.trigger),
+            None => Ok(()),
+        }
+    }
+
+    /// Withdraws host source `host_irq` from the VM.
+    pub fn unassign_source(&self, host_irq: usize) -> AxResult {
+        if host_irq >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        self.assigned_irqs.lock().set(host_irq, false);
+        self.source_infos.lock().remove(&host_irq);
+        Ok(())
+    }
+
+    /// Returns `true` if host source `host_irq` is assigned to the VM.
+    pub(crate) fn host_assigned(&self, host_irq: usize) -> bool {
+        host_irq != 0 && host_irq < PLIC_NUM_SOURCES && self.assigned_irqs.lock().get(host_irq)
+    }
+
+    /// Returns the description of every assigned source, by host number.
+    pub fn source_table(&self) -> Vec<SourceDesc> {
+        let assigned = *self.assigned_irqs.lock();
+        let infos = self.source_infos.lock().clone();
+        assigned
+            .into_iter()
+            .filter(|&host_irq| host_irq != 0)
+            .map(|host_irq| {
+                let guest_irq = self.guest_irq(host_irq);
+                SourceDesc {
+                    host_irq,
+                    guest_irq,
+                    owner: guest_irq.map_or(SourceOwner::Guest, |irq_id| self.source_owner(irq_id)),
+                    info: infos.get(&host_irq).copied().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the source table as CSV, one header line then one line per
+    /// source.
+    pub fn write_source_table<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "host_irq,guest_irq,name,owner,mode,trigger,target_context")?;
+        for desc in self.source_table() {
+            write!(out, "{},", desc.host_irq)?;
+            if let Some(guest_irq) = desc.guest_irq {
+                write!(out, "{}", guest_irq)?;
+            }
+            let name = match desc.guest_irq.and_then(|irq_id| self.source_name(irq_id)) {
+                Some(name) => name,
+                None => desc.info.name,
+            };
+            write!(
+                out,
+                ",{},{:?},{:?},{:?},",
+                name, desc.owner, desc.info.mode, desc.info.trigger
+            )?;
+            if let Some(context_id) = desc.info.target_context {
+                write!(out, "{}", context_id)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Static descriptions of the assigned sources, by host number.
+pub(crate) type SourceInfos = BTreeMap<usize, SourceInfo>;
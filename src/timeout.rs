@@ -0,0 +1,94 @@
+//! Claim timeouts against spurious wakeups.
+//!
+//! VSEIP may stay asserted for a vCPU after the interrupt that caused it was
+//! claimed elsewhere (e.g. by another context), leaving an idle vCPU waking
+//! up forever. With a claim timeout, every context gets a deadline when VSEIP
+//! is asserted; the hypervisor calls [`VPlicGlobal::check_claim_timeout`]
+//! periodically on the vCPU's hart, and VSEIP is withdrawn if no context of
+//! the vCPU claimed in time and none has a deliverable interrupt anymore.
+
+use core::sync::atomic::Ordering;
+
+use crate::delivery::GuestNotifier;
+use crate::utils::now_ns;
+use crate::VPlicGlobal;
+
+impl VPlicGlobal {
+    /// Sets the claim timeout in nanoseconds, or disables it with `None`.
+    pub fn set_claim_timeout(&self, timeout_ns: Option<u64>) {
+        self.claim_timeout_ns
+            .store(timeout_ns.unwrap_or(0), Ordering::Relaxed);
+        if timeout_ns.is_none() {
+            self.contexts
+                .lock()
+                .iter_mut()
+                .for_each(|context| context.claim_deadline = None);
+        }
+    }
+
+    /// Returns the claim timeout in nanoseconds, if enabled.
+    pub fn claim_timeout(&self) -> Option<u64> {
+        match self.claim_timeout_ns.load(Ordering::Relaxed) {
+            0 => None,
+            timeout_ns => Some(timeout_ns),
+        }
+    }
+
+    /// Checks the claim deadlines of the contexts of `vcpu_id` at `now_ns`,
+    /// withdrawing VSEIP if one expired and the vCPU has no deliverable
+    /// interrupt left. Must run on the hart of `vcpu_id`.
+    ///
+    /// Returns `true` if VSEIP was withdrawn.
+    pub fn check_claim_timeout(&self, vcpu_id: usize, now_ns: u64) -> bool {
+        let timeout_ns = match self.claim_timeout() {
+            Some(timeout_ns) => timeout_ns,
+            None => return false,
+        };
+        let contexts = self.contexts_of_vcpu(vcpu_id);
+        let expired = {
+            let states = self.contexts.lock();
+            contexts.iter().any(|&context_id| {
+                states[context_id]
+                    .claim_deadline
+                    .is_some_and(|deadline| deadline <= now_ns)
+            })
+        };
+        if !expired {
+            return false;
+        }
+        let spurious = contexts
+            .iter()
+            .all(|&context_id| !self.should_wake(context_id));
+        let mut states = self.contexts.lock();
+        for &context_id in &contexts {
+            states[context_id].claim_deadline = if spurious {
+                None
+            } else {
+                Some(now_ns.saturating_add(timeout_ns))
+            };
+        }
+        drop(states);
+        if spurious {
+            self.stats.count_spurious_wake();
+            self.deassert_vseip();
+        }
+        spurious
+    }
+
+    /// Arms the claim deadline of every context not waiting for a claim yet.
+    pub(crate) fn arm_claim_timeouts(&self) {
+        if let Some(timeout_ns) = self.claim_timeout() {
+            let deadline = now_ns().saturating_add(timeout_ns);
+            for context in self.contexts.lock().iter_mut() {
+                context.claim_deadline.get_or_insert(deadline);
+            }
+        }
+    }
+
+    /// Disarms the claim deadline of `context_id` once it claimed.
+    pub(crate) fn disarm_claim_timeout(&self, context_id: usize) {
+        if let Some(context) = self.contexts.lock().get_mut(context_id) {
+            context.claim_deadline = None;
+        }
+    }
+}
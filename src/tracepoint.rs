@@ -0,0 +1,45 @@
+//! Trace points with stable IDs.
+//!
+//! Key events are emitted with a numeric ID that never changes across
+//! releases, so external analysis tools and long-term dashboards keep
+//! decoding them however the code moves. Events go to the `log` crate at
+//! trace level (as `vplic-tp <id> ...`) and to
+//! [`VPlicHooks::trace`](crate::VPlicHooks::trace). New events get new IDs;
+//! IDs are never reused.
+
+use log::trace;
+
+use crate::VPlicGlobal;
+
+/// A trace point. The discriminant is its stable ID.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TracePoint {
+    /// A source was injected; the argument is 1 if it was already pending.
+    Inject = 1,
+    /// A context claimed a source; the argument is the context.
+    Claim = 2,
+    /// The guest completed a source; the argument is unused.
+    Complete = 3,
+    /// A source was masked (argument 1) or unmasked (argument 0) on the host
+    /// while its vCPU is descheduled.
+    Mask = 4,
+    /// A source is injected faster than the guest consumes it (argument 1),
+    /// or recovered (argument 0); see back-pressure.
+    StormDetected = 5,
+}
+
+impl TracePoint {
+    /// Returns the stable ID of the trace point.
+    pub const fn id(self) -> u16 {
+        self as u16
+    }
+}
+
+impl VPlicGlobal {
+    /// Emits trace point `point` for `irq_id` with argument `arg`.
+    pub(crate) fn trace_point(&self, point: TracePoint, irq_id: usize, arg: usize) {
+        trace!("vplic-tp {} {:?} irq {} arg {}", point.id(), point, irq_id, arg);
+        self.call_hooks(|hooks| hooks.trace(point, irq_id, arg));
+    }
+}
@@ -2,8 +2,10 @@ use core::result::Result::Ok;
 use axaddrspace::{device::AccessWidth, HostPhysAddr};
 use axerrno::AxResult;
 
+use crate::hal::hal;
+
 pub(crate) fn perform_mmio_read(addr: HostPhysAddr, width: AccessWidth) -> AxResult<usize> {
-    let addr = axvisor_api::memory::phys_to_virt(addr).as_ptr();
+    let addr = hal().phys_to_virt(addr).as_ptr();
 
     match width {
         AccessWidth::Byte => Ok(unsafe { addr.read_volatile() as _ }),
@@ -13,12 +15,26 @@ pub(crate) fn perform_mmio_read(addr: HostPhysAddr, width: AccessWidth) -> AxRes
     }
 }
 
+/// Validates a guest access of `width` carrying `val` to a 32-bit PLIC
+/// register and normalizes it to the 32-bit value to forward to the host.
+///
+/// Real PLICs only support word accesses, so whatever the guest used, the
+/// host sees a single 32-bit access. Narrower accesses cannot carry a whole
+/// register and wider ones must not set bits beyond it.
+pub(crate) fn normalize_word_access(width: AccessWidth, val: usize) -> AxResult<u32> {
+    match width {
+        AccessWidth::Dword => Ok(val as u32),
+        AccessWidth::Qword if val >> 32 == 0 => Ok(val as u32),
+        _ => Err(axerrno::AxError::InvalidInput),
+    }
+}
+
 pub(crate) fn perform_mmio_write(
     addr: HostPhysAddr,
     width: AccessWidth,
     val: usize,
 ) -> AxResult<()> {
-    let addr = axvisor_api::memory::phys_to_virt(addr).as_mut_ptr();
+    let addr = hal().phys_to_virt(addr).as_mut_ptr();
 
     match width {
         AccessWidth::Byte => unsafe {
@@ -37,3 +53,24 @@ pub(crate) fn perform_mmio_write(
 
     Ok(())
 }
+
+/// Small xorshift64 generator for reproducible pseudo-random decisions.
+pub(crate) struct XorShift64(u64);
+
+impl XorShift64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Returns the current host time in nanoseconds.
+pub(crate) fn now_ns() -> u64 {
+    hal().now_ns()
+}
@@ -0,0 +1,81 @@
+//! Detection of write-1-to-clear attempts on the pending registers.
+//!
+//! Guests ported from other interrupt controllers sometimes try to clear
+//! pending interrupts by writing ones to the PLIC pending registers. A write
+//! hitting bits that are already pending is counted as such an attempt,
+//! reported by [`VPlicGlobal::audit`], and handled per [`PendingW1c`].
+//!
+//! The pending registers are read-only per the PLIC specification: guest
+//! writes are ignored, and would otherwise let a guest inject any source
+//! into itself. Device models inject through [`VPlicGlobal::inject_irq`].
+//! Legacy guests relying on self-injection can be allowed it with
+//! [`VPlicGlobal::set_guest_self_injection`].
+
+use core::sync::atomic::Ordering;
+
+use log::warn;
+
+use crate::VPlicGlobal;
+
+/// Handling of guest writes to the pending registers that look like
+/// write-1-to-clear attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PendingW1c {
+    /// Handle the write like any other pending write: ignored, or a
+    /// self-injection if allowed.
+    #[default]
+    Inject,
+    /// Ignore the write.
+    Ignore,
+    /// Emulate write-1-to-clear semantics, dropping the written pending
+    /// interrupts. Like self-injection, only applies if guest writes to the
+    /// pending registers are allowed at all.
+    Clear,
+}
+
+impl VPlicGlobal {
+    /// Sets the handling of write-1-to-clear attempts.
+    pub fn set_pending_w1c(&self, policy: PendingW1c) {
+        *self.pending_w1c.lock() = policy;
+    }
+
+    /// Returns the handling of write-1-to-clear attempts.
+    pub fn pending_w1c(&self) -> PendingW1c {
+        *self.pending_w1c.lock()
+    }
+
+    /// Allows (`true`) or forbids (`false`, the default) guest writes to the
+    /// pending registers to inject interrupts.
+    pub fn set_guest_self_injection(&self, allow: bool) {
+        self.guest_self_injection.store(allow, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if guest writes to the pending registers inject
+    /// interrupts.
+    pub fn guest_self_injection(&self) -> bool {
+        self.guest_self_injection.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of write-1-to-clear attempts seen.
+    pub fn w1c_attempts(&self) -> u64 {
+        self.w1c_attempts.load(Ordering::Relaxed)
+    }
+
+    /// Checks a guest write of `val` to pending word `word_index`, whose
+    /// current value is `pending`.
+    ///
+    /// Returns the policy to apply if it looks like a write-1-to-clear.
+    pub(crate) fn detect_w1c(&self, word_index: usize, pending: u32, val: u32) -> Option<PendingW1c> {
+        if val & pending == 0 {
+            return None;
+        }
+        if self.w1c_attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+            warn!(
+                "vPlicGlobal: guest wrote {:#x} to pending word {} holding {:#x}, \
+                 pending registers are not write-1-to-clear",
+                val, word_index, pending
+            );
+        }
+        Some(self.pending_w1c())
+    }
+}
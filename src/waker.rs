@@ -0,0 +1,84 @@
+//! Asynchronous completion notifications.
+//!
+//! Async device models running on the hypervisor's executor can await the
+//! guest's completion of an interrupt instead of polling the active bitmap.
+//! Only `core::task` is used, so any executor works.
+
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use axerrno::{AxError, AxResult};
+
+use crate::{VPlicGlobal, PLIC_NUM_SOURCES};
+
+/// Wakers waiting for completions, with the source each one waits for.
+pub(crate) type CompleteWakers = Vec<(usize, Waker)>;
+
+/// Future resolving when the guest completes a source.
+///
+/// Created by [`VPlicGlobal::wait_complete`]; resolves on the first
+/// completion of the source after its creation.
+pub struct CompleteFuture<'a> {
+    vplic: &'a VPlicGlobal,
+    irq_id: usize,
+    seq: u64,
+}
+
+impl Future for CompleteFuture<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut wakers = self.vplic.complete_wakers.lock();
+        if self.vplic.completion_seq(self.irq_id) != self.seq {
+            return Poll::Ready(());
+        }
+        let registered = wakers
+            .iter()
+            .any(|(irq_id, waker)| *irq_id == self.irq_id && waker.will_wake(cx.waker()));
+        if !registered {
+            wakers.push((self.irq_id, cx.waker().clone()));
+        }
+        Poll::Pending
+    }
+}
+
+impl VPlicGlobal {
+    /// Returns a future resolving on the next guest completion of `irq_id`.
+    ///
+    /// Fails with [`AxError::InvalidInput`] if `irq_id` is not a valid source.
+    pub fn wait_complete(&self, irq_id: usize) -> AxResult<CompleteFuture<'_>> {
+        if irq_id == 0 || irq_id >= PLIC_NUM_SOURCES {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(CompleteFuture {
+            vplic: self,
+            irq_id,
+            seq: self.completion_seq(irq_id),
+        })
+    }
+
+    /// Returns the number of completions of `irq_id` so far.
+    fn completion_seq(&self, irq_id: usize) -> u64 {
+        self.completion_seqs.lock()[irq_id]
+    }
+
+    /// Accounts a completion of `irq_id` and wakes the tasks waiting for it.
+    pub(crate) fn notify_complete(&self, irq_id: usize) {
+        let mut woken = Vec::new();
+        {
+            let mut wakers = self.complete_wakers.lock();
+            self.completion_seqs.lock()[irq_id] += 1;
+            wakers.retain(|(id, waker)| {
+                if *id == irq_id {
+                    woken.push(waker.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        woken.into_iter().for_each(Waker::wake);
+    }
+}